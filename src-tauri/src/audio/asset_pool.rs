@@ -3,16 +3,24 @@ use std::{
     sync::{Arc, LazyLock, RwLock},
 };
 
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, Guard};
+use log::info;
 use nanoid::nanoid;
 
 use crate::{
-    audio::decoder::DecodedAudioData,
-    core::types::{EngineSampleFormat, Id},
+    audio::{
+        decoder::{decode_audio_file, DecodedAudioData},
+        engine::AUDIO_ENGINE,
+    },
+    core::{
+        notify::log_and_notify_error,
+        types::{EngineSampleFormat, Id},
+    },
 };
 
 pub struct AudioPcmData {
     data: Vec<EngineSampleFormat>,
+    sample_rate: usize,
 }
 
 impl AudioPcmData {
@@ -29,10 +37,17 @@ pub struct AudioMetaData {
 }
 
 pub struct AudioAsset {
-    pcmData: Arc<AudioPcmData>,
+    pcmData: ArcSwap<AudioPcmData>,
     metaData: ArcSwap<AudioMetaData>,
 }
 
+impl AudioAsset {
+    /// Lock-free, safe to call from the render path.
+    pub fn pcm_data(&self) -> Guard<Arc<AudioPcmData>> {
+        self.pcmData.load()
+    }
+}
+
 struct AudioStoreInner {
     store: HashMap<Id, Arc<AudioAsset>>,
     path_to_id: HashMap<String, Id>,
@@ -52,8 +67,8 @@ impl AudioStore {
         }
     }
 
-    pub fn get_pcm_by_id(&self, id: &str) -> Option<Arc<AudioPcmData>> {
-        Some(self.inner.read().unwrap().store.get(id)?.pcmData.clone())
+    pub fn get_by_id(&self, id: &str) -> Option<Arc<AudioAsset>> {
+        self.inner.read().unwrap().store.get(id).cloned()
     }
 
     pub fn get_id_by_path(&self, path: &str) -> Option<Id> {
@@ -65,7 +80,31 @@ impl AudioStore {
     }
 
     pub fn get_num_samples_by_id(&self, id: &str) -> Option<usize> {
-        Some(self.inner.read().unwrap().store.get(id)?.pcmData.data.len())
+        Some(
+            self.inner
+                .read()
+                .unwrap()
+                .store
+                .get(id)?
+                .pcmData
+                .load()
+                .data
+                .len(),
+        )
+    }
+
+    pub fn get_file_path_by_id(&self, id: &str) -> Option<String> {
+        Some(
+            self.inner
+                .read()
+                .unwrap()
+                .store
+                .get(id)?
+                .metaData
+                .load()
+                .file_path
+                .clone(),
+        )
     }
 
     pub fn get_display_name_by_id(&self, id: &str) -> Option<String> {
@@ -87,6 +126,7 @@ impl AudioStore {
             data,
             original_num_channels,
             original_sample_rate,
+            sample_rate,
             file_path,
             file_name,
         } = decoded_audio_data;
@@ -97,7 +137,7 @@ impl AudioStore {
         }
 
         let new_audio = Arc::new(AudioAsset {
-            pcmData: Arc::new(AudioPcmData { data }),
+            pcmData: ArcSwap::new(Arc::new(AudioPcmData { data, sample_rate })),
             metaData: ArcSwap::new(Arc::new(AudioMetaData {
                 file_path: file_path.clone(),
                 display_name: file_name,
@@ -112,6 +152,29 @@ impl AudioStore {
         id
     }
 
+    /// Decodes every asset again from its source file when it was resampled for another engine rate.
+    /// Blocking, the scheduler has to be rebuilt afterwards since clip lengths in samples change.
+    pub fn resample_to_engine_rate(&self) {
+        let engine_sample_rate = AUDIO_ENGINE.sample_rate();
+        let assets: Vec<Arc<AudioAsset>> =
+            self.inner.read().unwrap().store.values().cloned().collect();
+
+        for asset in assets {
+            if asset.pcmData.load().sample_rate == engine_sample_rate {
+                continue;
+            }
+            let file_path = asset.metaData.load().file_path.clone();
+            info!("Resampling {} to {} Hz", file_path, engine_sample_rate);
+            match decode_audio_file(file_path) {
+                Ok(decoded_audio_data) => asset.pcmData.store(Arc::new(AudioPcmData {
+                    data: decoded_audio_data.data,
+                    sample_rate: decoded_audio_data.sample_rate,
+                })),
+                Err(e) => log_and_notify_error(format!("Error trying to resample audio file: {e}")),
+            }
+        }
+    }
+
     pub fn remove(&self, id: &str) {
         let mut inner = self.inner.write().unwrap();
         if let Some(asset) = inner.store.remove(id) {
@@ -0,0 +1,157 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, LazyLock,
+};
+
+use anyhow::{bail, Result};
+use log::info;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{
+    engine::AUDIO_ENGINE,
+    project_state::PROJECT_STATE,
+    renderer::Renderer,
+    snapshot::{data_nodes::DataNodes, project_snapshot::load_project_snapshot},
+    wav_writer::{WavBitDepth, WavFileWriter},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BounceOptions {
+    pub file_path: String,
+    pub bit_depth: WavBitDepth,
+    pub start_ppq: Option<usize>,
+    pub end_ppq: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BounceProgress {
+    pub rendered_samples: usize,
+    pub total_samples: usize,
+}
+
+/// Renders the arrangement straight from the project snapshot into a WAV file,
+/// as fast as the CPU allows and without touching the output stream.
+/// Reverb and delay tails ring out past the end of the range.
+pub struct Bouncer {
+    pub is_running: AtomicBool,
+    pub is_canceled: AtomicBool,
+}
+
+impl Bouncer {
+    pub fn new() -> Self {
+        Self {
+            is_running: AtomicBool::new(false),
+            is_canceled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.is_canceled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn bounce(
+        &self,
+        options: BounceOptions,
+        on_progress: impl FnMut(BounceProgress),
+    ) -> Result<()> {
+        if self.is_running.swap(true, Ordering::SeqCst) {
+            bail!("A bounce is already in progress");
+        }
+        self.is_canceled.store(false, Ordering::SeqCst);
+        let result = self.render_to_file(&options, on_progress);
+        self.is_running.store(false, Ordering::SeqCst);
+
+        if result.is_err() || self.is_canceled.load(Ordering::SeqCst) {
+            let _ = std::fs::remove_file(&options.file_path);
+        }
+        result
+    }
+
+    fn render_to_file(
+        &self,
+        options: &BounceOptions,
+        mut on_progress: impl FnMut(BounceProgress),
+    ) -> Result<()> {
+        // Effects carry state from block to block, playback keeps its own instances untouched
+        let Some(data_nodes) =
+            DataNodes::build_detached(|| self.is_canceled.load(Ordering::SeqCst))
+        else {
+            info!("Bounce canceled");
+            return Ok(());
+        };
+        data_nodes.reset_effects();
+        let snapshot =
+            Arc::new(load_project_snapshot().with_data_nodes(Arc::new(data_nodes), nanoid!()));
+        let start_sample = PROJECT_STATE.ppq_to_samples(options.start_ppq.unwrap_or(0));
+        let end_sample = match options.end_ppq {
+            Some(end_ppq) => PROJECT_STATE.ppq_to_samples(end_ppq),
+            None => snapshot.get_scheduler().end_sample(),
+        };
+        if end_sample <= start_sample {
+            bail!("Nothing to bounce in the selected range");
+        }
+
+        let num_channels = AUDIO_ENGINE.num_channels();
+        let end_ppq =
+            snapshot
+                .tempo_map
+                .samples_to_ppq(end_sample, AUDIO_ENGINE.sample_rate(), num_channels);
+        let tail_samples = snapshot.tail_frames(
+            AUDIO_ENGINE.sample_rate(),
+            snapshot.tempo_map.bpm_at(end_ppq),
+        ) * num_channels;
+
+        info!(
+            "Bouncing samples {}..{} with a tail of {} to {}",
+            start_sample, end_sample, tail_samples, options.file_path
+        );
+
+        let mut writer = WavFileWriter::create(
+            &options.file_path,
+            options.bit_depth,
+            num_channels,
+            AUDIO_ENGINE.sample_rate(),
+        )?;
+        let mut renderer =
+            Renderer::new(AUDIO_ENGINE.buffer_size() * num_channels).with_private_buffers();
+
+        let total_samples = end_sample - start_sample + tail_samples;
+        let mut position_samples = start_sample;
+        let mut last_reported_percent = None;
+        while position_samples < end_sample + tail_samples {
+            if self.is_canceled.load(Ordering::SeqCst) {
+                info!("Bounce canceled");
+                return Ok(());
+            }
+
+            // The last block in range already holds the start of the tail
+            let block = if position_samples < end_sample {
+                renderer.render_until(&snapshot, position_samples, end_sample, None)
+            } else {
+                renderer.render_tail(&snapshot, end_sample, None)
+            };
+            let block_len = block
+                .len()
+                .min(end_sample + tail_samples - position_samples);
+            writer.write_samples(&block[..block_len])?;
+            position_samples += block_len;
+
+            let rendered_samples = position_samples - start_sample;
+            let percent = rendered_samples * 100 / total_samples;
+            if last_reported_percent != Some(percent) {
+                last_reported_percent = Some(percent);
+                on_progress(BounceProgress {
+                    rendered_samples,
+                    total_samples,
+                });
+            }
+        }
+
+        writer.finalize()
+    }
+}
+
+pub static BOUNCER: LazyLock<Bouncer> = LazyLock::new(|| Bouncer::new());
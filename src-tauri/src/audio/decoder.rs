@@ -97,6 +97,7 @@ pub struct DecodedAudioData {
     pub data: Vec<f32>,
     pub original_num_channels: usize,
     pub original_sample_rate: usize,
+    pub sample_rate: usize,
     pub file_path: String,
     pub file_name: String,
 }
@@ -296,6 +297,7 @@ pub fn decode_audio_file(file_path: String) -> Result<DecodedAudioData> {
         .context("Unsupported codec")?;
 
     let engine_channels = AUDIO_ENGINE.num_channels();
+    let engine_sample_rate = AUDIO_ENGINE.sample_rate();
 
     let mut resampler = create_preview_resampler(track_sample_rate as usize)?;
     let channel_map = build_channel_index_map(track_channels);
@@ -403,7 +405,115 @@ pub fn decode_audio_file(file_path: String) -> Result<DecodedAudioData> {
         data: decoded_data,
         original_num_channels: num_track_channels,
         original_sample_rate: track_sample_rate as usize,
+        sample_rate: engine_sample_rate,
         file_path,
         file_name,
     })
 }
+
+/// Audio with every channel of the file kept apart, e.g. the four paths of a true-stereo
+/// impulse response.
+pub struct DecodedChannels {
+    /// One buffer per channel of the file.
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: usize,
+}
+
+/// Decodes a whole file without downmixing and resamples it to the engine rate with the
+/// offline resampler. Meant for short files such as impulse responses.
+pub fn decode_audio_file_channels(file_path: &str) -> Result<DecodedChannels> {
+    let mut format_reader = get_format_reader(file_path)?;
+
+    let track = format_reader
+        .default_track()
+        .context("no default track found")?;
+
+    let track_id = track.id;
+    let track_sample_rate = track
+        .codec_params
+        .sample_rate
+        .context("sample rate missing")? as usize;
+    let num_track_channels = track
+        .codec_params
+        .channels
+        .context("channels missing")?
+        .count();
+
+    let dec_opts: DecoderOptions = Default::default();
+    let mut decoder = get_codecs()
+        .make(&track.codec_params, &dec_opts)
+        .context("Unsupported codec")?;
+
+    let mut channels = vec![Vec::new(); num_track_channels];
+    let mut temp_buffer: Option<SampleBuffer<f32>> = None;
+
+    while let Ok(packet) = format_reader.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let Ok(decoded) = decoder.decode(&packet) else {
+            continue;
+        };
+        let num_frames = decoded.frames();
+        if num_frames == 0 {
+            continue;
+        }
+
+        let temp_buffer = match temp_buffer.as_mut() {
+            Some(temp_buffer) if temp_buffer.capacity() >= num_frames => temp_buffer,
+            _ => temp_buffer.insert(SampleBuffer::<f32>::new(num_frames as u64, *decoded.spec())),
+        };
+        temp_buffer.copy_interleaved_ref(decoded);
+        for frame in temp_buffer.samples().chunks_exact(num_track_channels) {
+            for (channel, sample) in channels.iter_mut().zip(frame.iter()) {
+                channel.push(*sample);
+            }
+        }
+    }
+
+    let engine_sample_rate = AUDIO_ENGINE.sample_rate();
+    if track_sample_rate != engine_sample_rate {
+        channels = resample_channels(&channels, track_sample_rate)?;
+    }
+
+    Ok(DecodedChannels {
+        channels,
+        sample_rate: engine_sample_rate,
+    })
+}
+
+fn resample_channels(channels: &[Vec<f32>], original_sample_rate: usize) -> Result<Vec<Vec<f32>>> {
+    let num_frames = channels.first().map_or(0, |channel| channel.len());
+    let expected_frames = (num_frames as f64 * AUDIO_ENGINE.sample_rate() as f64
+        / original_sample_rate as f64)
+        .round() as usize;
+
+    let mut resampler = create_offline_resampler(original_sample_rate, channels.len())?;
+    let output_delay = resampler.output_delay();
+    let mut output_buffer = resampler.output_buffer_allocate(true);
+    let mut resampled = vec![Vec::with_capacity(expected_frames + output_delay); channels.len()];
+
+    let mut position = 0;
+    while resampled[0].len() < expected_frames + output_delay {
+        let end = (position + resampler.input_frames_next()).min(num_frames);
+        let input: Vec<&[f32]> = channels
+            .iter()
+            .map(|channel| &channel[position..end])
+            .collect();
+        // Past the end the resampler is fed silence to flush its filter
+        let (frames_read, frames_written) = resampler
+            .process_partial_into_buffer(Some(&input), &mut output_buffer, None)
+            .map_err(|e| anyhow!(e))?;
+        position += frames_read.min(end - position);
+        for (channel, output) in resampled.iter_mut().zip(output_buffer.iter()) {
+            channel.extend_from_slice(&output[..frames_written]);
+        }
+    }
+
+    for channel in resampled.iter_mut() {
+        channel.drain(..output_delay);
+        channel.truncate(expected_frames);
+    }
+    Ok(resampled)
+}
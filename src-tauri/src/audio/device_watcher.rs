@@ -0,0 +1,181 @@
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cpal::{traits::HostTrait, DeviceId};
+use log::{info, warn};
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::{
+    app_handle,
+    audio::{
+        devices::{restart_input_after_output_change, restart_output},
+        engine::{open_host, OutputDeviceInfo, AUDIO_ENGINE},
+        recorder::RECORDER,
+    },
+    core::{
+        constants::{AUDIO_DEVICE_LOST_EVENT, AUDIO_DEVICE_RESTORED_EVENT, NULL_HOST_ID},
+        notify::log_and_notify_error,
+        settings::{AudioSettings, SETTINGS},
+    },
+};
+
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioDeviceChange {
+    pub previous_device_name: String,
+    pub output: OutputDeviceInfo,
+}
+
+/// Keeps audio running when the output device disappears.
+/// The stream error callback only raises a flag; a watcher thread then falls back to the
+/// host's default device or the null output and switches back once the saved device returns.
+/// The transport keeps playing from the same position and the project is left untouched.
+pub struct DeviceWatcher {
+    is_device_lost: AtomicBool,
+    // Set while running on a fallback device
+    is_fallback: AtomicBool,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DeviceWatcher {
+    pub fn new() -> Self {
+        Self {
+            is_device_lost: AtomicBool::new(false),
+            is_fallback: AtomicBool::new(false),
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Lock-free, called from the stream error callback.
+    pub fn report_device_lost(&self) {
+        self.is_device_lost.store(true, Ordering::SeqCst);
+    }
+
+    /// The user picked a device, so there is nothing to switch back to.
+    pub fn clear_fallback(&self) {
+        self.is_fallback.store(false, Ordering::SeqCst);
+    }
+
+    /// Spawns the watcher thread. It lives as long as the app.
+    pub fn start(&self) {
+        let mut handle = self.handle.lock().unwrap();
+        if handle.is_some() {
+            return;
+        }
+        *handle = Some(thread::spawn(|| loop {
+            thread::sleep(DEVICE_POLL_INTERVAL);
+            if DEVICE_WATCHER.is_device_lost.swap(false, Ordering::SeqCst) {
+                DEVICE_WATCHER.recover();
+            } else if DEVICE_WATCHER.is_fallback.load(Ordering::SeqCst) {
+                DEVICE_WATCHER.try_restore();
+            }
+        }));
+    }
+
+    /// Rebuilds the stream on the saved device, which is enough when only the stream was
+    /// invalidated, then on the host's default device and finally on the null output.
+    fn recover(&self) {
+        let previous_device_name = AUDIO_ENGINE.output_device_info().device_name;
+        let preferred = SETTINGS.get().audio;
+        warn!("Output device lost: {previous_device_name}");
+        // The input is reopened to follow the output, which can't happen under a running take
+        if RECORDER.is_recording() {
+            warn!("Finishing the running take before reopening the input");
+            RECORDER.stop_take();
+        }
+
+        if restart_output(&preferred).is_ok() {
+            info!("Output stream rebuilt on the same device");
+            restart_input_after_output_change();
+            return;
+        }
+
+        // Landing on the default device is only a fallback when a specific device was saved
+        let has_saved_device = preferred.output_device_id.is_some();
+        let default_device = AudioSettings {
+            output_device_id: None,
+            ..preferred.clone()
+        };
+        let null_device = AudioSettings {
+            host_id: Some(NULL_HOST_ID.to_string()),
+            output_device_id: None,
+            ..preferred
+        };
+        let is_fallback = match restart_output(&default_device) {
+            Ok(()) => has_saved_device,
+            Err(e) => {
+                warn!("Default output device unavailable, falling back to null output: {e}");
+                if let Err(e) = restart_output(&null_device) {
+                    log_and_notify_error(format!("Error trying to start null output: {e}"));
+                    return;
+                }
+                true
+            }
+        };
+        self.is_fallback.store(is_fallback, Ordering::SeqCst);
+        restart_input_after_output_change();
+
+        let output = AUDIO_ENGINE.output_device_info();
+        info!("Fell back to output device: {}", output.device_name);
+        let _ = app_handle().emit(
+            AUDIO_DEVICE_LOST_EVENT,
+            AudioDeviceChange {
+                previous_device_name,
+                output,
+            },
+        );
+    }
+
+    fn try_restore(&self) {
+        let preferred = SETTINGS.get().audio;
+        // Switching back would cut a running take, try again on a later poll
+        if RECORDER.is_recording() || !is_output_available(&preferred) {
+            return;
+        }
+        let previous_device_name = AUDIO_ENGINE.output_device_info().device_name;
+        if let Err(e) = restart_output(&preferred) {
+            warn!("Saved output device is back but couldn't be opened: {e}");
+            return;
+        }
+        self.is_fallback.store(false, Ordering::SeqCst);
+        restart_input_after_output_change();
+
+        let output = AUDIO_ENGINE.output_device_info();
+        info!("Output device restored: {}", output.device_name);
+        let _ = app_handle().emit(
+            AUDIO_DEVICE_RESTORED_EVENT,
+            AudioDeviceChange {
+                previous_device_name,
+                output,
+            },
+        );
+    }
+}
+
+fn is_output_available(settings: &AudioSettings) -> bool {
+    if settings.host_id.as_deref() == Some(NULL_HOST_ID) {
+        return true;
+    }
+    let Ok(host) = open_host(settings.host_id.as_deref()) else {
+        return false;
+    };
+    match settings.output_device_id.as_deref() {
+        Some(device_id) => DeviceId::from_str(device_id)
+            .ok()
+            .and_then(|device_id| host.device_by_id(&device_id))
+            .is_some(),
+        None => host.default_output_device().is_some(),
+    }
+}
+
+pub static DEVICE_WATCHER: LazyLock<DeviceWatcher> = LazyLock::new(|| DeviceWatcher::new());
@@ -0,0 +1,239 @@
+use std::sync::atomic::Ordering;
+
+use anyhow::{bail, Context, Result};
+use cpal::{
+    available_hosts,
+    traits::{DeviceTrait, HostTrait},
+    Device, DeviceId, SupportedBufferSize, SupportedStreamConfigRange,
+};
+use serde::Serialize;
+
+use crate::{
+    audio::{
+        asset_pool::ASSET_POOL,
+        device_watcher::DEVICE_WATCHER,
+        engine::{device_name, open_host, OutputDeviceInfo, AUDIO_ENGINE, NULL_DEVICE_NAME},
+        metronome::METRONOME,
+        preview_mixer::PREVIEW_MIXER,
+        project_state::PROJECT_STATE,
+        recorder::RECORDER,
+        snapshot::project_snapshot::{
+            rebuild_data_nodes_blocking, rebuild_render_graph_blocking, rebuild_scheduler_blocking,
+        },
+        transport::TRANSPORT,
+    },
+    core::{
+        constants::{
+            ENGINE_NUM_CHANNELS, NULL_HOST_ID, NULL_OUTPUT_BUFFER_SIZE_MAX,
+            NULL_OUTPUT_BUFFER_SIZE_MIN,
+        },
+        notify::log_and_notify_error,
+        settings::{AudioSettings, SETTINGS},
+    },
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioHostInfo {
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioDeviceConfigInfo {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub min_buffer_size: Option<u32>,
+    pub max_buffer_size: Option<u32>,
+    pub sample_format: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioDeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+    pub supported_configs: Vec<AudioDeviceConfigInfo>,
+}
+
+pub fn list_hosts() -> Vec<AudioHostInfo> {
+    let default_host_id = cpal::default_host().id();
+    let mut hosts: Vec<AudioHostInfo> = available_hosts()
+        .into_iter()
+        .map(|host_id| AudioHostInfo {
+            id: host_id.to_string(),
+            name: host_id.name().to_string(),
+            is_default: host_id == default_host_id,
+        })
+        .collect();
+    hosts.push(AudioHostInfo {
+        id: NULL_HOST_ID.to_string(),
+        name: NULL_DEVICE_NAME.to_string(),
+        is_default: false,
+    });
+    hosts
+}
+
+fn null_output_device() -> AudioDeviceInfo {
+    AudioDeviceInfo {
+        id: NULL_HOST_ID.to_string(),
+        name: NULL_DEVICE_NAME.to_string(),
+        is_default: true,
+        supported_configs: vec![AudioDeviceConfigInfo {
+            channels: ENGINE_NUM_CHANNELS as u16,
+            min_sample_rate: 8000,
+            max_sample_rate: 192000,
+            min_buffer_size: Some(NULL_OUTPUT_BUFFER_SIZE_MIN),
+            max_buffer_size: Some(NULL_OUTPUT_BUFFER_SIZE_MAX),
+            sample_format: cpal::SampleFormat::F32.to_string(),
+        }],
+    }
+}
+
+fn config_info(config: &SupportedStreamConfigRange) -> AudioDeviceConfigInfo {
+    let (min_buffer_size, max_buffer_size) = match config.buffer_size() {
+        SupportedBufferSize::Range { min, max } => (Some(*min), Some(*max)),
+        SupportedBufferSize::Unknown => (None, None),
+    };
+    AudioDeviceConfigInfo {
+        channels: config.channels(),
+        min_sample_rate: config.min_sample_rate(),
+        max_sample_rate: config.max_sample_rate(),
+        min_buffer_size,
+        max_buffer_size,
+        sample_format: config.sample_format().to_string(),
+    }
+}
+
+fn device_info(
+    device: &Device,
+    default_device_id: Option<&DeviceId>,
+    supported_configs: impl Iterator<Item = SupportedStreamConfigRange>,
+) -> Option<AudioDeviceInfo> {
+    let id = device.id().ok()?;
+    Some(AudioDeviceInfo {
+        is_default: default_device_id == Some(&id),
+        id: id.to_string(),
+        name: device_name(device),
+        supported_configs: supported_configs
+            .map(|config| config_info(&config))
+            .collect(),
+    })
+}
+
+pub fn list_output_devices(host_id: Option<&str>) -> Result<Vec<AudioDeviceInfo>> {
+    if host_id == Some(NULL_HOST_ID) {
+        return Ok(vec![null_output_device()]);
+    }
+
+    let host = open_host(host_id)?;
+    let default_device_id = host
+        .default_output_device()
+        .and_then(|device| device.id().ok());
+
+    let devices = host
+        .output_devices()
+        .context("Couldn't enumerate output devices")?
+        .filter_map(|device| {
+            let supported_configs = device.supported_output_configs().into_iter().flatten();
+            device_info(&device, default_device_id.as_ref(), supported_configs)
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+pub fn list_input_devices(host_id: Option<&str>) -> Result<Vec<AudioDeviceInfo>> {
+    if host_id == Some(NULL_HOST_ID) {
+        return Ok(Vec::new());
+    }
+
+    let host = open_host(host_id)?;
+    let default_device_id = host
+        .default_input_device()
+        .and_then(|device| device.id().ok());
+
+    let devices = host
+        .input_devices()
+        .context("Couldn't enumerate input devices")?
+        .filter_map(|device| {
+            let supported_configs = device.supported_input_configs().into_iter().flatten();
+            device_info(&device, default_device_id.as_ref(), supported_configs)
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// Switches the recording input and remembers it. An open input is reopened on the new device.
+pub fn set_input_device(device_id: Option<String>) -> Result<()> {
+    if RECORDER.is_recording() {
+        bail!("Can't change the input device while recording");
+    }
+    SETTINGS.update(|current| current.audio.input_device_id = device_id)?;
+    RECORDER.restart_input()
+}
+
+/// Rebuilds the output stream from the given settings without remembering them.
+/// Playback is suspended while the stream is rebuilt and resumed from the same position.
+/// On a sample rate change every asset is resampled from its source before playback resumes.
+pub fn restart_output(settings: &AudioSettings) -> Result<()> {
+    PREVIEW_MIXER.is_canceled.store(true, Ordering::SeqCst);
+    let was_playing = TRANSPORT.is_playing.swap(false, Ordering::SeqCst);
+    let previous_sample_rate = AUDIO_ENGINE.sample_rate();
+    let previous_buffer_size = AUDIO_ENGINE.buffer_size();
+
+    let result = AUDIO_ENGINE.start_with_settings(settings);
+
+    // Render graph buffers are sized for the device buffer
+    if AUDIO_ENGINE.buffer_size() != previous_buffer_size {
+        rebuild_render_graph_blocking();
+    }
+
+    let sample_rate = AUDIO_ENGINE.sample_rate();
+    if sample_rate != previous_sample_rate {
+        PROJECT_STATE.rescale_clip_offsets(previous_sample_rate, sample_rate);
+        TRANSPORT.rescale_position(previous_sample_rate, sample_rate);
+        ASSET_POOL.audio.resample_to_engine_rate();
+        METRONOME.rebuild_sounds();
+        rebuild_scheduler_blocking();
+        // Convolution reverbs reload their impulse responses at the new rate
+        rebuild_data_nodes_blocking();
+    }
+    // The rebuilds above are stored by now, so the first block renders with the new snapshot
+    if was_playing {
+        TRANSPORT.resume();
+    }
+    result
+}
+
+/// The input monitors into the output stream and has to follow its host and sample rate.
+pub fn restart_input_after_output_change() {
+    if let Err(e) = RECORDER.restart_input() {
+        log_and_notify_error(format!("Error trying to reopen input device: {e}"));
+    }
+}
+
+/// Reopens the output with new device, sample rate or buffer size settings and remembers them.
+/// Refused while recording, since the take would be cut.
+pub fn apply_audio_settings(settings: AudioSettings) -> Result<OutputDeviceInfo> {
+    if RECORDER.is_recording() {
+        bail!("Can't change audio settings while recording");
+    }
+    restart_output(&settings)?;
+    DEVICE_WATCHER.clear_fallback();
+
+    // The device may have fallen back to another rate or buffer size, keep what is actually in use
+    let settings = AudioSettings {
+        sample_rate: settings.sample_rate.map(|_| AUDIO_ENGINE.sample_rate()),
+        buffer_size: settings.buffer_size.map(|_| AUDIO_ENGINE.buffer_size()),
+        ..settings
+    };
+    SETTINGS.update(|current| current.audio = settings)?;
+    restart_input_after_output_change();
+    Ok(AUDIO_ENGINE.output_device_info())
+}
@@ -0,0 +1,377 @@
+use std::sync::{atomic::Ordering, Arc, Mutex};
+
+use atomic_float::AtomicF32;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{
+        effects::{
+            compressor::Compressor,
+            convolution_reverb::ConvolutionReverb,
+            delay::Delay,
+            gain_effect::GainEffect,
+            gate::Gate,
+            impulse_response::{ImpulseResponse, IMPULSE_RESPONSES},
+            limiter::Limiter,
+            parametric_eq::ParametricEq,
+            reverb::Reverb,
+        },
+        engine::AUDIO_ENGINE,
+    },
+    core::{
+        notify::log_and_notify_error,
+        types::{EngineSampleFormat, Id},
+    },
+};
+
+/// Describes a parameter an effect exposes. Values are always in the parameter's own unit.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectParameter {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub unit: &'static str,
+    /// Labels of a parameter that picks one of several options, its value is the option index.
+    /// Empty for continuous parameters.
+    pub options: &'static [&'static str],
+}
+
+/// What an effect knows about the block it processes.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessContext {
+    pub sample_rate: usize,
+    /// Tempo at the start of the block, also while tails ring out after stopping.
+    pub tempo_bpm: f32,
+}
+
+/// Insert effect running on the render path.
+/// `process` is called for every block, so it must not allocate, lock or block.
+pub trait AudioEffect: Send {
+    /// Sets the parameter at `index` in `EffectKind::parameters`, already clamped to its range.
+    fn set_parameter(&mut self, index: usize, value: f32);
+
+    /// Delay the effect adds to the signal, in frames.
+    fn latency_frames(&self) -> usize {
+        0
+    }
+
+    /// Clears internal state such as filter memory and tails.
+    fn reset(&mut self);
+
+    /// Processes an interleaved stereo buffer in place.
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext);
+
+    /// Highest gain reduction during the last processed block, in positive dB.
+    /// Only dynamics processors report it.
+    fn gain_reduction_db(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Built-in effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EffectKind {
+    Gain,
+    ParametricEq,
+    Compressor,
+    Limiter,
+    Gate,
+    Reverb,
+    Delay,
+    ConvolutionReverb,
+}
+
+impl EffectKind {
+    pub const ALL: &'static [EffectKind] = &[
+        EffectKind::Gain,
+        EffectKind::ParametricEq,
+        EffectKind::Compressor,
+        EffectKind::Limiter,
+        EffectKind::Gate,
+        EffectKind::Reverb,
+        EffectKind::Delay,
+        EffectKind::ConvolutionReverb,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectKind::Gain => "Gain",
+            EffectKind::ParametricEq => "Parametric EQ",
+            EffectKind::Compressor => "Compressor",
+            EffectKind::Limiter => "Limiter",
+            EffectKind::Gate => "Gate",
+            EffectKind::Reverb => "Reverb",
+            EffectKind::Delay => "Delay",
+            EffectKind::ConvolutionReverb => "Convolution reverb",
+        }
+    }
+
+    pub fn parameters(&self) -> &'static [EffectParameter] {
+        match self {
+            EffectKind::Gain => GainEffect::PARAMETERS,
+            EffectKind::ParametricEq => ParametricEq::PARAMETERS,
+            EffectKind::Compressor => Compressor::PARAMETERS,
+            EffectKind::Limiter => Limiter::PARAMETERS,
+            EffectKind::Gate => Gate::PARAMETERS,
+            EffectKind::Reverb => Reverb::PARAMETERS,
+            EffectKind::Delay => Delay::PARAMETERS,
+            EffectKind::ConvolutionReverb => ConvolutionReverb::PARAMETERS,
+        }
+    }
+
+    pub fn default_parameters(&self) -> Vec<f32> {
+        self.parameters()
+            .iter()
+            .map(|parameter| parameter.default)
+            .collect()
+    }
+
+    /// Creates the effect with default parameters for the engine running at `sample_rate`.
+    /// Only the convolution reverb uses `impulse_response`.
+    pub fn create(
+        &self,
+        sample_rate: usize,
+        impulse_response: Option<Arc<ImpulseResponse>>,
+    ) -> Box<dyn AudioEffect> {
+        match self {
+            EffectKind::Gain => Box::new(GainEffect::new()),
+            EffectKind::ParametricEq => Box::new(ParametricEq::new()),
+            EffectKind::Compressor => Box::new(Compressor::new()),
+            EffectKind::Limiter => Box::new(Limiter::new(sample_rate)),
+            EffectKind::Gate => Box::new(Gate::new()),
+            EffectKind::Reverb => Box::new(Reverb::new()),
+            EffectKind::Delay => Box::new(Delay::new()),
+            EffectKind::ConvolutionReverb => Box::new(ConvolutionReverb::new(impulse_response)),
+        }
+    }
+
+    /// Seconds the effect keeps sounding after its input goes silent, so stopping and bouncing
+    /// can let it ring out.
+    pub fn tail_time(&self, parameters: &[f32], tempo_bpm: f32) -> f64 {
+        match self {
+            EffectKind::Reverb => Reverb::tail_time(parameters),
+            EffectKind::Delay => Delay::tail_time(parameters, tempo_bpm),
+            _ => 0.0,
+        }
+    }
+
+    /// Whether the effect reports gain reduction to the meters.
+    pub fn reports_gain_reduction(&self) -> bool {
+        matches!(
+            self,
+            EffectKind::Compressor | EffectKind::Limiter | EffectKind::Gate
+        )
+    }
+
+    /// Gain in dB at each of `frequencies` for the given parameters, for effects that filter.
+    /// Lets the frontend draw the curve without doing DSP itself.
+    pub fn frequency_response(
+        &self,
+        parameters: &[f32],
+        sample_rate: usize,
+        frequencies: &[f64],
+    ) -> Option<Vec<f32>> {
+        match self {
+            EffectKind::ParametricEq => Some(ParametricEq::frequency_response(
+                parameters,
+                sample_rate,
+                frequencies,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Effect kind with its parameters, listed for the frontend.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectInfo {
+    pub kind: EffectKind,
+    pub name: &'static str,
+    pub parameters: &'static [EffectParameter],
+}
+
+impl From<EffectKind> for EffectInfo {
+    fn from(kind: EffectKind) -> Self {
+        Self {
+            kind,
+            name: kind.name(),
+            parameters: kind.parameters(),
+        }
+    }
+}
+
+/// Gain of an effect over the audible range, for drawing its curve.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyResponse {
+    /// Log-spaced from 20 Hz to 20 kHz.
+    pub frequencies: Vec<f32>,
+    pub gains_db: Vec<f32>,
+}
+
+impl FrequencyResponse {
+    const MIN_FREQUENCY: f64 = 20.0;
+    const MAX_FREQUENCY: f64 = 20000.0;
+
+    /// Evaluates the effect at `num_points` frequencies, `None` if it doesn't filter.
+    pub fn of(insert: &InsertEffect, sample_rate: usize, num_points: usize) -> Option<Self> {
+        let num_points = num_points.max(2);
+        let ratio = Self::MAX_FREQUENCY / Self::MIN_FREQUENCY;
+        let frequencies: Vec<f64> = (0..num_points)
+            .map(|index| Self::MIN_FREQUENCY * ratio.powf(index as f64 / (num_points - 1) as f64))
+            .collect();
+        let gains_db =
+            insert
+                .kind
+                .frequency_response(&insert.parameters, sample_rate, &frequencies)?;
+        Some(Self {
+            frequencies: frequencies
+                .iter()
+                .map(|frequency| *frequency as f32)
+                .collect(),
+            gains_db,
+        })
+    }
+}
+
+/// Effect in a track's insert chain, as stored in the project.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertEffect {
+    pub id: Id,
+    pub kind: EffectKind,
+    pub bypassed: bool,
+    /// Values in the order of `EffectKind::parameters`.
+    pub parameters: Vec<f32>,
+    /// Audio asset the convolution reverb uses as its impulse response.
+    pub impulse_response_id: Option<Id>,
+}
+
+impl InsertEffect {
+    pub fn new(kind: EffectKind) -> Self {
+        Self {
+            id: nanoid!(),
+            kind,
+            bypassed: false,
+            parameters: kind.default_parameters(),
+            impulse_response_id: None,
+        }
+    }
+}
+
+struct EffectState {
+    effect: Box<dyn AudioEffect>,
+    applied_parameters: Vec<f32>,
+}
+
+/// Running effect together with the parameters it was last given, so only changed parameters
+/// are passed on.
+pub struct EffectInstance {
+    state: Mutex<EffectState>,
+    impulse_response_id: Option<Id>,
+    impulse_response: Option<Arc<ImpulseResponse>>,
+    // Engine sample rate the effect was created for, which its latency depends on
+    sample_rate: usize,
+    // Read by the UI without locking the running effect
+    latency_frames: usize,
+    reports_gain_reduction: bool,
+    // Highest gain reduction since the previous reading, in dB
+    gain_reduction_db: AtomicF32,
+}
+
+impl EffectInstance {
+    /// Loads the impulse response first if the effect has one. Blocking, called while building
+    /// data nodes.
+    pub fn new(insert: &InsertEffect) -> Self {
+        let kind = insert.kind;
+        let impulse_response = insert
+            .impulse_response_id
+            .as_deref()
+            .filter(|_| kind == EffectKind::ConvolutionReverb)
+            .and_then(|asset_id| match IMPULSE_RESPONSES.get(asset_id) {
+                Ok(impulse_response) => Some(impulse_response),
+                Err(e) => {
+                    log_and_notify_error(format!("Error trying to load impulse response: {e}"));
+                    None
+                }
+            });
+        let sample_rate = AUDIO_ENGINE.sample_rate();
+        let effect = kind.create(sample_rate, impulse_response.clone());
+        let latency_frames = effect.latency_frames();
+        Self {
+            state: Mutex::new(EffectState {
+                effect,
+                applied_parameters: kind.default_parameters(),
+            }),
+            impulse_response_id: insert.impulse_response_id.clone(),
+            impulse_response,
+            sample_rate,
+            latency_frames,
+            reports_gain_reduction: kind.reports_gain_reduction(),
+            gain_reduction_db: AtomicF32::new(0.0),
+        }
+    }
+
+    /// Whether the instance can keep running `insert`. An effect never changes its kind, but a
+    /// new impulse response or engine sample rate needs a new instance.
+    pub fn is_current(&self, insert: &InsertEffect) -> bool {
+        self.impulse_response_id == insert.impulse_response_id
+            && self.sample_rate == AUDIO_ENGINE.sample_rate()
+    }
+
+    /// Length of the loaded impulse response, which the tail rings out for.
+    pub fn impulse_response_frames(&self) -> usize {
+        self.impulse_response
+            .as_ref()
+            .map_or(0, |impulse_response| impulse_response.len_frames)
+    }
+
+    pub fn latency_frames(&self) -> usize {
+        self.latency_frames
+    }
+
+    pub fn reset(&self) {
+        self.state.lock().unwrap().effect.reset();
+    }
+
+    /// Returns the gain reduction since the previous reading and starts a new window.
+    /// Lock-free, so the meter reporter never holds up a renderer.
+    pub fn take_gain_reduction_db(&self) -> Option<f32> {
+        self.reports_gain_reduction
+            .then(|| self.gain_reduction_db.swap(0.0, Ordering::Relaxed))
+    }
+
+    pub fn process(
+        &self,
+        parameters: &[f32],
+        buffer: &mut [EngineSampleFormat],
+        context: &ProcessContext,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let EffectState {
+            effect,
+            applied_parameters,
+        } = &mut *state;
+        for (index, (applied, value)) in applied_parameters
+            .iter_mut()
+            .zip(parameters.iter())
+            .enumerate()
+        {
+            if applied != value {
+                effect.set_parameter(index, *value);
+                *applied = *value;
+            }
+        }
+        effect.process(buffer, context);
+        if let Some(gain_reduction_db) = effect.gain_reduction_db() {
+            self.gain_reduction_db
+                .fetch_max(gain_reduction_db, Ordering::Relaxed);
+        }
+    }
+}
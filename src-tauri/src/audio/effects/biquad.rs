@@ -0,0 +1,150 @@
+use std::f64::consts::PI;
+
+/// Second order filter coefficients, normalized so a0 is 1. Formulas from the RBJ Audio EQ
+/// Cookbook.
+#[derive(Debug, Clone, Copy)]
+pub struct BiquadCoefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl BiquadCoefficients {
+    pub const IDENTITY: Self = Self {
+        b0: 1.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+    };
+
+    fn normalized(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Angular frequency, kept below Nyquist, with its cosine and the bandwidth term alpha.
+    fn prewarp(sample_rate: f64, frequency: f64, q: f64) -> (f64, f64) {
+        let frequency = frequency.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        (w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    pub fn low_pass(sample_rate: f64, frequency: f64, q: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, frequency, q);
+        Self::normalized(
+            (1.0 - cos_w0) / 2.0,
+            1.0 - cos_w0,
+            (1.0 - cos_w0) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_w0,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn high_pass(sample_rate: f64, frequency: f64, q: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, frequency, q);
+        Self::normalized(
+            (1.0 + cos_w0) / 2.0,
+            -(1.0 + cos_w0),
+            (1.0 + cos_w0) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_w0,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn notch(sample_rate: f64, frequency: f64, q: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, frequency, q);
+        Self::normalized(
+            1.0,
+            -2.0 * cos_w0,
+            1.0,
+            1.0 + alpha,
+            -2.0 * cos_w0,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn peak(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, frequency, q);
+        let a = 10f64.powf(gain_db / 40.0);
+        Self::normalized(
+            1.0 + alpha * a,
+            -2.0 * cos_w0,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos_w0,
+            1.0 - alpha / a,
+        )
+    }
+
+    pub fn low_shelf(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, frequency, q);
+        let a = 10f64.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+            a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha),
+            (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+            (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha,
+        )
+    }
+
+    pub fn high_shelf(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, frequency, q);
+        let a = 10f64.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+            a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+            (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha,
+        )
+    }
+
+    /// Gain of the filter at `frequency`, as a linear factor.
+    pub fn magnitude(&self, sample_rate: f64, frequency: f64) -> f64 {
+        let w = 2.0 * PI * frequency / sample_rate;
+        let (cos_w, sin_w) = (w.cos(), w.sin());
+        let (cos_2w, sin_2w) = ((2.0 * w).cos(), (2.0 * w).sin());
+        let numerator_re = self.b0 + self.b1 * cos_w + self.b2 * cos_2w;
+        let numerator_im = -(self.b1 * sin_w + self.b2 * sin_2w);
+        let denominator_re = 1.0 + self.a1 * cos_w + self.a2 * cos_2w;
+        let denominator_im = -(self.a1 * sin_w + self.a2 * sin_2w);
+        ((numerator_re * numerator_re + numerator_im * numerator_im)
+            / (denominator_re * denominator_re + denominator_im * denominator_im))
+            .sqrt()
+    }
+}
+
+/// Memory of one filter on one channel, transposed direct form II.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiquadState {
+    s1: f64,
+    s2: f64,
+}
+
+impl BiquadState {
+    pub fn process(&mut self, coefficients: &BiquadCoefficients, input: f64) -> f64 {
+        let output = coefficients.b0 * input + self.s1;
+        self.s1 = coefficients.b1 * input - coefficients.a1 * output + self.s2;
+        self.s2 = coefficients.b2 * input - coefficients.a2 * output;
+        output
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
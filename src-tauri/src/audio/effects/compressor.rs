@@ -0,0 +1,170 @@
+use crate::{
+    audio::{
+        effects::{
+            audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+            dynamics::{follow, frame_level_db, parameter_smoothing_coefficient, time_coefficient},
+        },
+        gain::db_to_gain,
+    },
+    core::{constants::ENGINE_NUM_CHANNELS, types::EngineSampleFormat},
+};
+
+const THRESHOLD_INDEX: usize = 0;
+const RATIO_INDEX: usize = 1;
+const KNEE_INDEX: usize = 2;
+const ATTACK_INDEX: usize = 3;
+const RELEASE_INDEX: usize = 4;
+const MAKEUP_GAIN_INDEX: usize = 5;
+
+/// Feed-forward compressor with a soft knee, both channels linked.
+/// Gain reduction is smoothed in dB, attacking while it grows and releasing while it shrinks.
+pub struct Compressor {
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    makeup_gain_db: f32,
+    // Smoothed state, carried between blocks
+    reduction_db: f32,
+    current_makeup_gain_db: f32,
+    block_reduction_db: f32,
+}
+
+impl Compressor {
+    pub const PARAMETERS: &'static [EffectParameter] = &[
+        EffectParameter {
+            id: "threshold",
+            name: "Threshold",
+            min: -60.0,
+            max: 0.0,
+            default: -18.0,
+            unit: "dB",
+            options: &[],
+        },
+        EffectParameter {
+            id: "ratio",
+            name: "Ratio",
+            min: 1.0,
+            max: 20.0,
+            default: 4.0,
+            unit: ":1",
+            options: &[],
+        },
+        EffectParameter {
+            id: "knee",
+            name: "Knee",
+            min: 0.0,
+            max: 24.0,
+            default: 6.0,
+            unit: "dB",
+            options: &[],
+        },
+        EffectParameter {
+            id: "attack",
+            name: "Attack",
+            min: 0.1,
+            max: 100.0,
+            default: 10.0,
+            unit: "ms",
+            options: &[],
+        },
+        EffectParameter {
+            id: "release",
+            name: "Release",
+            min: 5.0,
+            max: 1000.0,
+            default: 100.0,
+            unit: "ms",
+            options: &[],
+        },
+        EffectParameter {
+            id: "makeupGain",
+            name: "Makeup gain",
+            min: 0.0,
+            max: 24.0,
+            default: 0.0,
+            unit: "dB",
+            options: &[],
+        },
+    ];
+
+    pub fn new() -> Self {
+        let parameter = |index: usize| Self::PARAMETERS[index].default;
+        Self {
+            threshold_db: parameter(THRESHOLD_INDEX),
+            ratio: parameter(RATIO_INDEX),
+            knee_db: parameter(KNEE_INDEX),
+            attack_ms: parameter(ATTACK_INDEX),
+            release_ms: parameter(RELEASE_INDEX),
+            makeup_gain_db: parameter(MAKEUP_GAIN_INDEX),
+            reduction_db: 0.0,
+            current_makeup_gain_db: parameter(MAKEUP_GAIN_INDEX),
+            block_reduction_db: 0.0,
+        }
+    }
+
+    /// Gain reduction the static curve asks for at `level_db`, in positive dB.
+    fn target_reduction_db(&self, level_db: f32) -> f32 {
+        let overshoot = level_db - self.threshold_db;
+        let slope = 1.0 - 1.0 / self.ratio;
+        if 2.0 * overshoot <= -self.knee_db {
+            0.0
+        } else if 2.0 * overshoot.abs() < self.knee_db {
+            // Quadratic blend between no compression and the full ratio across the knee
+            slope * (overshoot + self.knee_db / 2.0).powi(2) / (2.0 * self.knee_db)
+        } else {
+            slope * overshoot
+        }
+    }
+}
+
+impl AudioEffect for Compressor {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            THRESHOLD_INDEX => self.threshold_db = value,
+            RATIO_INDEX => self.ratio = value.max(1.0),
+            KNEE_INDEX => self.knee_db = value,
+            ATTACK_INDEX => self.attack_ms = value,
+            RELEASE_INDEX => self.release_ms = value,
+            MAKEUP_GAIN_INDEX => self.makeup_gain_db = value,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+        self.current_makeup_gain_db = self.makeup_gain_db;
+        self.block_reduction_db = 0.0;
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let sample_rate = context.sample_rate;
+        let attack = time_coefficient(self.attack_ms, sample_rate);
+        let release = time_coefficient(self.release_ms, sample_rate);
+        let smoothing = parameter_smoothing_coefficient(sample_rate);
+
+        self.block_reduction_db = 0.0;
+        for frame in buffer.chunks_exact_mut(ENGINE_NUM_CHANNELS as usize) {
+            let target = self.target_reduction_db(frame_level_db(frame));
+            let coefficient = if target > self.reduction_db {
+                attack
+            } else {
+                release
+            };
+            self.reduction_db = follow(self.reduction_db, target, coefficient);
+            self.current_makeup_gain_db =
+                follow(self.current_makeup_gain_db, self.makeup_gain_db, smoothing);
+            self.block_reduction_db = self.block_reduction_db.max(self.reduction_db);
+
+            let gain = db_to_gain(self.current_makeup_gain_db - self.reduction_db);
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+
+    fn gain_reduction_db(&self) -> Option<f32> {
+        Some(self.block_reduction_db)
+    }
+}
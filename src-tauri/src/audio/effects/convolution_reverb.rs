@@ -0,0 +1,346 @@
+use std::{ops::Range, sync::Arc};
+
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+
+use crate::{
+    audio::{
+        effects::{
+            audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+            dynamics::{follow, parameter_smoothing_coefficient},
+            impulse_response::{
+                ImpulseResponse, PartitionedSegment, HEAD_PARTITION_FRAMES, TAIL_PARTITION_FRAMES,
+            },
+        },
+        gain::db_to_gain,
+    },
+    core::{constants::ENGINE_NUM_CHANNELS, types::EngineSampleFormat},
+};
+
+const MIX_INDEX: usize = 0;
+const GAIN_INDEX: usize = 1;
+/// Head blocks per tail partition, the tail's work is split into this many slices.
+const TAIL_STEPS: usize = TAIL_PARTITION_FRAMES / HEAD_PARTITION_FRAMES;
+
+/// Uniformly partitioned convolution of one segment of an impulse response.
+/// Every partition of input per channel gives the matching partition of output, keeping the
+/// spectra of past inputs in a ring so each partition of the response is applied to the input
+/// it lines up with. `process` does it in one go, the steps it is made of can also be spread
+/// over several calls.
+struct ConvolutionStage {
+    partition_frames: usize,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    // The previous and the current partition of input, per channel
+    input_windows: [Vec<f32>; 2],
+    // Spectra of the last inputs per channel, one for every partition of the response
+    input_spectra: [Vec<Vec<Complex<f32>>>; 2],
+    // Ring index of the newest spectrum
+    position: usize,
+    // Sum of the products so far, per output channel
+    accumulators: [Vec<Complex<f32>>; 2],
+    time_buffer: Vec<f32>,
+    scratch: Vec<Complex<f32>>,
+    outputs: [Vec<f32>; 2],
+}
+
+impl ConvolutionStage {
+    fn new(segment: &PartitionedSegment, planner: &mut RealFftPlanner<f32>) -> Self {
+        let partition_frames = segment.partition_frames;
+        let forward = planner.plan_fft_forward(partition_frames * 2);
+        let inverse = planner.plan_fft_inverse(partition_frames * 2);
+        let scratch_len = forward.get_scratch_len().max(inverse.get_scratch_len());
+        let num_partitions = segment.num_partitions().max(1);
+        Self {
+            partition_frames,
+            input_windows: [
+                vec![0.0; partition_frames * 2],
+                vec![0.0; partition_frames * 2],
+            ],
+            input_spectra: [
+                vec![forward.make_output_vec(); num_partitions],
+                vec![forward.make_output_vec(); num_partitions],
+            ],
+            position: 0,
+            accumulators: [forward.make_output_vec(), forward.make_output_vec()],
+            time_buffer: forward.make_input_vec(),
+            scratch: vec![Complex::default(); scratch_len],
+            outputs: [vec![0.0; partition_frames], vec![0.0; partition_frames]],
+            forward,
+            inverse,
+        }
+    }
+
+    fn reset(&mut self) {
+        for channel in 0..2 {
+            self.input_windows[channel].fill(0.0);
+            for spectrum in self.input_spectra[channel].iter_mut() {
+                spectrum.fill(Complex::default());
+            }
+            self.outputs[channel].fill(0.0);
+        }
+        self.position = 0;
+    }
+
+    /// Convolves the next `partition_frames` frames of both channels into `outputs`.
+    fn process(&mut self, segment: &PartitionedSegment, inputs: [&[f32]; 2]) {
+        self.push_input(inputs);
+        self.transform_input();
+        self.accumulate(segment, 0..segment.num_partitions());
+        self.finish(segment);
+    }
+
+    /// Takes the next `partition_frames` frames of both channels.
+    fn push_input(&mut self, inputs: [&[f32]; 2]) {
+        let partition_frames = self.partition_frames;
+        for (window, input) in self.input_windows.iter_mut().zip(inputs) {
+            window.copy_within(partition_frames.., 0);
+            window[partition_frames..].copy_from_slice(input);
+        }
+    }
+
+    /// Transforms the input taken last into the ring and starts new sums.
+    fn transform_input(&mut self) {
+        let num_partitions = self.input_spectra[0].len();
+        self.position = (self.position + 1) % num_partitions;
+        for channel in 0..2 {
+            // The transform overwrites its input, the window is still needed for the next call
+            self.time_buffer
+                .copy_from_slice(&self.input_windows[channel]);
+            // Lengths always match the plan
+            let _ = self.forward.process_with_scratch(
+                &mut self.time_buffer,
+                &mut self.input_spectra[channel][self.position],
+                &mut self.scratch,
+            );
+            self.accumulators[channel].fill(Complex::default());
+        }
+    }
+
+    /// Adds the products of the response's `partitions` with the inputs they line up with.
+    fn accumulate(&mut self, segment: &PartitionedSegment, partitions: Range<usize>) {
+        let num_partitions = self.input_spectra[0].len();
+        for path in segment.paths.iter() {
+            let spectra = &self.input_spectra[path.input_channel];
+            let accumulator = &mut self.accumulators[path.output_channel];
+            for (index, partition) in path
+                .partitions
+                .iter()
+                .enumerate()
+                .take(partitions.end)
+                .skip(partitions.start)
+            {
+                let spectrum = &spectra[(self.position + num_partitions - index) % num_partitions];
+                for ((sum, input), response) in accumulator
+                    .iter_mut()
+                    .zip(spectrum.iter())
+                    .zip(partition.iter())
+                {
+                    *sum += input * response;
+                }
+            }
+        }
+    }
+
+    /// Turns the sums back into `partition_frames` frames of output per channel.
+    fn finish(&mut self, segment: &PartitionedSegment) {
+        let partition_frames = self.partition_frames;
+        for (output_channel, output) in self.outputs.iter_mut().enumerate() {
+            if !segment
+                .paths
+                .iter()
+                .any(|path| path.output_channel == output_channel)
+            {
+                output.fill(0.0);
+                continue;
+            }
+            // Rounding can leave the imaginary parts at DC and Nyquist slightly off zero, which
+            // the inverse transform ignores
+            let _ = self.inverse.process_with_scratch(
+                &mut self.accumulators[output_channel],
+                &mut self.time_buffer,
+                &mut self.scratch,
+            );
+            // The first half wrapped around from the previous window
+            output.copy_from_slice(&self.time_buffer[partition_frames..]);
+        }
+    }
+}
+
+/// Reverb that convolves the signal with a recorded impulse response from the asset pool.
+/// The start of the response runs in small partitions and the rest in large ones, so the
+/// latency stays at `HEAD_PARTITION_FRAMES` for responses of any length and the effect can be
+/// used on live tracks. A large partition is computed a slice per small block while the next
+/// one gathers, which keeps the cost of every block about the same.
+/// Without a response only the dry signal passes.
+pub struct ConvolutionReverb {
+    impulse_response: Option<Arc<ImpulseResponse>>,
+    head: Option<ConvolutionStage>,
+    tail: Option<ConvolutionStage>,
+    mix: f32,
+    wet_gain: f32,
+    current_mix: f32,
+    current_wet_gain: f32,
+    // Input of the block being gathered. Until a frame is overwritten it holds the previous
+    // block, which is played back dry alongside `wet`.
+    block: [Vec<f32>; 2],
+    block_filled: usize,
+    // Wet output of the previous block
+    wet: [Vec<f32>; 2],
+    // Input gathered for the next tail partition, the one before is being convolved meanwhile
+    tail_input: [Vec<f32>; 2],
+    tail_filled: usize,
+}
+
+impl ConvolutionReverb {
+    pub const PARAMETERS: &'static [EffectParameter] = &[
+        EffectParameter {
+            id: "mix",
+            name: "Mix",
+            min: 0.0,
+            max: 100.0,
+            default: 100.0,
+            unit: "%",
+            options: &[],
+        },
+        EffectParameter {
+            id: "gain",
+            name: "Gain",
+            min: -24.0,
+            max: 24.0,
+            default: 0.0,
+            unit: "dB",
+            options: &[],
+        },
+    ];
+
+    pub fn new(impulse_response: Option<Arc<ImpulseResponse>>) -> Self {
+        let parameter = |index: usize| Self::PARAMETERS[index].default;
+        let mut planner = RealFftPlanner::new();
+        let head = impulse_response
+            .as_ref()
+            .map(|impulse_response| ConvolutionStage::new(&impulse_response.head, &mut planner));
+        let tail = impulse_response
+            .as_ref()
+            .and_then(|impulse_response| impulse_response.tail.as_ref())
+            .map(|segment| ConvolutionStage::new(segment, &mut planner));
+        Self {
+            impulse_response,
+            head,
+            tail,
+            mix: parameter(MIX_INDEX) / 100.0,
+            wet_gain: db_to_gain(parameter(GAIN_INDEX)),
+            current_mix: parameter(MIX_INDEX) / 100.0,
+            current_wet_gain: db_to_gain(parameter(GAIN_INDEX)),
+            block: [
+                vec![0.0; HEAD_PARTITION_FRAMES],
+                vec![0.0; HEAD_PARTITION_FRAMES],
+            ],
+            block_filled: 0,
+            wet: [
+                vec![0.0; HEAD_PARTITION_FRAMES],
+                vec![0.0; HEAD_PARTITION_FRAMES],
+            ],
+            tail_input: [
+                vec![0.0; TAIL_PARTITION_FRAMES],
+                vec![0.0; TAIL_PARTITION_FRAMES],
+            ],
+            tail_filled: 0,
+        }
+    }
+
+    /// Convolves the block just gathered into `wet`.
+    fn process_block(&mut self) {
+        let (Some(impulse_response), Some(head)) = (&self.impulse_response, &mut self.head) else {
+            return;
+        };
+        head.process(&impulse_response.head, [&self.block[0], &self.block[1]]);
+        for channel in 0..2 {
+            self.wet[channel].copy_from_slice(&head.outputs[channel]);
+        }
+
+        let (Some(segment), Some(tail)) = (&impulse_response.tail, &mut self.tail) else {
+            return;
+        };
+        // The tail partition finished last lines up with the block just gathered, as the
+        // response's tail starts `HEAD_FRAMES` in: one partition to gather the input and one
+        // to convolve it
+        let range = self.tail_filled..self.tail_filled + HEAD_PARTITION_FRAMES;
+        for channel in 0..2 {
+            for (wet, tail_output) in self.wet[channel]
+                .iter_mut()
+                .zip(tail.outputs[channel][range.clone()].iter())
+            {
+                *wet += tail_output;
+            }
+            self.tail_input[channel][range.clone()].copy_from_slice(&self.block[channel]);
+        }
+
+        let step = self.tail_filled / HEAD_PARTITION_FRAMES;
+        if step == 0 {
+            tail.transform_input();
+        }
+        let num_partitions = segment.num_partitions();
+        tail.accumulate(
+            segment,
+            step * num_partitions / TAIL_STEPS..(step + 1) * num_partitions / TAIL_STEPS,
+        );
+        if step == TAIL_STEPS - 1 {
+            tail.finish(segment);
+        }
+
+        self.tail_filled = range.end;
+        if self.tail_filled == TAIL_PARTITION_FRAMES {
+            tail.push_input([&self.tail_input[0], &self.tail_input[1]]);
+            self.tail_filled = 0;
+        }
+    }
+}
+
+impl AudioEffect for ConvolutionReverb {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            MIX_INDEX => self.mix = value / 100.0,
+            GAIN_INDEX => self.wet_gain = db_to_gain(value),
+            _ => {}
+        }
+    }
+
+    fn latency_frames(&self) -> usize {
+        HEAD_PARTITION_FRAMES
+    }
+
+    fn reset(&mut self) {
+        self.current_mix = self.mix;
+        self.current_wet_gain = self.wet_gain;
+        for channel in 0..2 {
+            self.block[channel].fill(0.0);
+            self.wet[channel].fill(0.0);
+            self.tail_input[channel].fill(0.0);
+        }
+        self.block_filled = 0;
+        self.tail_filled = 0;
+        for stage in self.head.iter_mut().chain(self.tail.iter_mut()) {
+            stage.reset();
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let smoothing = parameter_smoothing_coefficient(context.sample_rate);
+        for frame in buffer.chunks_exact_mut(ENGINE_NUM_CHANNELS as usize) {
+            self.current_mix = follow(self.current_mix, self.mix, smoothing);
+            self.current_wet_gain = follow(self.current_wet_gain, self.wet_gain, smoothing);
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let dry = self.block[channel][self.block_filled];
+                let wet = self.wet[channel][self.block_filled];
+                self.block[channel][self.block_filled] = *sample;
+                *sample =
+                    dry * (1.0 - self.current_mix) + wet * self.current_wet_gain * self.current_mix;
+            }
+            self.block_filled += 1;
+            if self.block_filled == HEAD_PARTITION_FRAMES {
+                self.process_block();
+                self.block_filled = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    audio::{
+        effects::{
+            audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+            delay_line::DelayLine,
+            dynamics::{follow, parameter_smoothing_coefficient},
+        },
+        gain::gain_to_db,
+    },
+    core::{
+        constants::{EFFECT_SAMPLE_RATE_MAX, ENGINE_NUM_CHANNELS},
+        types::EngineSampleFormat,
+    },
+};
+
+const SYNC_INDEX: usize = 0;
+const TIME_INDEX: usize = 1;
+const NOTE_VALUE_INDEX: usize = 2;
+const FEEDBACK_INDEX: usize = 3;
+const PING_PONG_INDEX: usize = 4;
+const MIX_INDEX: usize = 5;
+
+/// Longest delay in seconds, synced note values at slow tempos are clamped to it.
+const MAX_DELAY_TIME: f32 = 4.0;
+/// Tails of very high feedback settings are cut off after this many seconds.
+const MAX_TAIL_TIME: f64 = 30.0;
+
+const NOTE_VALUES: &[&str] = &[
+    "1/32", "1/16T", "1/16", "1/16D", "1/8T", "1/8", "1/8D", "1/4T", "1/4", "1/4D", "1/2", "1/1",
+];
+/// Length of each of `NOTE_VALUES` in quarter notes.
+const NOTE_VALUE_BEATS: &[f32] = &[
+    0.125,
+    1.0 / 6.0,
+    0.25,
+    0.375,
+    1.0 / 3.0,
+    0.5,
+    0.75,
+    2.0 / 3.0,
+    1.0,
+    1.5,
+    2.0,
+    4.0,
+];
+
+fn delay_time(sync: bool, time_ms: f32, note_value: f32, tempo_bpm: f32) -> f32 {
+    let time = if sync {
+        let beats = NOTE_VALUE_BEATS[(note_value as usize).min(NOTE_VALUE_BEATS.len() - 1)];
+        beats * 60.0 / tempo_bpm
+    } else {
+        time_ms / 1000.0
+    };
+    time.min(MAX_DELAY_TIME)
+}
+
+/// Stereo echo with feedback. Synced to the tempo it follows the tempo map block by block,
+/// gliding to the new time like a tape delay instead of jumping.
+/// Ping-pong feeds the input into the left line and bounces the repeats between the channels.
+pub struct Delay {
+    sync: bool,
+    time_ms: f32,
+    note_value: f32,
+    feedback: f32,
+    ping_pong: bool,
+    mix: f32,
+    // Delay in frames the lines are currently read at, `None` jumps to the target
+    current_frames: Option<f32>,
+    current_mix: f32,
+    lines: [DelayLine; 2],
+}
+
+impl Delay {
+    pub const PARAMETERS: &'static [EffectParameter] = &[
+        EffectParameter {
+            id: "sync",
+            name: "Sync",
+            min: 0.0,
+            max: 1.0,
+            default: 1.0,
+            unit: "",
+            options: &["Off", "On"],
+        },
+        EffectParameter {
+            id: "time",
+            name: "Time",
+            min: 1.0,
+            max: MAX_DELAY_TIME * 1000.0,
+            default: 250.0,
+            unit: "ms",
+            options: &[],
+        },
+        EffectParameter {
+            id: "noteValue",
+            name: "Note value",
+            min: 0.0,
+            max: (NOTE_VALUES.len() - 1) as f32,
+            default: 5.0,
+            unit: "",
+            options: NOTE_VALUES,
+        },
+        EffectParameter {
+            id: "feedback",
+            name: "Feedback",
+            min: 0.0,
+            max: 95.0,
+            default: 35.0,
+            unit: "%",
+            options: &[],
+        },
+        EffectParameter {
+            id: "pingPong",
+            name: "Ping-pong",
+            min: 0.0,
+            max: 1.0,
+            default: 0.0,
+            unit: "",
+            options: &["Off", "On"],
+        },
+        EffectParameter {
+            id: "mix",
+            name: "Mix",
+            min: 0.0,
+            max: 100.0,
+            default: 35.0,
+            unit: "%",
+            options: &[],
+        },
+    ];
+
+    pub fn new() -> Self {
+        let parameter = |index: usize| Self::PARAMETERS[index].default;
+        let max_frames = (MAX_DELAY_TIME * EFFECT_SAMPLE_RATE_MAX as f32) as usize + 2;
+        Self {
+            sync: parameter(SYNC_INDEX) >= 0.5,
+            time_ms: parameter(TIME_INDEX),
+            note_value: parameter(NOTE_VALUE_INDEX),
+            feedback: parameter(FEEDBACK_INDEX) / 100.0,
+            ping_pong: parameter(PING_PONG_INDEX) >= 0.5,
+            mix: parameter(MIX_INDEX) / 100.0,
+            current_frames: None,
+            current_mix: parameter(MIX_INDEX) / 100.0,
+            lines: [DelayLine::new(max_frames), DelayLine::new(max_frames)],
+        }
+    }
+
+    /// Seconds until the repeats have faded by 60 dB at the given tempo.
+    pub fn tail_time(parameters: &[f32], tempo_bpm: f32) -> f64 {
+        let parameter = |index: usize| {
+            parameters
+                .get(index)
+                .copied()
+                .unwrap_or(Self::PARAMETERS[index].default)
+        };
+        let time = delay_time(
+            parameter(SYNC_INDEX) >= 0.5,
+            parameter(TIME_INDEX),
+            parameter(NOTE_VALUE_INDEX),
+            tempo_bpm,
+        ) as f64;
+        let feedback = parameter(FEEDBACK_INDEX) / 100.0;
+        let repeats = if feedback > 0.0 {
+            1.0 + 60.0 / -gain_to_db(feedback) as f64
+        } else {
+            1.0
+        };
+        (time * repeats).min(MAX_TAIL_TIME)
+    }
+}
+
+impl AudioEffect for Delay {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            SYNC_INDEX => self.sync = value >= 0.5,
+            TIME_INDEX => self.time_ms = value,
+            NOTE_VALUE_INDEX => self.note_value = value,
+            FEEDBACK_INDEX => self.feedback = value / 100.0,
+            PING_PONG_INDEX => self.ping_pong = value >= 0.5,
+            MIX_INDEX => self.mix = value / 100.0,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.current_frames = None;
+        self.current_mix = self.mix;
+        for line in self.lines.iter_mut() {
+            line.reset();
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let time = delay_time(self.sync, self.time_ms, self.note_value, context.tempo_bpm);
+        let target_frames = (time * context.sample_rate as f32).max(1.0);
+        let smoothing = parameter_smoothing_coefficient(context.sample_rate);
+        let mut current_frames = self.current_frames.unwrap_or(target_frames);
+
+        for frame in buffer.chunks_exact_mut(ENGINE_NUM_CHANNELS as usize) {
+            current_frames = follow(current_frames, target_frames, smoothing);
+            self.current_mix = follow(self.current_mix, self.mix, smoothing);
+            let wet = [
+                self.lines[0].read_fractional(current_frames),
+                self.lines[1].read_fractional(current_frames),
+            ];
+            if self.ping_pong {
+                self.lines[0].write((frame[0] + frame[1]) * 0.5 + wet[1] * self.feedback);
+                self.lines[1].write(wet[0] * self.feedback);
+            } else {
+                self.lines[0].write(frame[0] + wet[0] * self.feedback);
+                self.lines[1].write(frame[1] + wet[1] * self.feedback);
+            }
+            for (sample, wet_sample) in frame.iter_mut().zip(wet.iter()) {
+                *sample = *sample * (1.0 - self.current_mix) + wet_sample * self.current_mix;
+            }
+        }
+        self.current_frames = Some(current_frames);
+    }
+}
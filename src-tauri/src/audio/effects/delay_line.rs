@@ -0,0 +1,43 @@
+use crate::core::types::EngineSampleFormat;
+
+/// Ring buffer of past samples for one channel. Allocated up front and never grows,
+/// so delays can change length on the render path.
+pub struct DelayLine {
+    buffer: Vec<EngineSampleFormat>,
+    position: usize,
+}
+
+impl DelayLine {
+    pub fn new(max_delay: usize) -> Self {
+        Self {
+            buffer: vec![0.0; max_delay.max(2)],
+            position: 0,
+        }
+    }
+
+    /// Sample written `delay` writes ago, 1 being the latest one. Clamped to the line's length.
+    pub fn read(&self, delay: usize) -> EngineSampleFormat {
+        let len = self.buffer.len();
+        let delay = delay.clamp(1, len);
+        self.buffer[(self.position + len - delay) % len]
+    }
+
+    /// Like `read`, linearly interpolated between whole delays so the length can glide.
+    pub fn read_fractional(&self, delay: f32) -> EngineSampleFormat {
+        let delay = delay.clamp(1.0, (self.buffer.len() - 1) as f32);
+        let whole = delay as usize;
+        let fraction = delay - whole as f32;
+        let current = self.read(whole);
+        current + (self.read(whole + 1) - current) * fraction
+    }
+
+    pub fn write(&mut self, sample: EngineSampleFormat) {
+        self.buffer[self.position] = sample;
+        self.position = (self.position + 1) % self.buffer.len();
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.position = 0;
+    }
+}
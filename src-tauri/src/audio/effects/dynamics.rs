@@ -0,0 +1,34 @@
+use crate::{
+    audio::gain::gain_to_db,
+    core::{constants::EFFECT_PARAMETER_SMOOTHING_TIME, types::EngineSampleFormat},
+};
+
+/// Levels are floored here before converting to dB, about -120 dBFS.
+const MIN_LEVEL: f32 = 1e-6;
+
+/// Coefficient of a one-pole filter that covers about 63% of a step in `time_ms`.
+/// Zero jumps right away.
+pub fn time_coefficient(time_ms: f32, sample_rate: usize) -> f32 {
+    if time_ms <= 0.0 {
+        return 0.0;
+    }
+    (-1000.0 / (time_ms * sample_rate as f32)).exp()
+}
+
+/// Coefficient for gliding a parameter sample by sample.
+pub fn parameter_smoothing_coefficient(sample_rate: usize) -> f32 {
+    time_coefficient(EFFECT_PARAMETER_SMOOTHING_TIME as f32 * 1000.0, sample_rate)
+}
+
+/// Moves `value` towards `target` by one step of a one-pole filter.
+pub fn follow(value: f32, target: f32, coefficient: f32) -> f32 {
+    target + coefficient * (value - target)
+}
+
+/// Level of a stereo frame in dB, both channels linked so the image doesn't shift.
+pub fn frame_level_db(frame: &[EngineSampleFormat]) -> f32 {
+    let peak = frame
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    gain_to_db(peak.max(MIN_LEVEL))
+}
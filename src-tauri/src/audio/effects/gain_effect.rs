@@ -0,0 +1,50 @@
+use crate::{
+    audio::{
+        effects::audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+        gain::{apply_gain_ramp, db_to_gain},
+    },
+    core::types::EngineSampleFormat,
+};
+
+/// Trims the level of a track before its fader.
+pub struct GainEffect {
+    gain: f32,
+    // Gain applied to the previous block, ramped from to avoid clicks
+    previous_gain: f32,
+}
+
+impl GainEffect {
+    pub const PARAMETERS: &'static [EffectParameter] = &[EffectParameter {
+        id: "gain",
+        name: "Gain",
+        min: -24.0,
+        max: 24.0,
+        default: 0.0,
+        unit: "dB",
+        options: &[],
+    }];
+
+    pub fn new() -> Self {
+        Self {
+            gain: 1.0,
+            previous_gain: 1.0,
+        }
+    }
+}
+
+impl AudioEffect for GainEffect {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if index == 0 {
+            self.gain = db_to_gain(value);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.previous_gain = self.gain;
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], _context: &ProcessContext) {
+        apply_gain_ramp(buffer, [self.previous_gain; 2], [self.gain; 2]);
+        self.previous_gain = self.gain;
+    }
+}
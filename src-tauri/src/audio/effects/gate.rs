@@ -0,0 +1,169 @@
+use crate::{
+    audio::{
+        effects::{
+            audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+            dynamics::{follow, frame_level_db, time_coefficient},
+        },
+        gain::{db_to_gain, gain_to_db},
+    },
+    core::{constants::ENGINE_NUM_CHANNELS, types::EngineSampleFormat},
+};
+
+const THRESHOLD_INDEX: usize = 0;
+const RANGE_INDEX: usize = 1;
+const ATTACK_INDEX: usize = 2;
+const HOLD_INDEX: usize = 3;
+const RELEASE_INDEX: usize = 4;
+/// The gate closes this far below the threshold, so levels hovering around it don't chatter.
+const HYSTERESIS_DB: f32 = 4.0;
+/// Release of the level detector, long enough to ride over the zero crossings of low notes.
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+
+/// Noise gate that attenuates by `range` while the level stays below the threshold,
+/// both channels linked.
+pub struct Gate {
+    threshold_db: f32,
+    range_db: f32,
+    attack_ms: f32,
+    hold_ms: f32,
+    release_ms: f32,
+    // Detector and gain state, carried between blocks
+    level_db: f32,
+    is_open: bool,
+    hold_frames_left: usize,
+    gain: f32,
+    block_reduction_db: f32,
+}
+
+impl Gate {
+    pub const PARAMETERS: &'static [EffectParameter] = &[
+        EffectParameter {
+            id: "threshold",
+            name: "Threshold",
+            min: -80.0,
+            max: 0.0,
+            default: -40.0,
+            unit: "dB",
+            options: &[],
+        },
+        EffectParameter {
+            id: "range",
+            name: "Range",
+            min: -80.0,
+            max: 0.0,
+            default: -80.0,
+            unit: "dB",
+            options: &[],
+        },
+        EffectParameter {
+            id: "attack",
+            name: "Attack",
+            min: 0.1,
+            max: 50.0,
+            default: 1.0,
+            unit: "ms",
+            options: &[],
+        },
+        EffectParameter {
+            id: "hold",
+            name: "Hold",
+            min: 0.0,
+            max: 500.0,
+            default: 50.0,
+            unit: "ms",
+            options: &[],
+        },
+        EffectParameter {
+            id: "release",
+            name: "Release",
+            min: 5.0,
+            max: 2000.0,
+            default: 100.0,
+            unit: "ms",
+            options: &[],
+        },
+    ];
+
+    pub fn new() -> Self {
+        let parameter = |index: usize| Self::PARAMETERS[index].default;
+        Self {
+            threshold_db: parameter(THRESHOLD_INDEX),
+            range_db: parameter(RANGE_INDEX),
+            attack_ms: parameter(ATTACK_INDEX),
+            hold_ms: parameter(HOLD_INDEX),
+            release_ms: parameter(RELEASE_INDEX),
+            level_db: f32::NEG_INFINITY,
+            is_open: false,
+            hold_frames_left: 0,
+            gain: db_to_gain(parameter(RANGE_INDEX)),
+            block_reduction_db: 0.0,
+        }
+    }
+}
+
+impl AudioEffect for Gate {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            THRESHOLD_INDEX => self.threshold_db = value,
+            RANGE_INDEX => self.range_db = value,
+            ATTACK_INDEX => self.attack_ms = value,
+            HOLD_INDEX => self.hold_ms = value,
+            RELEASE_INDEX => self.release_ms = value,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.level_db = f32::NEG_INFINITY;
+        self.is_open = false;
+        self.hold_frames_left = 0;
+        self.gain = db_to_gain(self.range_db);
+        self.block_reduction_db = 0.0;
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let sample_rate = context.sample_rate;
+        let attack = time_coefficient(self.attack_ms, sample_rate);
+        let release = time_coefficient(self.release_ms, sample_rate);
+        let detector_release = time_coefficient(DETECTOR_RELEASE_MS, sample_rate);
+        let hold_frames = (self.hold_ms * sample_rate as f32 / 1000.0) as usize;
+        let closed_gain = db_to_gain(self.range_db);
+
+        self.block_reduction_db = 0.0;
+        for frame in buffer.chunks_exact_mut(ENGINE_NUM_CHANNELS as usize) {
+            let frame_level = frame_level_db(frame);
+            self.level_db = if frame_level > self.level_db {
+                frame_level
+            } else {
+                follow(self.level_db, frame_level, detector_release)
+            };
+
+            if self.level_db > self.threshold_db {
+                self.is_open = true;
+                self.hold_frames_left = hold_frames;
+            } else if self.is_open && self.level_db < self.threshold_db - HYSTERESIS_DB {
+                if self.hold_frames_left > 0 {
+                    self.hold_frames_left -= 1;
+                } else {
+                    self.is_open = false;
+                }
+            }
+
+            let (target, coefficient) = if self.is_open {
+                (1.0, attack)
+            } else {
+                (closed_gain, release)
+            };
+            self.gain = follow(self.gain, target, coefficient);
+            self.block_reduction_db = self.block_reduction_db.max(-gain_to_db(self.gain));
+
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+    }
+
+    fn gain_reduction_db(&self) -> Option<f32> {
+        Some(self.block_reduction_db)
+    }
+}
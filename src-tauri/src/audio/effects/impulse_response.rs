@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use realfft::{num_complex::Complex, RealFftPlanner};
+
+use crate::{
+    audio::{asset_pool::ASSET_POOL, decoder::decode_audio_file_channels, engine::AUDIO_ENGINE},
+    core::types::Id,
+};
+
+/// Frames per partition of the start of an impulse response, also the latency of convolving it.
+pub const HEAD_PARTITION_FRAMES: usize = 128;
+/// Frames per partition of the rest. Larger partitions cost less per frame. This part only starts
+/// `HEAD_FRAMES` into the response, so each partition can be worked on a slice per head block
+/// for a whole partition's worth of input and is still ready before it is heard.
+pub const TAIL_PARTITION_FRAMES: usize = 2048;
+/// Length of the start of a response that runs in head partitions.
+pub const HEAD_FRAMES: usize = 2 * TAIL_PARTITION_FRAMES;
+/// Longer responses are cut, mostly to keep a wrongly picked song from eating the CPU.
+const MAX_LENGTH_SECONDS: usize = 20;
+
+/// Convolution from one input to one output channel, a true-stereo response has four.
+pub struct ConvolutionPath {
+    pub input_channel: usize,
+    pub output_channel: usize,
+    /// Spectrum of every partition, zero-padded to twice its length and scaled for the inverse
+    /// transform.
+    pub partitions: Vec<Vec<Complex<f32>>>,
+}
+
+/// Part of an impulse response split into partitions of one size.
+pub struct PartitionedSegment {
+    pub partition_frames: usize,
+    pub paths: Vec<ConvolutionPath>,
+}
+
+impl PartitionedSegment {
+    fn new(
+        paths: &[(usize, usize, &[f32])],
+        partition_frames: usize,
+        planner: &mut RealFftPlanner<f32>,
+    ) -> Self {
+        let fft = planner.plan_fft_forward(partition_frames * 2);
+        let scale = 1.0 / fft.len() as f32;
+        let mut fft_input = fft.make_input_vec();
+        let paths = paths
+            .iter()
+            .map(|(input_channel, output_channel, samples)| ConvolutionPath {
+                input_channel: *input_channel,
+                output_channel: *output_channel,
+                partitions: samples
+                    .chunks(partition_frames)
+                    .map(|partition| {
+                        fft_input.fill(0.0);
+                        for (input, sample) in fft_input.iter_mut().zip(partition.iter()) {
+                            *input = sample * scale;
+                        }
+                        let mut spectrum = fft.make_output_vec();
+                        // Lengths always match the plan
+                        let _ = fft.process(&mut fft_input, &mut spectrum);
+                        spectrum
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self {
+            partition_frames,
+            paths,
+        }
+    }
+
+    pub fn num_partitions(&self) -> usize {
+        self.paths.first().map_or(0, |path| path.partitions.len())
+    }
+}
+
+/// Impulse response of a convolution reverb, resampled to the engine rate and transformed
+/// once so every effect using it shares the spectra.
+/// Mono and stereo responses convolve each channel on its own, true-stereo responses have
+/// four channels: left to left, left to right, right to left and right to right.
+pub struct ImpulseResponse {
+    pub sample_rate: usize,
+    pub len_frames: usize,
+    /// The first `HEAD_FRAMES` frames.
+    pub head: PartitionedSegment,
+    /// Everything after the head, `None` for short responses.
+    pub tail: Option<PartitionedSegment>,
+}
+
+impl ImpulseResponse {
+    /// Decodes the asset's file again to keep all of its channels. Blocking.
+    pub fn load(asset_id: &str) -> Result<Self> {
+        let file_path = ASSET_POOL
+            .audio
+            .get_file_path_by_id(asset_id)
+            .with_context(|| format!("Audio asset not found: {asset_id}"))?;
+        info!("Loading impulse response {file_path}");
+        let decoded = decode_audio_file_channels(&file_path)?;
+        Self::from_channels(decoded.channels, decoded.sample_rate)
+    }
+
+    /// Builds the response from one buffer per channel, normalized so the louder output
+    /// channel has unit energy and responses of any level sound about as loud.
+    fn from_channels(mut channels: Vec<Vec<f32>>, sample_rate: usize) -> Result<Self> {
+        // Input channel, output channel and channel of the file for every path
+        let routes: &[(usize, usize, usize)] = match channels.len() {
+            1 => &[(0, 0, 0), (1, 1, 0)],
+            2 => &[(0, 0, 0), (1, 1, 1)],
+            4 => &[(0, 0, 0), (0, 1, 1), (1, 0, 2), (1, 1, 3)],
+            num_channels => {
+                bail!("Impulse responses need 1, 2 or 4 channels, this one has {num_channels}")
+            }
+        };
+
+        let max_frames = MAX_LENGTH_SECONDS * sample_rate;
+        for channel in channels.iter_mut() {
+            channel.truncate(max_frames);
+        }
+        let len_frames = channels[0].len();
+
+        let mut output_energy = [0.0f32; 2];
+        for (_, output_channel, file_channel) in routes.iter() {
+            output_energy[*output_channel] += channels[*file_channel]
+                .iter()
+                .map(|sample| sample * sample)
+                .sum::<f32>();
+        }
+        let max_energy = output_energy[0].max(output_energy[1]);
+        if max_energy <= 0.0 {
+            bail!("The impulse response is silent");
+        }
+        let gain = 1.0 / max_energy.sqrt();
+        for channel in channels.iter_mut() {
+            for sample in channel.iter_mut() {
+                *sample *= gain;
+            }
+        }
+
+        let head_frames = len_frames.min(HEAD_FRAMES);
+        let mut planner = RealFftPlanner::new();
+        let segment_paths = |range: std::ops::Range<usize>| -> Vec<(usize, usize, &[f32])> {
+            routes
+                .iter()
+                .map(|(input_channel, output_channel, file_channel)| {
+                    (
+                        *input_channel,
+                        *output_channel,
+                        &channels[*file_channel][range.clone()],
+                    )
+                })
+                .collect()
+        };
+        let head = PartitionedSegment::new(
+            &segment_paths(0..head_frames),
+            HEAD_PARTITION_FRAMES,
+            &mut planner,
+        );
+        let tail = (len_frames > head_frames).then(|| {
+            PartitionedSegment::new(
+                &segment_paths(head_frames..len_frames),
+                TAIL_PARTITION_FRAMES,
+                &mut planner,
+            )
+        });
+
+        Ok(Self {
+            sample_rate,
+            len_frames,
+            head,
+            tail,
+        })
+    }
+}
+
+/// Impulse responses by asset id, shared by every convolution reverb using them.
+pub struct ImpulseResponses {
+    loaded: Mutex<HashMap<Id, Arc<ImpulseResponse>>>,
+}
+
+impl ImpulseResponses {
+    pub fn new() -> Self {
+        Self {
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Loads the response on first use and again after the engine sample rate changed.
+    /// Blocking, called off the render path.
+    pub fn get(&self, asset_id: &str) -> Result<Arc<ImpulseResponse>> {
+        let sample_rate = AUDIO_ENGINE.sample_rate();
+        if let Some(impulse_response) = self
+            .loaded
+            .lock()
+            .unwrap()
+            .get(asset_id)
+            .filter(|impulse_response| impulse_response.sample_rate == sample_rate)
+        {
+            return Ok(Arc::clone(impulse_response));
+        }
+
+        // Decoded without holding the lock, a second load of the same asset is only wasted work
+        let impulse_response = Arc::new(ImpulseResponse::load(asset_id)?);
+        self.loaded
+            .lock()
+            .unwrap()
+            .insert(asset_id.to_string(), Arc::clone(&impulse_response));
+        Ok(impulse_response)
+    }
+}
+
+pub static IMPULSE_RESPONSES: LazyLock<ImpulseResponses> =
+    LazyLock::new(|| ImpulseResponses::new());
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+
+use crate::{
+    audio::{
+        effects::{
+            audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+            dynamics::{follow, parameter_smoothing_coefficient, time_coefficient},
+        },
+        gain::{db_to_gain, gain_to_db},
+    },
+    core::{
+        constants::{EFFECT_SAMPLE_RATE_MAX, ENGINE_NUM_CHANNELS},
+        types::EngineSampleFormat,
+    },
+};
+
+const INPUT_GAIN_INDEX: usize = 0;
+const CEILING_INDEX: usize = 1;
+const RELEASE_INDEX: usize = 2;
+/// How far ahead the limiter sees peaks coming, in seconds. Also the latency it adds.
+const LOOK_AHEAD_TIME: f32 = 0.005;
+const MAX_LOOK_AHEAD_FRAMES: usize = (LOOK_AHEAD_TIME * EFFECT_SAMPLE_RATE_MAX as f32) as usize;
+
+fn look_ahead_frames(sample_rate: usize) -> usize {
+    ((LOOK_AHEAD_TIME * sample_rate as f32) as usize).clamp(1, MAX_LOOK_AHEAD_FRAMES)
+}
+
+/// Brickwall limiter that never lets a sample past the ceiling, both channels linked.
+/// The signal is delayed by the look-ahead so gain reduction can fade in before a peak arrives:
+/// the gain each frame needs is held at its minimum over the look-ahead window, released
+/// exponentially and then averaged over the window, which keeps it below what any frame in
+/// the window needs.
+pub struct Limiter {
+    input_gain_db: f32,
+    ceiling_db: f32,
+    release_ms: f32,
+    look_ahead_frames: usize,
+    current_input_gain: f32,
+    // Interleaved input frames waiting to be output, used up to `look_ahead_frames`
+    delay_line: Vec<EngineSampleFormat>,
+    // Gains entering the moving average, used up to `look_ahead_frames`
+    average_window: Vec<f32>,
+    average_sum: f64,
+    position: usize,
+    // Candidates for the minimum needed gain as frame number and gain, increasing in both
+    minimum_window: VecDeque<(usize, f32)>,
+    frame_number: usize,
+    released_gain: f32,
+    block_reduction_db: f32,
+}
+
+impl Limiter {
+    pub const PARAMETERS: &'static [EffectParameter] = &[
+        EffectParameter {
+            id: "inputGain",
+            name: "Input gain",
+            min: 0.0,
+            max: 24.0,
+            default: 0.0,
+            unit: "dB",
+            options: &[],
+        },
+        EffectParameter {
+            id: "ceiling",
+            name: "Ceiling",
+            min: -24.0,
+            max: 0.0,
+            default: -0.3,
+            unit: "dB",
+            options: &[],
+        },
+        EffectParameter {
+            id: "release",
+            name: "Release",
+            min: 1.0,
+            max: 1000.0,
+            default: 100.0,
+            unit: "ms",
+            options: &[],
+        },
+    ];
+
+    /// `sample_rate` sizes the look-ahead, which is also the latency reported before the first
+    /// block is processed.
+    pub fn new(sample_rate: usize) -> Self {
+        let parameter = |index: usize| Self::PARAMETERS[index].default;
+        let mut limiter = Self {
+            input_gain_db: parameter(INPUT_GAIN_INDEX),
+            ceiling_db: parameter(CEILING_INDEX),
+            release_ms: parameter(RELEASE_INDEX),
+            look_ahead_frames: look_ahead_frames(sample_rate),
+            current_input_gain: db_to_gain(parameter(INPUT_GAIN_INDEX)),
+            delay_line: vec![0.0; MAX_LOOK_AHEAD_FRAMES * ENGINE_NUM_CHANNELS as usize],
+            average_window: vec![1.0; MAX_LOOK_AHEAD_FRAMES],
+            average_sum: 0.0,
+            position: 0,
+            minimum_window: VecDeque::with_capacity(MAX_LOOK_AHEAD_FRAMES + 1),
+            frame_number: 0,
+            released_gain: 1.0,
+            block_reduction_db: 0.0,
+        };
+        limiter.reset();
+        limiter
+    }
+}
+
+impl AudioEffect for Limiter {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            INPUT_GAIN_INDEX => self.input_gain_db = value,
+            CEILING_INDEX => self.ceiling_db = value,
+            RELEASE_INDEX => self.release_ms = value,
+            _ => {}
+        }
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.look_ahead_frames
+    }
+
+    fn reset(&mut self) {
+        self.current_input_gain = db_to_gain(self.input_gain_db);
+        self.delay_line.fill(0.0);
+        self.average_window.fill(1.0);
+        self.average_sum = self.look_ahead_frames as f64;
+        self.position = 0;
+        self.minimum_window.clear();
+        self.frame_number = 0;
+        self.released_gain = 1.0;
+        self.block_reduction_db = 0.0;
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let sample_rate = context.sample_rate;
+        let look_ahead_frames = look_ahead_frames(sample_rate);
+        if look_ahead_frames != self.look_ahead_frames {
+            self.look_ahead_frames = look_ahead_frames;
+            self.reset();
+        }
+        let num_channels = ENGINE_NUM_CHANNELS as usize;
+        let ceiling = db_to_gain(self.ceiling_db);
+        let input_gain = db_to_gain(self.input_gain_db);
+        let release = time_coefficient(self.release_ms, sample_rate);
+        let smoothing = parameter_smoothing_coefficient(sample_rate);
+
+        self.block_reduction_db = 0.0;
+        for frame in buffer.chunks_exact_mut(num_channels) {
+            self.current_input_gain = follow(self.current_input_gain, input_gain, smoothing);
+            let mut peak = 0.0f32;
+            for sample in frame.iter_mut() {
+                *sample *= self.current_input_gain;
+                peak = peak.max(sample.abs());
+            }
+            let needed_gain = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+            // Minimum over this frame and the `look_ahead_frames` before it
+            while self
+                .minimum_window
+                .back()
+                .is_some_and(|(_, gain)| *gain >= needed_gain)
+            {
+                self.minimum_window.pop_back();
+            }
+            self.minimum_window
+                .push_back((self.frame_number, needed_gain));
+            while self
+                .minimum_window
+                .front()
+                .is_some_and(|(frame_number, _)| {
+                    frame_number + look_ahead_frames < self.frame_number
+                })
+            {
+                self.minimum_window.pop_front();
+            }
+            self.frame_number += 1;
+            let held_gain = self.minimum_window.front().map_or(1.0, |(_, gain)| *gain);
+
+            // Instant attack keeps the bound, only recovering is smoothed
+            self.released_gain = if held_gain < self.released_gain {
+                held_gain
+            } else {
+                follow(self.released_gain, held_gain, release)
+            };
+
+            self.average_sum += (self.released_gain - self.average_window[self.position]) as f64;
+            self.average_window[self.position] = self.released_gain;
+            let gain = (self.average_sum / look_ahead_frames as f64) as f32;
+            self.block_reduction_db = self.block_reduction_db.max(-gain_to_db(gain));
+
+            let delayed = &mut self.delay_line
+                [self.position * num_channels..(self.position + 1) * num_channels];
+            for (sample, delayed_sample) in frame.iter_mut().zip(delayed.iter_mut()) {
+                let output = (*delayed_sample * gain).clamp(-ceiling, ceiling);
+                *delayed_sample = *sample;
+                *sample = output;
+            }
+            self.position = (self.position + 1) % look_ahead_frames;
+        }
+    }
+
+    fn gain_reduction_db(&self) -> Option<f32> {
+        Some(self.block_reduction_db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 48000;
+
+    fn context() -> ProcessContext {
+        ProcessContext {
+            sample_rate: SAMPLE_RATE,
+            tempo_bpm: 120.0,
+        }
+    }
+
+    fn sine(num_frames: usize, amplitude: f32) -> Vec<EngineSampleFormat> {
+        (0..num_frames)
+            .flat_map(|frame| {
+                let sample = amplitude * (frame as f32 * 0.05).sin();
+                [sample, -sample]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn never_exceeds_ceiling() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        limiter.set_parameter(INPUT_GAIN_INDEX, 12.0);
+        limiter.set_parameter(CEILING_INDEX, -6.0);
+        limiter.reset();
+        let ceiling = db_to_gain(-6.0);
+
+        let mut buffer = sine(4096, 1.0);
+        for block in buffer.chunks_mut(256) {
+            limiter.process(block, &context());
+        }
+        assert!(buffer.iter().all(|sample| sample.abs() <= ceiling));
+        assert!(limiter.gain_reduction_db().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn passes_quiet_signal_delayed_by_look_ahead() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let input = sine(1024, 0.5);
+        let mut buffer = input.clone();
+        limiter.process(&mut buffer, &context());
+
+        let latency = look_ahead_frames(SAMPLE_RATE) * ENGINE_NUM_CHANNELS as usize;
+        assert!(buffer[..latency].iter().all(|sample| *sample == 0.0));
+        assert_eq!(&buffer[latency..], &input[..input.len() - latency]);
+        assert_eq!(limiter.gain_reduction_db(), Some(0.0));
+    }
+}
@@ -0,0 +1,13 @@
+pub mod audio_effect;
+pub mod biquad;
+pub mod compressor;
+pub mod convolution_reverb;
+pub mod delay;
+pub mod delay_line;
+pub mod dynamics;
+pub mod gain_effect;
+pub mod gate;
+pub mod impulse_response;
+pub mod limiter;
+pub mod parametric_eq;
+pub mod reverb;
@@ -0,0 +1,469 @@
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use crate::{
+    audio::{
+        effects::{
+            audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+            biquad::{BiquadCoefficients, BiquadState},
+        },
+        gain::{apply_gain_ramp, db_to_gain},
+    },
+    core::{
+        constants::{EFFECT_PARAMETER_SMOOTHING_TIME, ENGINE_NUM_CHANNELS},
+        types::EngineSampleFormat,
+    },
+};
+
+const NUM_BANDS: usize = 8;
+const PARAMETERS_PER_BAND: usize = 6;
+const OUTPUT_GAIN_INDEX: usize = NUM_BANDS * PARAMETERS_PER_BAND;
+/// Cut filters go up to 48 dB/oct, four second order stages.
+const MAX_STAGES: usize = 4;
+/// Frames between coefficient updates while parameters glide.
+const SMOOTHING_BLOCK_FRAMES: usize = 32;
+
+const BAND_TYPES: &[&str] = &[
+    "Low cut",
+    "Low shelf",
+    "Peak",
+    "Notch",
+    "High shelf",
+    "High cut",
+];
+const SLOPES: &[&str] = &["12 dB/oct", "24 dB/oct", "36 dB/oct", "48 dB/oct"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BandType {
+    LowCut,
+    LowShelf,
+    Peak,
+    Notch,
+    HighShelf,
+    HighCut,
+}
+
+impl BandType {
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => BandType::LowCut,
+            1 => BandType::LowShelf,
+            3 => BandType::Notch,
+            4 => BandType::HighShelf,
+            5 => BandType::HighCut,
+            _ => BandType::Peak,
+        }
+    }
+}
+
+macro_rules! eq_parameters {
+    ($(($band:literal, $band_type:literal, $frequency:literal)),* $(,)?) => {
+        &[
+            $(
+                EffectParameter {
+                    id: concat!("band", $band, "Enabled"),
+                    name: concat!("Band ", $band),
+                    min: 0.0,
+                    max: 1.0,
+                    default: 0.0,
+                    unit: "",
+                    options: &["Off", "On"],
+                },
+                EffectParameter {
+                    id: concat!("band", $band, "Type"),
+                    name: concat!("Band ", $band, " type"),
+                    min: 0.0,
+                    max: 5.0,
+                    default: $band_type,
+                    unit: "",
+                    options: BAND_TYPES,
+                },
+                EffectParameter {
+                    id: concat!("band", $band, "Frequency"),
+                    name: concat!("Band ", $band, " frequency"),
+                    min: 20.0,
+                    max: 20000.0,
+                    default: $frequency,
+                    unit: "Hz",
+                    options: &[],
+                },
+                EffectParameter {
+                    id: concat!("band", $band, "Gain"),
+                    name: concat!("Band ", $band, " gain"),
+                    min: -24.0,
+                    max: 24.0,
+                    default: 0.0,
+                    unit: "dB",
+                    options: &[],
+                },
+                EffectParameter {
+                    id: concat!("band", $band, "Q"),
+                    name: concat!("Band ", $band, " Q"),
+                    min: 0.1,
+                    max: 18.0,
+                    default: 0.707,
+                    unit: "",
+                    options: &[],
+                },
+                EffectParameter {
+                    id: concat!("band", $band, "Slope"),
+                    name: concat!("Band ", $band, " slope"),
+                    min: 0.0,
+                    max: 3.0,
+                    default: 0.0,
+                    unit: "",
+                    options: SLOPES,
+                },
+            )*
+            EffectParameter {
+                id: "outputGain",
+                name: "Output gain",
+                min: -24.0,
+                max: 24.0,
+                default: 0.0,
+                unit: "dB",
+                options: &[],
+            },
+        ]
+    };
+}
+
+/// Values of the parameters that glide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BandSettings {
+    frequency: f64,
+    gain_db: f64,
+    q: f64,
+}
+
+impl BandSettings {
+    /// Moves `coefficient` of the way towards `target`, the frequency on a log scale.
+    /// Snaps once close enough so settled bands stop recomputing coefficients.
+    fn glide_to(&mut self, target: &BandSettings, coefficient: f64) {
+        let log_frequency = self.frequency.ln();
+        let next = BandSettings {
+            frequency: (log_frequency + (target.frequency.ln() - log_frequency) * coefficient)
+                .exp(),
+            gain_db: self.gain_db + (target.gain_db - self.gain_db) * coefficient,
+            q: self.q + (target.q - self.q) * coefficient,
+        };
+        let is_settled = (next.frequency / target.frequency - 1.0).abs() < 1e-4
+            && (next.gain_db - target.gain_db).abs() < 1e-3
+            && (next.q - target.q).abs() < 1e-4;
+        *self = if is_settled { *target } else { next };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BandFilter {
+    stages: [BiquadCoefficients; MAX_STAGES],
+    num_stages: usize,
+}
+
+impl BandFilter {
+    fn new(band_type: BandType, num_cut_stages: usize, settings: &BandSettings, sr: f64) -> Self {
+        let BandSettings {
+            frequency,
+            gain_db,
+            q,
+        } = *settings;
+        let mut stages = [BiquadCoefficients::IDENTITY; MAX_STAGES];
+        let num_stages = match band_type {
+            BandType::LowCut | BandType::HighCut => {
+                // Butterworth stages, the one with the highest Q also carries the band's resonance
+                for (stage, coefficients) in stages.iter_mut().take(num_cut_stages).enumerate() {
+                    let angle = (2 * stage + 1) as f64 * PI / (4 * num_cut_stages) as f64;
+                    let mut stage_q = 1.0 / (2.0 * angle.cos());
+                    if stage == num_cut_stages - 1 {
+                        stage_q *= q / FRAC_1_SQRT_2;
+                    }
+                    *coefficients = if band_type == BandType::LowCut {
+                        BiquadCoefficients::high_pass(sr, frequency, stage_q)
+                    } else {
+                        BiquadCoefficients::low_pass(sr, frequency, stage_q)
+                    };
+                }
+                num_cut_stages
+            }
+            BandType::LowShelf => {
+                stages[0] = BiquadCoefficients::low_shelf(sr, frequency, q, gain_db);
+                1
+            }
+            BandType::Peak => {
+                stages[0] = BiquadCoefficients::peak(sr, frequency, q, gain_db);
+                1
+            }
+            BandType::Notch => {
+                stages[0] = BiquadCoefficients::notch(sr, frequency, q);
+                1
+            }
+            BandType::HighShelf => {
+                stages[0] = BiquadCoefficients::high_shelf(sr, frequency, q, gain_db);
+                1
+            }
+        };
+        Self { stages, num_stages }
+    }
+
+    fn stages(&self) -> &[BiquadCoefficients] {
+        &self.stages[..self.num_stages]
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Band {
+    enabled: bool,
+    band_type: BandType,
+    num_cut_stages: usize,
+    // Type and slope the filter runs with, they follow the targets while the band is faded out
+    active_type: BandType,
+    active_cut_stages: usize,
+    target: BandSettings,
+    current: BandSettings,
+    // How much of the filtered signal is heard, from 0 for bypassed to 1
+    mix: f64,
+    filter: BandFilter,
+    // Filter memory by channel and stage
+    states: [[BiquadState; MAX_STAGES]; 2],
+    needs_update: bool,
+}
+
+impl Band {
+    /// Band as configured by `parameters`, without any smoothing. Missing parameters take their
+    /// default.
+    fn from_parameters(parameters: &[f32], band_index: usize) -> Self {
+        let offset = band_index * PARAMETERS_PER_BAND;
+        let parameter = |index: usize| {
+            parameters
+                .get(offset + index)
+                .copied()
+                .unwrap_or(ParametricEq::PARAMETERS[offset + index].default)
+        };
+        let target = BandSettings {
+            frequency: parameter(2) as f64,
+            gain_db: parameter(3) as f64,
+            q: parameter(4) as f64,
+        };
+        let enabled = parameter(0) >= 0.5;
+        let band_type = BandType::from_index(parameter(1).round() as usize);
+        let num_cut_stages = (parameter(5).round() as usize + 1).min(MAX_STAGES);
+        Self {
+            enabled,
+            band_type,
+            num_cut_stages,
+            active_type: band_type,
+            active_cut_stages: num_cut_stages,
+            target,
+            current: target,
+            mix: if enabled { 1.0 } else { 0.0 },
+            filter: BandFilter {
+                stages: [BiquadCoefficients::IDENTITY; MAX_STAGES],
+                num_stages: 0,
+            },
+            states: [[BiquadState::default(); MAX_STAGES]; 2],
+            needs_update: true,
+        }
+    }
+
+    /// Whether the filter runs what the parameters ask for, otherwise it fades out first.
+    fn is_switched(&self) -> bool {
+        self.active_type == self.band_type && self.active_cut_stages == self.num_cut_stages
+    }
+
+    /// Takes over the target type and slope with clear filter memory, once the band is silent.
+    fn switch_filter(&mut self) {
+        self.active_type = self.band_type;
+        self.active_cut_stages = self.num_cut_stages;
+        self.needs_update = true;
+        for state in self.states.iter_mut().flatten() {
+            state.reset();
+        }
+    }
+
+    fn update_filter(&mut self, sample_rate: f64) {
+        self.filter = BandFilter::new(
+            self.active_type,
+            self.active_cut_stages,
+            &self.current,
+            sample_rate,
+        );
+        self.needs_update = false;
+    }
+}
+
+/// Eight band parametric EQ. Frequency, gain and Q glide to new values. Switching a band on or
+/// off fades it in or out, and a new type or slope is crossfaded through the dry signal.
+pub struct ParametricEq {
+    bands: [Band; NUM_BANDS],
+    output_gain: f32,
+    previous_output_gain: f32,
+    sample_rate: usize,
+}
+
+impl ParametricEq {
+    pub const PARAMETERS: &'static [EffectParameter] = eq_parameters![
+        (1, 0.0, 30.0),
+        (2, 1.0, 100.0),
+        (3, 2.0, 250.0),
+        (4, 2.0, 500.0),
+        (5, 2.0, 1000.0),
+        (6, 2.0, 2500.0),
+        (7, 4.0, 6000.0),
+        (8, 5.0, 16000.0),
+    ];
+
+    pub fn new() -> Self {
+        let parameters: Vec<f32> = Self::PARAMETERS
+            .iter()
+            .map(|parameter| parameter.default)
+            .collect();
+        Self {
+            bands: std::array::from_fn(|band_index| Band::from_parameters(&parameters, band_index)),
+            output_gain: 1.0,
+            previous_output_gain: 1.0,
+            sample_rate: 0,
+        }
+    }
+
+    /// Combined gain in dB of every enabled band and the output gain.
+    pub fn frequency_response(
+        parameters: &[f32],
+        sample_rate: usize,
+        frequencies: &[f64],
+    ) -> Vec<f32> {
+        let sample_rate = sample_rate as f64;
+        let filters: Vec<BandFilter> = (0..NUM_BANDS)
+            .map(|band_index| Band::from_parameters(parameters, band_index))
+            .filter(|band| band.enabled)
+            .map(|band| {
+                BandFilter::new(
+                    band.band_type,
+                    band.num_cut_stages,
+                    &band.target,
+                    sample_rate,
+                )
+            })
+            .collect();
+        let output_gain_db = parameters
+            .get(OUTPUT_GAIN_INDEX)
+            .copied()
+            .unwrap_or(Self::PARAMETERS[OUTPUT_GAIN_INDEX].default);
+        frequencies
+            .iter()
+            .map(|frequency| {
+                let magnitude: f64 = filters
+                    .iter()
+                    .flat_map(|filter| filter.stages())
+                    .map(|stage| stage.magnitude(sample_rate, *frequency))
+                    .product();
+                20.0 * magnitude.max(1e-12).log10() as f32 + output_gain_db
+            })
+            .collect()
+    }
+}
+
+impl AudioEffect for ParametricEq {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if index == OUTPUT_GAIN_INDEX {
+            self.output_gain = db_to_gain(value);
+            return;
+        }
+        let Some(band) = self.bands.get_mut(index / PARAMETERS_PER_BAND) else {
+            return;
+        };
+        let value = value as f64;
+        match index % PARAMETERS_PER_BAND {
+            0 => band.enabled = value >= 0.5,
+            1 => band.band_type = BandType::from_index(value.round() as usize),
+            2 => band.target.frequency = value,
+            3 => band.target.gain_db = value,
+            4 => band.target.q = value,
+            _ => band.num_cut_stages = (value.round() as usize + 1).min(MAX_STAGES),
+        }
+    }
+
+    fn reset(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.current = band.target;
+            band.mix = if band.enabled { 1.0 } else { 0.0 };
+            band.switch_filter();
+        }
+        self.previous_output_gain = self.output_gain;
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let sample_rate = context.sample_rate;
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            for band in self.bands.iter_mut() {
+                band.needs_update = true;
+            }
+        }
+        let sample_rate = sample_rate as f64;
+        let smoothing = 1.0
+            - (-(SMOOTHING_BLOCK_FRAMES as f64) / (EFFECT_PARAMETER_SMOOTHING_TIME * sample_rate))
+                .exp();
+        // Fades run linearly over the smoothing time
+        let mix_step =
+            SMOOTHING_BLOCK_FRAMES as f64 / (EFFECT_PARAMETER_SMOOTHING_TIME * sample_rate);
+
+        let num_channels = ENGINE_NUM_CHANNELS as usize;
+        for block in buffer.chunks_mut(SMOOTHING_BLOCK_FRAMES * num_channels) {
+            for band in self.bands.iter_mut() {
+                if band.mix == 0.0 && !band.is_switched() {
+                    band.switch_filter();
+                }
+                let target_mix = if band.enabled && band.is_switched() {
+                    1.0
+                } else {
+                    0.0
+                };
+                let start_mix = band.mix;
+                band.mix = if target_mix > start_mix {
+                    (start_mix + mix_step).min(target_mix)
+                } else {
+                    (start_mix - mix_step).max(target_mix)
+                };
+                if start_mix == 0.0 && band.mix == 0.0 {
+                    // Silent bands skip the glide
+                    if band.current != band.target {
+                        band.current = band.target;
+                        band.needs_update = true;
+                    }
+                    continue;
+                }
+
+                if band.current != band.target {
+                    band.current.glide_to(&band.target, smoothing);
+                    band.needs_update = true;
+                }
+                if band.needs_update {
+                    band.update_filter(sample_rate);
+                }
+                let num_frames = block.len() / num_channels;
+                let mix_increment = (band.mix - start_mix) / num_frames as f64;
+                for (frame_index, frame) in block.chunks_exact_mut(num_channels).enumerate() {
+                    let mix = start_mix + mix_increment * (frame_index + 1) as f64;
+                    for (sample, states) in frame.iter_mut().zip(band.states.iter_mut()) {
+                        let dry = *sample as f64;
+                        let mut value = dry;
+                        for (coefficients, state) in band.filter.stages().iter().zip(states) {
+                            value = state.process(coefficients, value);
+                        }
+                        *sample = (dry + (value - dry) * mix) as EngineSampleFormat;
+                    }
+                }
+                // Faded out, so the next fade in starts from clear filter memory
+                if band.mix == 0.0 {
+                    band.switch_filter();
+                }
+            }
+        }
+
+        apply_gain_ramp(
+            buffer,
+            [self.previous_output_gain; 2],
+            [self.output_gain; 2],
+        );
+        self.previous_output_gain = self.output_gain;
+    }
+}
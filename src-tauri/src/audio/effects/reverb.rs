@@ -0,0 +1,265 @@
+use crate::{
+    audio::effects::{
+        audio_effect::{AudioEffect, EffectParameter, ProcessContext},
+        delay_line::DelayLine,
+        dynamics::{follow, parameter_smoothing_coefficient},
+    },
+    core::{
+        constants::{EFFECT_SAMPLE_RATE_MAX, ENGINE_NUM_CHANNELS},
+        types::EngineSampleFormat,
+    },
+};
+
+const SIZE_INDEX: usize = 0;
+const DECAY_INDEX: usize = 1;
+const PRE_DELAY_INDEX: usize = 2;
+const DAMPING_INDEX: usize = 3;
+const MIX_INDEX: usize = 4;
+
+/// Freeverb tuning, in frames at `TUNING_SAMPLE_RATE`.
+const TUNING_SAMPLE_RATE: f32 = 44100.0;
+const COMB_LENGTHS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_LENGTHS: [usize; 4] = [556, 441, 341, 225];
+/// Added to the right channel's lengths so the channels decorrelate.
+const STEREO_SPREAD: usize = 23;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.0;
+/// Damping only goes this far, beyond it the tail turns into a dull thud.
+const MAX_DAMPING: f32 = 0.4;
+/// Delay lengths are scaled from `MIN_SCALE` at size 0% to `MAX_SCALE` at size 100%.
+const MIN_SCALE: f32 = 0.4;
+const MAX_SCALE: f32 = 1.6;
+const MAX_PRE_DELAY_MS: f32 = 250.0;
+
+fn max_frames(tuning_frames: usize) -> usize {
+    ((tuning_frames + STEREO_SPREAD) as f32 * MAX_SCALE * EFFECT_SAMPLE_RATE_MAX as f32
+        / TUNING_SAMPLE_RATE) as usize
+        + 1
+}
+
+/// Feedback comb filter with a low pass in its loop.
+struct Comb {
+    line: DelayLine,
+    filter_state: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, length: usize, feedback: f32, damping: f32) -> f32 {
+        let output = self.line.read(length);
+        self.filter_state = output * (1.0 - damping) + self.filter_state * damping;
+        self.line.write(input + self.filter_state * feedback);
+        output
+    }
+}
+
+fn allpass(line: &mut DelayLine, input: f32, length: usize) -> f32 {
+    let buffered = line.read(length);
+    line.write(input + buffered * ALLPASS_FEEDBACK);
+    buffered - input
+}
+
+/// One channel of the reverb, its lengths offset for the right channel.
+struct Channel {
+    combs: Vec<Comb>,
+    allpasses: Vec<DelayLine>,
+    spread: usize,
+}
+
+impl Channel {
+    fn new(spread: usize) -> Self {
+        Self {
+            combs: COMB_LENGTHS
+                .iter()
+                .map(|length| Comb {
+                    line: DelayLine::new(max_frames(*length)),
+                    filter_state: 0.0,
+                })
+                .collect(),
+            allpasses: ALLPASS_LENGTHS
+                .iter()
+                .map(|length| DelayLine::new(max_frames(*length)))
+                .collect(),
+            spread,
+        }
+    }
+
+    fn reset(&mut self) {
+        for comb in self.combs.iter_mut() {
+            comb.line.reset();
+            comb.filter_state = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut() {
+            allpass.reset();
+        }
+    }
+}
+
+/// Stereo algorithmic reverb after Freeverb: parallel damped combs into series allpasses.
+/// Decay is the time the tail takes to fall by 60 dB, independent of the size.
+/// The mix defaults to fully wet for send-return buses.
+pub struct Reverb {
+    size: f32,
+    decay: f32,
+    pre_delay_ms: f32,
+    damping: f32,
+    mix: f32,
+    current_mix: f32,
+    // Both channels are fed the same mono input, so one pre-delay serves them
+    pre_delay: DelayLine,
+    channels: [Channel; 2],
+}
+
+impl Reverb {
+    pub const PARAMETERS: &'static [EffectParameter] = &[
+        EffectParameter {
+            id: "size",
+            name: "Size",
+            min: 0.0,
+            max: 100.0,
+            default: 50.0,
+            unit: "%",
+            options: &[],
+        },
+        EffectParameter {
+            id: "decay",
+            name: "Decay",
+            min: 0.1,
+            max: 20.0,
+            default: 2.0,
+            unit: "s",
+            options: &[],
+        },
+        EffectParameter {
+            id: "preDelay",
+            name: "Pre-delay",
+            min: 0.0,
+            max: MAX_PRE_DELAY_MS,
+            default: 20.0,
+            unit: "ms",
+            options: &[],
+        },
+        EffectParameter {
+            id: "damping",
+            name: "Damping",
+            min: 0.0,
+            max: 100.0,
+            default: 50.0,
+            unit: "%",
+            options: &[],
+        },
+        EffectParameter {
+            id: "mix",
+            name: "Mix",
+            min: 0.0,
+            max: 100.0,
+            default: 100.0,
+            unit: "%",
+            options: &[],
+        },
+    ];
+
+    pub fn new() -> Self {
+        let parameter = |index: usize| Self::PARAMETERS[index].default;
+        let max_pre_delay =
+            (MAX_PRE_DELAY_MS / 1000.0 * EFFECT_SAMPLE_RATE_MAX as f32) as usize + 1;
+        Self {
+            size: parameter(SIZE_INDEX) / 100.0,
+            decay: parameter(DECAY_INDEX),
+            pre_delay_ms: parameter(PRE_DELAY_INDEX),
+            damping: parameter(DAMPING_INDEX) / 100.0,
+            mix: parameter(MIX_INDEX) / 100.0,
+            current_mix: parameter(MIX_INDEX) / 100.0,
+            pre_delay: DelayLine::new(max_pre_delay),
+            channels: [Channel::new(0), Channel::new(STEREO_SPREAD)],
+        }
+    }
+
+    /// Seconds the reverb keeps sounding after its input stops.
+    pub fn tail_time(parameters: &[f32]) -> f64 {
+        let parameter = |index: usize| {
+            parameters
+                .get(index)
+                .copied()
+                .unwrap_or(Self::PARAMETERS[index].default) as f64
+        };
+        parameter(DECAY_INDEX) + parameter(PRE_DELAY_INDEX) / 1000.0
+    }
+}
+
+impl AudioEffect for Reverb {
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            SIZE_INDEX => self.size = value / 100.0,
+            DECAY_INDEX => self.decay = value,
+            PRE_DELAY_INDEX => self.pre_delay_ms = value,
+            DAMPING_INDEX => self.damping = value / 100.0,
+            MIX_INDEX => self.mix = value / 100.0,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.current_mix = self.mix;
+        self.pre_delay.reset();
+        for channel in self.channels.iter_mut() {
+            channel.reset();
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        let sample_rate = context.sample_rate as f32;
+        let scale =
+            (MIN_SCALE + (MAX_SCALE - MIN_SCALE) * self.size) * sample_rate / TUNING_SAMPLE_RATE;
+        let pre_delay_frames = (self.pre_delay_ms / 1000.0 * sample_rate) as usize;
+        let damping = self.damping * MAX_DAMPING;
+        let smoothing = parameter_smoothing_coefficient(context.sample_rate);
+
+        // Lengths and feedbacks only change with the parameters, so they are set per block
+        let mut comb_settings = [[(0, 0.0); COMB_LENGTHS.len()]; 2];
+        let mut allpass_lengths = [[0; ALLPASS_LENGTHS.len()]; 2];
+        for (channel_index, channel) in self.channels.iter().enumerate() {
+            for (setting, tuning_length) in comb_settings[channel_index]
+                .iter_mut()
+                .zip(COMB_LENGTHS.iter())
+            {
+                let length = (((tuning_length + channel.spread) as f32 * scale) as usize).max(1);
+                // Loses 60 dB over `decay` seconds
+                let feedback = 10f32.powf(-3.0 * length as f32 / (self.decay * sample_rate));
+                *setting = (length, feedback);
+            }
+            for (length, tuning_length) in allpass_lengths[channel_index]
+                .iter_mut()
+                .zip(ALLPASS_LENGTHS.iter())
+            {
+                *length = (((tuning_length + channel.spread) as f32 * scale) as usize).max(1);
+            }
+        }
+
+        for frame in buffer.chunks_exact_mut(ENGINE_NUM_CHANNELS as usize) {
+            self.current_mix = follow(self.current_mix, self.mix, smoothing);
+            self.pre_delay.write((frame[0] + frame[1]) * INPUT_GAIN);
+            let delayed = self.pre_delay.read(pre_delay_frames + 1);
+            for (channel_index, sample) in frame.iter_mut().enumerate() {
+                let channel = &mut self.channels[channel_index];
+                let mut wet = 0.0;
+                for (comb, (length, feedback)) in channel
+                    .combs
+                    .iter_mut()
+                    .zip(comb_settings[channel_index].iter())
+                {
+                    wet += comb.process(delayed, *length, *feedback, damping);
+                }
+                for (line, length) in channel
+                    .allpasses
+                    .iter_mut()
+                    .zip(allpass_lengths[channel_index].iter())
+                {
+                    wet = allpass(line, wet, *length);
+                }
+
+                *sample = *sample * (1.0 - self.current_mix) + wet * WET_GAIN * self.current_mix;
+            }
+        }
+    }
+}
@@ -1,51 +1,91 @@
-use crate::core::constants::{BUFFER_SIZE_DEFAULT, ENGINE_NUM_CHANNELS};
+use crate::audio::device_watcher::DEVICE_WATCHER;
+use crate::audio::engine_stats::{XrunKind, ENGINE_STATS};
+use crate::audio::null_output::{NullStream, OutputCapture, OutputCaptureTarget};
+use crate::audio::render_thread::RENDER_THREAD;
+use crate::audio::transport::TRANSPORT;
+use crate::core::constants::{
+    BUFFER_SIZE_DEFAULT, ENGINE_NUM_CHANNELS, NULL_HOST_ID, NULL_OUTPUT_BUFFER_SIZE_MAX,
+    NULL_OUTPUT_BUFFER_SIZE_MIN, NULL_OUTPUT_SAMPLE_RATE_DEFAULT,
+};
+use crate::core::settings::AudioSettings;
 use crate::core::types::EngineSampleFormat;
 
+use anyhow::{anyhow, Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{
-    available_hosts, Device, FromSample, Host, Sample, SampleFormat, SizedSample, Stream,
-    StreamConfig,
+    available_hosts, Device, DeviceId, FromSample, Host, HostId, Sample, SampleFormat, SizedSample,
+    Stream, StreamConfig, StreamError, SupportedStreamConfig,
 };
-use log::{error, info};
+use log::{error, info, warn};
 use ringbuf::traits::{Consumer, Split};
 use ringbuf::{HeapCons, HeapProd, HeapRb};
+use serde::Serialize;
 use std::fmt::{Debug, Display};
-use std::sync::{LazyLock, Mutex};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 
-pub struct AudioEngine {
-    host: Host,
-    device: Device,
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDeviceInfo {
+    pub host_id: String,
+    pub device_id: Option<String>,
+    pub device_name: String,
+    pub sample_rate: usize,
+    pub num_channels: usize,
+    pub buffer_size: usize,
+}
+
+enum OutputBackend {
+    Cpal {
+        host: Host,
+        device: Device,
+        sample_format: SampleFormat,
+    },
+    Null,
+}
+
+// Streams are only held so they keep running until they are dropped.
+#[allow(dead_code)]
+enum OutputStream {
+    Cpal(Stream),
+    Null(NullStream),
+}
+
+struct OutputDevice {
+    backend: OutputBackend,
     config: StreamConfig,
-    sample_format: SampleFormat,
-    stream: Mutex<Option<Stream>>,
-    pub engine_producer: Mutex<Option<HeapProd<f32>>>,
-    pub preview_producer: Mutex<Option<HeapProd<f32>>>,
-    engine_consumer: Mutex<Option<HeapCons<f32>>>,
-    preview_consumer: Mutex<Option<HeapCons<f32>>>,
 }
 
-impl AudioEngine {
-    pub fn new() -> Self {
+impl OutputDevice {
+    /// Opens the given host and device, falling back to the defaults when no id is given.
+    /// Uses `preferred_sample_rate` when the device supports it, otherwise the device default.
+    /// The buffer size is clamped to the range the device reports.
+    fn open(
+        host_id: Option<&str>,
+        device_id: Option<&str>,
+        preferred_sample_rate: Option<usize>,
+        buffer_size: usize,
+    ) -> Result<Self> {
+        if host_id == Some(NULL_HOST_ID) {
+            return Ok(Self::null(preferred_sample_rate, buffer_size));
+        }
+
         let hosts = available_hosts();
         info!("{hosts:?}");
 
-        let host = cpal::default_host();
-        // let host = if hosts.contains(&HostId::Asio) {
-        //     info!("ASIO host available, attempting to use it.");
-        //     cpal::host_from_id(HostId::Asio).unwrap_or_else(|_| cpal::default_host())
-        // } else {
-        //     cpal::default_host()
-        // };
-
-        let device = host
-            .default_output_device()
-            .expect("No output device available");
+        let host = open_host(host_id)?;
+        let device = match device_id {
+            Some(device_id) => host
+                .device_by_id(&DeviceId::from_str(device_id)?)
+                .with_context(|| format!("Output device not found: {device_id}"))?,
+            None => host
+                .default_output_device()
+                .context("No output device available")?,
+        };
 
         info!("Host: {:?}", host.id());
-        info!(
-            "Output device: {}",
-            device.name().unwrap_or("Unknown".to_string())
-        );
+        info!("Output device: {}", device_name(&device));
 
         // Print all supported configs for debugging
         if let Ok(configs) = device.supported_output_configs() {
@@ -55,107 +95,361 @@ impl AudioEngine {
             }
         }
 
-        // Try to find an f32 config, otherwise fall back to default (which might fail if not f32)
-        let supported_config = device.default_output_config().unwrap();
+        let default_config = device
+            .default_output_config()
+            .context("Output device has no default config")?;
+        let supported_config = preferred_sample_rate
+            .and_then(|sample_rate| {
+                find_config_with_sample_rate(&device, &default_config, sample_rate)
+            })
+            .unwrap_or(default_config);
         let sample_format = supported_config.sample_format();
         let mut config: StreamConfig = supported_config.config();
-        config.buffer_size = cpal::BufferSize::Fixed(BUFFER_SIZE_DEFAULT as u32);
         config.channels = ENGINE_NUM_CHANNELS as u16;
 
-        match supported_config.buffer_size() {
+        let buffer_size = match supported_config.buffer_size() {
             cpal::SupportedBufferSize::Range { min, max } => {
                 info!("Supported buffer size range: {} - {}", min, max);
+                (buffer_size as u32).clamp(*min, *max)
             }
             cpal::SupportedBufferSize::Unknown => {
                 info!("Supported buffer size: Unknown");
+                buffer_size as u32
             }
+        };
+        config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
+
+        info!(
+            "Output device opened: {} Hz, {} channels, {} frames, Format: {:?}",
+            config.sample_rate, config.channels, buffer_size, sample_format
+        );
+
+        Ok(Self {
+            backend: OutputBackend::Cpal {
+                host,
+                device,
+                sample_format,
+            },
+            config,
+        })
+    }
+
+    /// Output that plays into nothing, used when no sound card is available.
+    fn null(sample_rate: Option<usize>, buffer_size: usize) -> Self {
+        let sample_rate = sample_rate.unwrap_or(NULL_OUTPUT_SAMPLE_RATE_DEFAULT);
+        let buffer_size = buffer_size.clamp(
+            NULL_OUTPUT_BUFFER_SIZE_MIN as usize,
+            NULL_OUTPUT_BUFFER_SIZE_MAX as usize,
+        );
+        info!(
+            "Null output opened: {} Hz, {} frames",
+            sample_rate, buffer_size
+        );
+        Self {
+            backend: OutputBackend::Null,
+            config: StreamConfig {
+                channels: ENGINE_NUM_CHANNELS as u16,
+                sample_rate: sample_rate as u32,
+                buffer_size: cpal::BufferSize::Fixed(buffer_size as u32),
+            },
+        }
+    }
+
+    fn buffer_size(&self) -> usize {
+        match self.config.buffer_size {
+            cpal::BufferSize::Fixed(buffer_size) => buffer_size as usize,
+            cpal::BufferSize::Default => BUFFER_SIZE_DEFAULT as usize,
+        }
+    }
+
+    fn info(&self) -> OutputDeviceInfo {
+        let (host_id, device_id, device_name) = match &self.backend {
+            OutputBackend::Cpal { host, device, .. } => (
+                host.id().to_string(),
+                device.id().ok().map(|id| id.to_string()),
+                device_name(device),
+            ),
+            OutputBackend::Null => (NULL_HOST_ID.to_string(), None, NULL_DEVICE_NAME.to_string()),
+        };
+        OutputDeviceInfo {
+            host_id,
+            device_id,
+            device_name,
+            sample_rate: self.config.sample_rate as usize,
+            num_channels: self.config.channels as usize,
+            buffer_size: self.buffer_size(),
         }
+    }
+}
+
+pub const NULL_DEVICE_NAME: &str = "Null output";
+
+pub fn open_host(host_id: Option<&str>) -> Result<Host> {
+    match host_id {
+        Some(host_id) => cpal::host_from_id(
+            HostId::from_str(host_id).with_context(|| format!("Unknown host: {host_id}"))?,
+        )
+        .with_context(|| format!("Host unavailable: {host_id}")),
+        None => Ok(cpal::default_host()),
+    }
+}
+
+pub fn device_name(device: &Device) -> String {
+    device
+        .description()
+        .map(|description| description.name().to_string())
+        .unwrap_or("Unknown".to_string())
+}
+
+fn find_config_with_sample_rate(
+    device: &Device,
+    default_config: &SupportedStreamConfig,
+    sample_rate: usize,
+) -> Option<SupportedStreamConfig> {
+    device
+        .supported_output_configs()
+        .ok()?
+        .filter(|range| range.sample_format() == default_config.sample_format())
+        .find_map(|range| range.try_with_sample_rate(sample_rate as u32))
+}
+
+pub struct AudioEngine {
+    output: Mutex<OutputDevice>,
+    sample_rate: AtomicUsize,
+    num_channels: AtomicUsize,
+    buffer_size: AtomicUsize,
+    stream: Mutex<Option<OutputStream>>,
+    capture: Arc<Mutex<Option<OutputCapture>>>,
+    pub engine_producer: Mutex<Option<HeapProd<f32>>>,
+    pub preview_producer: Mutex<Option<HeapProd<f32>>>,
+    monitor_producer: Mutex<Option<HeapProd<f32>>>,
+    engine_consumer: Mutex<Option<HeapCons<f32>>>,
+    preview_consumer: Mutex<Option<HeapCons<f32>>>,
+    monitor_consumer: Mutex<Option<HeapCons<f32>>>,
+}
+
+impl AudioEngine {
+    pub fn new() -> Self {
+        let buffer_size = BUFFER_SIZE_DEFAULT as usize;
+        let output = OutputDevice::open(None, None, None, buffer_size).unwrap_or_else(|e| {
+            warn!("{e}, falling back to null output");
+            OutputDevice::null(None, buffer_size)
+        });
 
         info!(
-            "AudioEngine initialized: {} Hz, {} channels, Format: {:?}",
-            config.sample_rate, config.channels, sample_format
+            "AudioEngine initialized: {} Hz, {} channels",
+            output.config.sample_rate, output.config.channels
         );
 
         Self {
-            host,
-            device,
-            config,
-            sample_format,
+            sample_rate: AtomicUsize::new(output.config.sample_rate as usize),
+            num_channels: AtomicUsize::new(output.config.channels as usize),
+            buffer_size: AtomicUsize::new(output.buffer_size()),
+            output: Mutex::new(output),
             stream: Mutex::new(None),
+            capture: Arc::new(Mutex::new(None)),
             engine_producer: Mutex::new(None),
             preview_producer: Mutex::new(None),
+            monitor_producer: Mutex::new(None),
             engine_consumer: Mutex::new(None),
             preview_consumer: Mutex::new(None),
+            monitor_consumer: Mutex::new(None),
         }
     }
 
-    pub fn start(&self) {
+    /// Tears down the running stream and rebuilds it, with fresh ring buffers, from the given settings.
+    /// Without a configured sample rate the current one is kept, so loaded assets stay valid.
+    /// Blocks until the transport and preview have released their producers.
+    /// If the new device can't be opened the previous one is restarted and the error is returned.
+    pub fn start_with_settings(&self, settings: &AudioSettings) -> Result<()> {
+        let mut engine_producer = self.engine_producer.lock().unwrap();
+        let mut preview_producer = self.preview_producer.lock().unwrap();
+        self.stream.lock().unwrap().take();
+
+        let mut output = self.output.lock().unwrap();
+        let open_result = OutputDevice::open(
+            settings.host_id.as_deref(),
+            settings.output_device_id.as_deref(),
+            Some(settings.sample_rate.unwrap_or(self.sample_rate())),
+            settings.buffer_size.unwrap_or(BUFFER_SIZE_DEFAULT as usize),
+        );
+        let open_error = match open_result {
+            Ok(new_output) => {
+                *output = new_output;
+                None
+            }
+            Err(e) => {
+                warn!("Could not open output device, restarting previous one: {e}");
+                Some(e)
+            }
+        };
+
+        self.sample_rate
+            .store(output.config.sample_rate as usize, Ordering::SeqCst);
+        self.num_channels
+            .store(output.config.channels as usize, Ordering::SeqCst);
+        self.buffer_size
+            .store(output.buffer_size(), Ordering::SeqCst);
+        self.start_stream(&output, &mut engine_producer, &mut preview_producer)?;
+
+        match open_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts capturing everything the null output plays. Real devices are not captured.
+    pub fn start_capture(&self, target: OutputCaptureTarget) -> Result<()> {
+        let capture = OutputCapture::new(target, self.num_channels(), self.sample_rate())?;
+        *self.capture.lock().unwrap() = Some(capture);
+        Ok(())
+    }
+
+    /// Stops the running capture and returns the samples captured into memory.
+    pub fn stop_capture(&self) -> Result<Vec<EngineSampleFormat>> {
+        let capture = self.capture.lock().unwrap().take();
+        match capture {
+            Some(capture) => capture.finish(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn output_device_info(&self) -> OutputDeviceInfo {
+        self.output.lock().unwrap().info()
+    }
+
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate.load(Ordering::SeqCst)
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.num_channels.load(Ordering::SeqCst)
+    }
+
+    /// Frames per device buffer, which is also the block size the transport renders.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.load(Ordering::SeqCst)
+    }
+
+    /// Frames between rendering a block and hearing it: the ring buffer holds two buffers
+    /// on top of the one the device is playing.
+    pub fn output_latency_frames(&self) -> usize {
+        self.buffer_size() * 3
+    }
+
+    /// Hands the input monitoring producer to the input stream.
+    /// The output stream mixes whatever is pushed into it on top of the engine output.
+    /// A new one is created every time the output stream is rebuilt.
+    pub fn take_monitor_producer(&self) -> Option<HeapProd<f32>> {
+        self.monitor_producer.lock().unwrap().take()
+    }
+
+    fn start_stream(
+        &self,
+        output: &OutputDevice,
+        engine_producer: &mut Option<HeapProd<f32>>,
+        preview_producer: &mut Option<HeapProd<f32>>,
+    ) -> Result<()> {
         // Double the buffer size for safety
         let buffer_multiplier = 2;
         // Create and split the ring buffers
-        let engine_rb = HeapRb::<f32>::new(
-            BUFFER_SIZE_DEFAULT as usize * self.num_channels() * buffer_multiplier,
-        );
+        let engine_rb =
+            HeapRb::<f32>::new(self.buffer_size() * self.num_channels() * buffer_multiplier);
         let (engine_prod, engine_cons) = engine_rb.split();
 
         let preview_rb = HeapRb::<f32>::new(44100);
         let (preview_prod, preview_cons) = preview_rb.split();
 
-        *self.engine_producer.lock().unwrap() = Some(engine_prod);
-        *self.preview_producer.lock().unwrap() = Some(preview_prod);
+        // Kept as small as the engine ring so monitoring adds as little latency as possible
+        let monitor_rb =
+            HeapRb::<f32>::new(self.buffer_size() * self.num_channels() * buffer_multiplier);
+        let (monitor_prod, monitor_cons) = monitor_rb.split();
+
+        *engine_producer = Some(engine_prod);
+        *preview_producer = Some(preview_prod);
+        *self.monitor_producer.lock().unwrap() = Some(monitor_prod);
         *self.engine_consumer.lock().unwrap() = Some(engine_cons);
         *self.preview_consumer.lock().unwrap() = Some(preview_cons);
+        *self.monitor_consumer.lock().unwrap() = Some(monitor_cons);
 
-        match self.sample_format {
-            cpal::SampleFormat::I8 => self.build_output_stream::<i8>(),
-            cpal::SampleFormat::I16 => self.build_output_stream::<i16>(),
-            cpal::SampleFormat::I24 => self.build_output_stream::<i32>(),
-            cpal::SampleFormat::I32 => self.build_output_stream::<i32>(),
-            cpal::SampleFormat::I64 => self.build_output_stream::<i64>(),
-            cpal::SampleFormat::U8 => self.build_output_stream::<u8>(),
-            cpal::SampleFormat::U16 => self.build_output_stream::<u16>(),
-            cpal::SampleFormat::U24 => self.build_output_stream::<u32>(),
-            cpal::SampleFormat::U32 => self.build_output_stream::<u32>(),
-            cpal::SampleFormat::U64 => self.build_output_stream::<u64>(),
-            cpal::SampleFormat::F32 => self.build_output_stream::<f32>(),
-            cpal::SampleFormat::F64 => self.build_output_stream::<f64>(),
-            _ => panic!("Unsupported sample format"),
+        let (device, sample_format) = match &output.backend {
+            OutputBackend::Cpal {
+                device,
+                sample_format,
+                ..
+            } => (device, *sample_format),
+            OutputBackend::Null => return self.build_null_stream(&output.config),
         };
+
+        match sample_format {
+            cpal::SampleFormat::I8 => self.build_output_stream::<i8>(device, &output.config),
+            cpal::SampleFormat::I16 => self.build_output_stream::<i16>(device, &output.config),
+            cpal::SampleFormat::I24 => self.build_output_stream::<i32>(device, &output.config),
+            cpal::SampleFormat::I32 => self.build_output_stream::<i32>(device, &output.config),
+            cpal::SampleFormat::I64 => self.build_output_stream::<i64>(device, &output.config),
+            cpal::SampleFormat::U8 => self.build_output_stream::<u8>(device, &output.config),
+            cpal::SampleFormat::U16 => self.build_output_stream::<u16>(device, &output.config),
+            cpal::SampleFormat::U24 => self.build_output_stream::<u32>(device, &output.config),
+            cpal::SampleFormat::U32 => self.build_output_stream::<u32>(device, &output.config),
+            cpal::SampleFormat::U64 => self.build_output_stream::<u64>(device, &output.config),
+            cpal::SampleFormat::F32 => self.build_output_stream::<f32>(device, &output.config),
+            cpal::SampleFormat::F64 => self.build_output_stream::<f64>(device, &output.config),
+            sample_format => Err(anyhow!("Unsupported sample format: {sample_format}")),
+        }
     }
 
-    pub fn sample_rate(&self) -> usize {
-        self.config.sample_rate as usize
+    fn take_consumers(&self) -> Result<(HeapCons<f32>, HeapCons<f32>, HeapCons<f32>)> {
+        let engine_consumer = self
+            .engine_consumer
+            .lock()
+            .map_err(|_| anyhow!("Could not lock engine consumer"))?
+            .take()
+            .context("Engine consumer missing or stream already started")?;
+        let preview_consumer = self
+            .preview_consumer
+            .lock()
+            .map_err(|_| anyhow!("Could not lock preview consumer"))?
+            .take()
+            .context("Preview consumer missing or stream already started")?;
+        let monitor_consumer = self
+            .monitor_consumer
+            .lock()
+            .map_err(|_| anyhow!("Could not lock monitor consumer"))?
+            .take()
+            .context("Monitor consumer missing or stream already started")?;
+        Ok((engine_consumer, preview_consumer, monitor_consumer))
     }
 
-    pub fn num_channels(&self) -> usize {
-        self.config.channels as usize
+    fn build_null_stream(&self, config: &StreamConfig) -> Result<()> {
+        let (engine_consumer, preview_consumer, monitor_consumer) = self.take_consumers()?;
+        let stream = NullStream::start(
+            config.sample_rate as usize,
+            config.channels as usize,
+            self.buffer_size(),
+            engine_consumer,
+            preview_consumer,
+            monitor_consumer,
+            Arc::clone(&self.capture),
+        );
+        *self.stream.lock().unwrap() = Some(OutputStream::Null(stream));
+        Ok(())
     }
 
-    fn build_output_stream<SampleType>(&self)
+    fn build_output_stream<SampleType>(&self, device: &Device, config: &StreamConfig) -> Result<()>
     where
         SampleType: Sample + SizedSample + FromSample<f32> + Copy + Send + Debug + Display,
     {
         // Pre-allocate a scratch buffer to avoid allocation in the callback
         let mut mixer_temp_output = vec![0.0f32; 4096];
         let mut preview_temp_output = vec![0.0f32; 4096];
+        let mut monitor_temp_output = vec![0.0f32; 4096];
 
-        let mut engine_consumer = self
-            .engine_consumer
-            .lock()
-            .expect("Could not lock engine consumer")
-            .take()
-            .expect("Engine consumer missing or stream already started");
-        let mut preview_consumer = self
-            .preview_consumer
-            .lock()
-            .expect("Could not lock preview consumer")
-            .take()
-            .expect("Preview consumer missing or stream already started");
+        let (mut engine_consumer, mut preview_consumer, mut monitor_consumer) =
+            self.take_consumers()?;
 
-        let stream = self
-            .device
+        let stream = device
             .build_output_stream(
-                &self.config,
+                config,
                 move |output: &mut [SampleType], _| {
                     if mixer_temp_output.len() < output.len() {
                         mixer_temp_output.resize(output.len(), 0.0);
@@ -163,28 +457,51 @@ impl AudioEngine {
                     if preview_temp_output.len() < output.len() {
                         preview_temp_output.resize(output.len(), 0.0);
                     }
+                    if monitor_temp_output.len() < output.len() {
+                        monitor_temp_output.resize(output.len(), 0.0);
+                    }
 
                     let engine_slice = &mut mixer_temp_output[..output.len()];
                     let preview_slice = &mut preview_temp_output[..output.len()];
+                    let monitor_slice = &mut monitor_temp_output[..output.len()];
 
                     engine_slice.fill(0.0);
                     preview_slice.fill(0.0);
+                    monitor_slice.fill(0.0);
 
-                    engine_consumer.pop_slice(engine_slice);
+                    let engine_len = engine_consumer.pop_slice(engine_slice);
+                    if engine_len < output.len() && TRANSPORT.is_streaming() {
+                        ENGINE_STATS.record_xrun(XrunKind::Underrun);
+                    }
                     preview_consumer.pop_slice(preview_slice);
+                    monitor_consumer.pop_slice(monitor_slice);
 
                     for i in 0..output.len() {
-                        let mixed = engine_slice[i] + preview_slice[i];
+                        // Hard clip as a last resort, integer formats would wrap around instead.
+                        // A limiter on the master keeps the mix from getting here.
+                        let mixed = (engine_slice[i] + preview_slice[i] + monitor_slice[i])
+                            .clamp(-1.0, 1.0);
                         output[i] = SampleType::from_sample::<EngineSampleFormat>(mixed);
                     }
+                    RENDER_THREAD.wake();
+                },
+                move |err| {
+                    match err {
+                        StreamError::BufferUnderrun => ENGINE_STATS.record_xrun(XrunKind::Device),
+                        StreamError::DeviceNotAvailable | StreamError::StreamInvalidated => {
+                            DEVICE_WATCHER.report_device_lost()
+                        }
+                        _ => {}
+                    }
+                    error!("Stream error: {}", err)
                 },
-                move |err| error!("Stream error: {}", err),
                 None,
             )
-            .expect("Failed to build output stream");
+            .context("Failed to build output stream")?;
 
-        stream.play().expect("Failed to play stream");
-        *self.stream.lock().unwrap() = Some(stream);
+        stream.play().context("Failed to play stream")?;
+        *self.stream.lock().unwrap() = Some(OutputStream::Cpal(stream));
+        Ok(())
     }
 }
 
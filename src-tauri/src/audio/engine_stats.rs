@@ -0,0 +1,175 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use atomic_float::AtomicF32;
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::{app_handle, audio::engine::AUDIO_ENGINE, core::constants::XRUN_EVENT};
+
+const XRUN_REPORT_INTERVAL: Duration = Duration::from_millis(250);
+/// Weight of the newest block in the smoothed DSP load.
+const DSP_LOAD_SMOOTHING: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum XrunKind {
+    /// The output callback found the engine ring buffer empty while playing.
+    Underrun,
+    /// Rendering a block took longer than the block lasts.
+    LateBuffer,
+    /// The audio backend reported an xrun of its own.
+    Device,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XrunReport {
+    pub kind: XrunKind,
+    /// Xruns of this kind since the previous report.
+    pub count: u64,
+    pub total: u64,
+    pub timestamp_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineStatsInfo {
+    /// Render time relative to the buffer duration, smoothed. Above 1.0 the engine can't keep up.
+    pub dsp_load: f32,
+    pub peak_dsp_load: f32,
+    pub underruns: u64,
+    pub late_buffers: u64,
+    pub device_xruns: u64,
+    pub last_xrun_timestamp_ms: Option<u64>,
+    pub buffer_size: usize,
+    pub sample_rate: usize,
+}
+
+/// Counters written from the audio threads with atomics only.
+/// A reporter thread turns new xruns into `XRUN_EVENT`s for the frontend.
+pub struct EngineStats {
+    underruns: AtomicU64,
+    late_buffers: AtomicU64,
+    device_xruns: AtomicU64,
+    last_xrun_timestamp_ms: AtomicU64,
+    dsp_load: AtomicF32,
+    peak_dsp_load: AtomicF32,
+    reporter: Mutex<Option<JoinHandle<()>>>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl EngineStats {
+    pub fn new() -> Self {
+        Self {
+            underruns: AtomicU64::new(0),
+            late_buffers: AtomicU64::new(0),
+            device_xruns: AtomicU64::new(0),
+            last_xrun_timestamp_ms: AtomicU64::new(0),
+            dsp_load: AtomicF32::new(0.0),
+            peak_dsp_load: AtomicF32::new(0.0),
+            reporter: Mutex::new(None),
+        }
+    }
+
+    fn counter(&self, kind: XrunKind) -> &AtomicU64 {
+        match kind {
+            XrunKind::Underrun => &self.underruns,
+            XrunKind::LateBuffer => &self.late_buffers,
+            XrunKind::Device => &self.device_xruns,
+        }
+    }
+
+    /// Lock-free, safe to call from the output callback.
+    pub fn record_xrun(&self, kind: XrunKind) {
+        self.counter(kind).fetch_add(1, Ordering::Relaxed);
+        self.last_xrun_timestamp_ms
+            .store(now_ms(), Ordering::Relaxed);
+    }
+
+    /// Called by the render thread after every block with the time it took to render it.
+    pub fn record_render_time(&self, elapsed: Duration, block_duration: Duration) {
+        let load = elapsed.as_secs_f32() / block_duration.as_secs_f32();
+        let smoothed = self.dsp_load.load(Ordering::Relaxed) * (1.0 - DSP_LOAD_SMOOTHING)
+            + load * DSP_LOAD_SMOOTHING;
+        self.dsp_load.store(smoothed, Ordering::Relaxed);
+        self.peak_dsp_load.fetch_max(load, Ordering::Relaxed);
+        if load > 1.0 {
+            self.record_xrun(XrunKind::LateBuffer);
+        }
+    }
+
+    pub fn reset(&self) {
+        self.underruns.store(0, Ordering::Relaxed);
+        self.late_buffers.store(0, Ordering::Relaxed);
+        self.device_xruns.store(0, Ordering::Relaxed);
+        self.last_xrun_timestamp_ms.store(0, Ordering::Relaxed);
+        self.peak_dsp_load.store(0.0, Ordering::Relaxed);
+    }
+
+    pub fn info(&self) -> EngineStatsInfo {
+        let last_xrun_timestamp_ms = self.last_xrun_timestamp_ms.load(Ordering::Relaxed);
+        EngineStatsInfo {
+            dsp_load: self.dsp_load.load(Ordering::Relaxed),
+            peak_dsp_load: self.peak_dsp_load.load(Ordering::Relaxed),
+            underruns: self.underruns.load(Ordering::Relaxed),
+            late_buffers: self.late_buffers.load(Ordering::Relaxed),
+            device_xruns: self.device_xruns.load(Ordering::Relaxed),
+            last_xrun_timestamp_ms: (last_xrun_timestamp_ms > 0).then_some(last_xrun_timestamp_ms),
+            buffer_size: AUDIO_ENGINE.buffer_size(),
+            sample_rate: AUDIO_ENGINE.sample_rate(),
+        }
+    }
+
+    /// Spawns the thread that reports new xruns to the frontend. It lives as long as the app.
+    pub fn start_reporter(&self) {
+        let mut reporter = self.reporter.lock().unwrap();
+        if reporter.is_some() {
+            return;
+        }
+        *reporter = Some(thread::spawn(report_xruns));
+    }
+}
+
+fn report_xruns() {
+    let kinds = [XrunKind::Underrun, XrunKind::LateBuffer, XrunKind::Device];
+    let mut reported = [0u64; 3];
+
+    loop {
+        thread::sleep(XRUN_REPORT_INTERVAL);
+        for (kind, reported) in kinds.iter().zip(reported.iter_mut()) {
+            let total = ENGINE_STATS.counter(*kind).load(Ordering::Relaxed);
+            // A reset brings the total below what was already reported
+            if total < *reported {
+                *reported = 0;
+            }
+            if total == *reported {
+                continue;
+            }
+            let _ = app_handle().emit(
+                XRUN_EVENT,
+                XrunReport {
+                    kind: *kind,
+                    count: total - *reported,
+                    total,
+                    timestamp_ms: ENGINE_STATS.last_xrun_timestamp_ms.load(Ordering::Relaxed),
+                },
+            );
+            *reported = total;
+        }
+    }
+}
+
+pub static ENGINE_STATS: LazyLock<EngineStats> = LazyLock::new(|| EngineStats::new());
@@ -0,0 +1,143 @@
+use std::f32::consts::FRAC_PI_4;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{constants::ENGINE_NUM_CHANNELS, types::EngineSampleFormat};
+
+/// How panning splits a track between the left and right channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PanLaw {
+    /// Unity gain in the center, panning only turns the opposite side down.
+    #[default]
+    Balance,
+    /// Constant power, both sides at -3 dB in the center.
+    ConstantPower,
+    /// Linear, both sides at -6 dB in the center.
+    Linear,
+}
+
+impl PanLaw {
+    /// Left and right gain for a pan position in -1..1.
+    pub fn gains(&self, pan: f32) -> [f32; 2] {
+        let pan = pan.clamp(-1.0, 1.0);
+        match self {
+            PanLaw::Balance => [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)],
+            PanLaw::ConstantPower => {
+                let angle = (pan + 1.0) * FRAC_PI_4;
+                [angle.cos(), angle.sin()]
+            }
+            PanLaw::Linear => [(1.0 - pan) / 2.0, (1.0 + pan) / 2.0],
+        }
+    }
+}
+
+/// Converts decibels into a linear gain factor.
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Converts a linear gain factor into decibels, silence being negative infinity.
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
+
+/// Per-channel gain of a track or the master, muted tracks are silent.
+pub fn channel_gains(volume: f32, pan: f32, muted: bool, pan_law: PanLaw) -> [f32; 2] {
+    if muted {
+        return [0.0; 2];
+    }
+    pan_law.gains(pan).map(|gain| gain * volume)
+}
+
+/// Adds `source` scaled by `gain` to `destination`, ramping linearly from `from` to `to` over the
+/// buffer like `apply_gain_ramp`.
+pub fn mix_with_gain_ramp(
+    destination: &mut [EngineSampleFormat],
+    source: &[EngineSampleFormat],
+    from: f32,
+    to: f32,
+) {
+    let num_frames = destination.len() / ENGINE_NUM_CHANNELS as usize;
+    if num_frames == 0 {
+        return;
+    }
+    let step = (to - from) / num_frames as f32;
+    for (index, (destination_frame, source_frame)) in destination
+        .chunks_exact_mut(ENGINE_NUM_CHANNELS as usize)
+        .zip(source.chunks_exact(ENGINE_NUM_CHANNELS as usize))
+        .enumerate()
+    {
+        let gain = from + step * (index + 1) as f32;
+        for (destination_sample, source_sample) in destination_frame.iter_mut().zip(source_frame) {
+            *destination_sample += *source_sample * gain;
+        }
+    }
+}
+
+/// Applies per-channel gains to an interleaved stereo buffer, ramping linearly from `from`
+/// to `to` over the buffer so gain changes don't click.
+pub fn apply_gain_ramp(buffer: &mut [EngineSampleFormat], from: [f32; 2], to: [f32; 2]) {
+    let num_frames = buffer.len() / ENGINE_NUM_CHANNELS as usize;
+    if num_frames == 0 {
+        return;
+    }
+    if from == to {
+        for frame in buffer.chunks_exact_mut(ENGINE_NUM_CHANNELS as usize) {
+            frame[0] *= to[0];
+            frame[1] *= to[1];
+        }
+        return;
+    }
+    let step = [
+        (to[0] - from[0]) / num_frames as f32,
+        (to[1] - from[1]) / num_frames as f32,
+    ];
+    for (index, frame) in buffer
+        .chunks_exact_mut(ENGINE_NUM_CHANNELS as usize)
+        .enumerate()
+    {
+        let progress = (index + 1) as f32;
+        frame[0] *= from[0] + step[0] * progress;
+        frame[1] *= from[1] + step[1] * progress;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_laws_at_center_and_edges() {
+        assert_eq!(PanLaw::Balance.gains(0.0), [1.0, 1.0]);
+        assert_eq!(PanLaw::Balance.gains(-0.5), [1.0, 0.5]);
+        assert_eq!(PanLaw::Linear.gains(0.0), [0.5, 0.5]);
+        assert_eq!(PanLaw::Linear.gains(1.0), [0.0, 1.0]);
+        let [left, right] = PanLaw::ConstantPower.gains(0.0);
+        assert!((gain_to_db(left) + 3.0103).abs() < 1e-3);
+        assert!((left - right).abs() < 1e-6);
+        let [left, right] = PanLaw::ConstantPower.gains(-2.0);
+        assert_eq!(left, 1.0);
+        assert!(right.abs() < 1e-6);
+    }
+
+    #[test]
+    fn channel_gains_scale_by_volume_and_mute() {
+        assert_eq!(channel_gains(0.5, 0.0, false, PanLaw::Balance), [0.5, 0.5]);
+        assert_eq!(channel_gains(0.5, 1.0, false, PanLaw::Balance), [0.0, 0.5]);
+        assert_eq!(channel_gains(1.0, 0.0, true, PanLaw::Balance), [0.0, 0.0]);
+        assert!((db_to_gain(-6.0) - 0.501187).abs() < 1e-6);
+        assert!((gain_to_db(db_to_gain(-12.5)) + 12.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn gain_ramp_ends_on_target() {
+        let mut buffer = vec![1.0; 8];
+        apply_gain_ramp(&mut buffer, [0.0, 1.0], [1.0, 0.0]);
+        assert_eq!(buffer, [0.25, 0.75, 0.5, 0.5, 0.75, 0.25, 1.0, 0.0]);
+
+        let mut destination = vec![1.0; 4];
+        mix_with_gain_ramp(&mut destination, &[2.0; 4], 0.0, 0.5);
+        assert_eq!(destination, [1.5, 1.5, 2.0, 2.0]);
+    }
+}
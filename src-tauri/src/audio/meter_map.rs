@@ -0,0 +1,243 @@
+use anyhow::{bail, Result};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{constants::TIME_SIGNATURE_NUMERATOR_MAX, types::Id};
+
+/// Time signature change at the start of a bar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterEvent {
+    pub id: Id,
+    /// 1-based bar the time signature starts at.
+    pub bar: usize,
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+/// Musical position, bars and beats are 1-based, ticks are ppq into the beat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarBeatTick {
+    pub bar: usize,
+    pub beat: usize,
+    pub tick: usize,
+}
+
+#[derive(Debug, Clone)]
+struct MeterSegment {
+    start_bar: usize,
+    start_ppq: usize,
+    numerator: usize,
+    ticks_per_beat: usize,
+}
+
+impl MeterSegment {
+    fn ticks_per_bar(&self) -> usize {
+        self.numerator * self.ticks_per_beat
+    }
+}
+
+/// Time signature changes of the project, sorted by bar. There is always an event at bar 1.
+/// Bars are counted in ppq, so the map doesn't depend on the tempo.
+/// Immutable once built; edits produce a new map that replaces the old one.
+#[derive(Debug, Clone)]
+pub struct MeterMap {
+    ppq: u16,
+    events: Vec<MeterEvent>,
+    segments: Vec<MeterSegment>,
+}
+
+impl MeterMap {
+    pub fn new(ppq: u16, numerator: u8, denominator: u8) -> Self {
+        Self::from_events(
+            ppq,
+            vec![MeterEvent {
+                id: nanoid!(),
+                bar: 1,
+                numerator,
+                denominator,
+            }],
+        )
+    }
+
+    fn from_events(ppq: u16, mut events: Vec<MeterEvent>) -> Self {
+        events.sort_by_key(|event| event.bar);
+
+        let mut segments: Vec<MeterSegment> = Vec::with_capacity(events.len());
+        for event in events.iter() {
+            let start_ppq = match segments.last() {
+                Some(previous) => {
+                    previous.start_ppq + (event.bar - previous.start_bar) * previous.ticks_per_bar()
+                }
+                None => 0,
+            };
+            segments.push(MeterSegment {
+                start_bar: event.bar,
+                start_ppq,
+                numerator: event.numerator as usize,
+                ticks_per_beat: ppq as usize * 4 / event.denominator as usize,
+            });
+        }
+
+        Self {
+            ppq,
+            events,
+            segments,
+        }
+    }
+
+    pub fn events(&self) -> &[MeterEvent] {
+        &self.events
+    }
+
+    fn segment_at_ppq(&self, position_ppq: usize) -> &MeterSegment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start_ppq <= position_ppq);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    fn segment_at_bar(&self, bar: usize) -> &MeterSegment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start_bar <= bar);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    pub fn ppq_to_bar_beat_tick(&self, position_ppq: usize) -> BarBeatTick {
+        let segment = self.segment_at_ppq(position_ppq);
+        let ppq_in_segment = position_ppq - segment.start_ppq;
+        let beats = ppq_in_segment / segment.ticks_per_beat;
+        BarBeatTick {
+            bar: segment.start_bar + beats / segment.numerator,
+            beat: beats % segment.numerator + 1,
+            tick: ppq_in_segment % segment.ticks_per_beat,
+        }
+    }
+
+    /// Beats and ticks past the end of the bar carry over into the following bars.
+    pub fn bar_beat_tick_to_ppq(&self, position: BarBeatTick) -> usize {
+        let bar = position.bar.max(1);
+        let segment = self.segment_at_bar(bar);
+        segment.start_ppq
+            + (bar - segment.start_bar) * segment.ticks_per_bar()
+            + position.beat.saturating_sub(1) * segment.ticks_per_beat
+            + position.tick
+    }
+
+    /// Ticks per beat and beats per bar at a position.
+    pub fn beat_grid_at(&self, position_ppq: usize) -> (usize, usize) {
+        let segment = self.segment_at_ppq(position_ppq);
+        (segment.ticks_per_beat, segment.numerator)
+    }
+
+    /// Calls `f` with the position of every beat in `start_ppq..end_ppq` and whether it starts a bar.
+    /// Doesn't allocate, so the metronome can use it on the render thread.
+    pub fn for_each_beat(&self, start_ppq: usize, end_ppq: usize, mut f: impl FnMut(usize, bool)) {
+        let mut index = self
+            .segments
+            .partition_point(|segment| segment.start_ppq <= start_ppq)
+            .saturating_sub(1);
+        let mut position_ppq = start_ppq;
+        while let Some(segment) = self.segments.get(index) {
+            let segment_end_ppq = self
+                .segments
+                .get(index + 1)
+                .map_or(end_ppq, |next| next.start_ppq.min(end_ppq));
+            let mut beat = (position_ppq - segment.start_ppq).div_ceil(segment.ticks_per_beat);
+            loop {
+                let beat_ppq = segment.start_ppq + beat * segment.ticks_per_beat;
+                if beat_ppq >= segment_end_ppq {
+                    break;
+                }
+                f(beat_ppq, beat % segment.numerator == 0);
+                beat += 1;
+            }
+            if segment_end_ppq >= end_ppq {
+                break;
+            }
+            index += 1;
+            position_ppq = segment_end_ppq;
+        }
+    }
+
+    fn validate(
+        &self,
+        bar: usize,
+        numerator: u8,
+        denominator: u8,
+        others: &[MeterEvent],
+    ) -> Result<()> {
+        if bar == 0 {
+            bail!("Bars start at 1");
+        }
+        if numerator == 0 || numerator > TIME_SIGNATURE_NUMERATOR_MAX {
+            bail!("Beats per bar must be between 1 and {TIME_SIGNATURE_NUMERATOR_MAX}");
+        }
+        if !denominator.is_power_of_two()
+            || !(self.ppq as usize * 4).is_multiple_of(denominator as usize)
+        {
+            bail!("Unsupported beat unit: {denominator}");
+        }
+        if others.iter().any(|event| event.bar == bar) {
+            bail!("There is already a time signature change at bar {bar}");
+        }
+        Ok(())
+    }
+
+    pub fn with_added_event(
+        &self,
+        bar: usize,
+        numerator: u8,
+        denominator: u8,
+    ) -> Result<(Self, MeterEvent)> {
+        self.validate(bar, numerator, denominator, &self.events)?;
+        let event = MeterEvent {
+            id: nanoid!(),
+            bar,
+            numerator,
+            denominator,
+        };
+        let mut events = self.events.clone();
+        events.push(event.clone());
+        Ok((Self::from_events(self.ppq, events), event))
+    }
+
+    /// The first event stays at bar 1 and can only change its time signature.
+    pub fn with_updated_event(
+        &self,
+        event_id: &str,
+        bar: usize,
+        numerator: u8,
+        denominator: u8,
+    ) -> Result<(Self, MeterEvent)> {
+        let Some(index) = self.events.iter().position(|event| event.id == event_id) else {
+            bail!("Time signature change not found: {event_id}");
+        };
+        let bar = if index == 0 { 1 } else { bar };
+        let mut events = self.events.clone();
+        let event = events.remove(index);
+        self.validate(bar, numerator, denominator, &events)?;
+        let event = MeterEvent {
+            bar,
+            numerator,
+            denominator,
+            ..event
+        };
+        events.push(event.clone());
+        Ok((Self::from_events(self.ppq, events), event))
+    }
+
+    pub fn with_removed_event(&self, event_id: &str) -> Result<Self> {
+        let Some(index) = self.events.iter().position(|event| event.id == event_id) else {
+            bail!("Time signature change not found: {event_id}");
+        };
+        if index == 0 {
+            bail!("The time signature at the project start can't be removed");
+        }
+        let mut events = self.events.clone();
+        events.remove(index);
+        Ok(Self::from_events(self.ppq, events))
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use atomic_float::AtomicF32;
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::{
+    app_handle,
+    audio::{snapshot::project_snapshot::load_project_snapshot, transport::TRANSPORT},
+    core::{
+        constants::{ENGINE_NUM_CHANNELS, METERS_EVENT},
+        types::{EngineSampleFormat, Id},
+    },
+};
+
+/// About 30 frames per second.
+const METER_REPORT_INTERVAL: Duration = Duration::from_millis(33);
+/// Short-term loudness is measured over 3 s, kept as 30 blocks of 100 ms.
+const LOUDNESS_BLOCKS: usize = 30;
+const LOUDNESS_BLOCKS_PER_SECOND: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterReading {
+    /// Linear peak per channel since the previous reading.
+    pub peak: [f32; 2],
+    /// Linear RMS per channel since the previous reading.
+    pub rms: [f32; 2],
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMeterReading {
+    pub track_id: Id,
+    #[serde(flatten)]
+    pub reading: MeterReading,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectGainReduction {
+    pub effect_id: Id,
+    /// Highest gain reduction since the previous frame, in positive dB.
+    pub gain_reduction_db: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetersFrame {
+    pub tracks: Vec<TrackMeterReading>,
+    pub master: MeterReading,
+    /// `None` while the master is silent.
+    pub master_short_term_lufs: Option<f32>,
+    /// Every insert effect that compresses, limits or gates, on tracks, buses and the master.
+    pub effects: Vec<EffectGainReduction>,
+}
+
+/// Peak and RMS accumulators written by the render thread and drained by the meter reporter.
+/// Atomics only, so the render path never waits on the UI.
+pub struct LevelMeter {
+    peak: [AtomicF32; 2],
+    sum_squares: [AtomicF32; 2],
+    num_frames: AtomicU32,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            peak: [AtomicF32::new(0.0), AtomicF32::new(0.0)],
+            sum_squares: [AtomicF32::new(0.0), AtomicF32::new(0.0)],
+            num_frames: AtomicU32::new(0),
+        }
+    }
+
+    /// Accumulates an interleaved stereo buffer.
+    pub fn process(&self, buffer: &[EngineSampleFormat]) {
+        let mut peak = [0.0f32; 2];
+        let mut sum_squares = [0.0f32; 2];
+        for frame in buffer.chunks_exact(ENGINE_NUM_CHANNELS as usize) {
+            for channel in 0..2 {
+                let sample = frame[channel];
+                peak[channel] = peak[channel].max(sample.abs());
+                sum_squares[channel] += sample * sample;
+            }
+        }
+        for channel in 0..2 {
+            self.peak[channel].fetch_max(peak[channel], Ordering::Relaxed);
+            self.sum_squares[channel].fetch_add(sum_squares[channel], Ordering::Relaxed);
+        }
+        let num_frames = buffer.len() / ENGINE_NUM_CHANNELS as usize;
+        self.num_frames
+            .fetch_add(num_frames as u32, Ordering::Relaxed);
+    }
+
+    /// Returns the levels since the previous reading and starts a new window.
+    pub fn take_reading(&self) -> MeterReading {
+        let num_frames = self.num_frames.swap(0, Ordering::Relaxed);
+        let mut reading = MeterReading::default();
+        for channel in 0..2 {
+            reading.peak[channel] = self.peak[channel].swap(0.0, Ordering::Relaxed);
+            let sum_squares = self.sum_squares[channel].swap(0.0, Ordering::Relaxed);
+            if num_frames > 0 {
+                reading.rms[channel] = (sum_squares / num_frames as f32).sqrt();
+            }
+        }
+        reading
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+/// Short-term loudness (ITU-R BS.1770, 3 s window, no gating) of an interleaved stereo signal.
+/// Owned by the render thread, all state is allocated up front.
+pub struct LoudnessMeter {
+    sample_rate: usize,
+    // Pre-filter and RLB high-pass per channel
+    k_weighting: [[Biquad; 2]; 2],
+    block_sum: f64,
+    block_frames: usize,
+    blocks: [f64; LOUDNESS_BLOCKS],
+    blocks_filled: usize,
+    next_block: usize,
+}
+
+impl LoudnessMeter {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 0,
+            k_weighting: [[Biquad::default(); 2]; 2],
+            block_sum: 0.0,
+            block_frames: 0,
+            blocks: [0.0; LOUDNESS_BLOCKS],
+            blocks_filled: 0,
+            next_block: 0,
+        }
+    }
+
+    /// K-weighting coefficients for any sample rate, as derived in libebur128.
+    fn reset(&mut self, sample_rate: usize) {
+        let sample_rate_f = sample_rate as f64;
+
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / sample_rate_f).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let pre_filter = Biquad {
+            b0: (vh + vb * k / q + k * k) / a0,
+            b1: 2.0 * (k * k - vh) / a0,
+            b2: (vh - vb * k / q + k * k) / a0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            ..Biquad::default()
+        };
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / sample_rate_f).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b0: 1.0,
+            b1: -2.0,
+            b2: 1.0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            ..Biquad::default()
+        };
+
+        *self = Self::new();
+        self.sample_rate = sample_rate;
+        self.k_weighting = [[pre_filter, high_pass]; 2];
+    }
+
+    pub fn process(&mut self, buffer: &[EngineSampleFormat], sample_rate: usize) {
+        if sample_rate != self.sample_rate {
+            self.reset(sample_rate);
+        }
+        let frames_per_block = sample_rate / LOUDNESS_BLOCKS_PER_SECOND;
+
+        for frame in buffer.chunks_exact(ENGINE_NUM_CHANNELS as usize) {
+            for (channel, filters) in self.k_weighting.iter_mut().enumerate() {
+                let mut sample = frame[channel] as f64;
+                for filter in filters.iter_mut() {
+                    sample = filter.process(sample);
+                }
+                self.block_sum += sample * sample;
+            }
+            self.block_frames += 1;
+
+            if self.block_frames == frames_per_block {
+                self.blocks[self.next_block] = self.block_sum / frames_per_block as f64;
+                self.next_block = (self.next_block + 1) % LOUDNESS_BLOCKS;
+                self.blocks_filled = (self.blocks_filled + 1).min(LOUDNESS_BLOCKS);
+                self.block_sum = 0.0;
+                self.block_frames = 0;
+            }
+        }
+    }
+
+    pub fn short_term_lufs(&self) -> f32 {
+        if self.blocks_filled == 0 {
+            return f32::NEG_INFINITY;
+        }
+        let mean_square =
+            self.blocks[..self.blocks_filled].iter().sum::<f64>() / self.blocks_filled as f64;
+        (-0.691 + 10.0 * mean_square.log10()) as f32
+    }
+}
+
+/// Level meters for every track and the master, fed by the render thread
+/// and sent to the frontend as `METERS_EVENT` while the transport plays.
+pub struct Metering {
+    track_meters: Mutex<HashMap<Id, Arc<LevelMeter>>>,
+    pub master: LevelMeter,
+    master_short_term_lufs: AtomicF32,
+    reporter: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Metering {
+    pub fn new() -> Self {
+        Self {
+            track_meters: Mutex::new(HashMap::new()),
+            master: LevelMeter::new(),
+            master_short_term_lufs: AtomicF32::new(f32::NEG_INFINITY),
+            reporter: Mutex::new(None),
+        }
+    }
+
+    /// Meter of a track, kept across snapshot rebuilds. Called when building the scheduler.
+    pub fn track_meter(&self, track_id: &str) -> Arc<LevelMeter> {
+        let mut track_meters = self.track_meters.lock().unwrap();
+        Arc::clone(
+            track_meters
+                .entry(track_id.to_string())
+                .or_insert_with(|| Arc::new(LevelMeter::new())),
+        )
+    }
+
+    /// Drops the meters of tracks that no longer exist.
+    pub fn retain_tracks(&self, track_ids: &[Id]) {
+        self.track_meters
+            .lock()
+            .unwrap()
+            .retain(|track_id, _| track_ids.contains(track_id));
+    }
+
+    pub fn set_master_short_term_lufs(&self, lufs: f32) {
+        self.master_short_term_lufs.store(lufs, Ordering::Relaxed);
+    }
+
+    fn take_frame(&self) -> MetersFrame {
+        let snapshot = load_project_snapshot();
+        let tracks = snapshot
+            .get_scheduler()
+            .tracks
+            .iter()
+            .map(|track| TrackMeterReading {
+                track_id: track.id.clone(),
+                reading: track.meter.take_reading(),
+            })
+            .collect();
+        let effects = snapshot
+            .get_data_nodes()
+            .nodes
+            .values()
+            .filter_map(|node| node.inserts())
+            .flat_map(|inserts| inserts.effects.iter())
+            .filter_map(|effect| {
+                Some(EffectGainReduction {
+                    effect_id: effect.id.clone(),
+                    gain_reduction_db: effect.instance.take_gain_reduction_db()?,
+                })
+            })
+            .collect();
+        let lufs = self.master_short_term_lufs.load(Ordering::Relaxed);
+        MetersFrame {
+            tracks,
+            master: self.master.take_reading(),
+            master_short_term_lufs: lufs.is_finite().then_some(lufs),
+            effects,
+        }
+    }
+
+    /// Spawns the thread that sends meter frames to the frontend. It lives as long as the app.
+    pub fn start_reporter(&self) {
+        let mut reporter = self.reporter.lock().unwrap();
+        if reporter.is_some() {
+            return;
+        }
+        *reporter = Some(thread::spawn(report_meters));
+    }
+}
+
+fn report_meters() {
+    let mut was_playing = false;
+    loop {
+        thread::sleep(METER_REPORT_INTERVAL);
+        let is_playing = TRANSPORT.is_playing.load(Ordering::SeqCst);
+        // One more frame after stopping so the meters fall back to silence
+        if is_playing || was_playing {
+            if !is_playing {
+                METERING.set_master_short_term_lufs(f32::NEG_INFINITY);
+            }
+            let _ = app_handle().emit(METERS_EVENT, METERING.take_frame());
+        }
+        was_playing = is_playing;
+    }
+}
+
+pub static METERING: LazyLock<Metering> = LazyLock::new(|| Metering::new());
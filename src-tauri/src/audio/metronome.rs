@@ -0,0 +1,372 @@
+use std::{
+    f32::consts::TAU,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        LazyLock, Mutex,
+    },
+};
+
+use anyhow::{bail, Result};
+use arc_swap::ArcSwap;
+use atomic_float::AtomicF32;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{
+        asset_pool::ASSET_POOL, engine::AUDIO_ENGINE, project_state::PROJECT_STATE,
+        snapshot::project_snapshot::ProjectSnapshot,
+    },
+    core::{
+        constants::{
+            METRONOME_ACCENT_FREQUENCY, METRONOME_BEAT_FREQUENCY, METRONOME_CLICK_DURATION,
+            METRONOME_COUNT_IN_BARS_MAX, METRONOME_VOLUME_DEFAULT,
+        },
+        types::{EngineSampleFormat, Id},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClickKind {
+    /// First beat of a bar.
+    Accent,
+    Beat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum ClickSource {
+    BuiltIn,
+    Asset { asset_id: Id, name: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetronomeInfo {
+    pub is_enabled: bool,
+    pub volume: f32,
+    pub count_in_bars: usize,
+    pub accent: ClickSource,
+    pub beat: ClickSource,
+}
+
+/// Click samples at the engine rate, interleaved like the engine output.
+struct ClickSounds {
+    sample_rate: usize,
+    accent: Vec<EngineSampleFormat>,
+    beat: Vec<EngineSampleFormat>,
+}
+
+impl ClickSounds {
+    fn get(&self, kind: ClickKind) -> &[EngineSampleFormat] {
+        match kind {
+            ClickKind::Accent => &self.accent,
+            ClickKind::Beat => &self.beat,
+        }
+    }
+
+    fn longest(&self) -> usize {
+        self.accent.len().max(self.beat.len())
+    }
+}
+
+/// A decaying sine burst.
+fn built_in_click(frequency: f32, sample_rate: usize, channels: usize) -> Vec<EngineSampleFormat> {
+    let num_frames = (METRONOME_CLICK_DURATION * sample_rate as f32) as usize;
+    let attack_frames = (sample_rate / 1000).max(1);
+    let decay = (sample_rate as f32 * METRONOME_CLICK_DURATION / 5.0).max(1.0);
+    let mut samples = Vec::with_capacity(num_frames * channels);
+    for frame in 0..num_frames {
+        let attack = (frame as f32 / attack_frames as f32).min(1.0);
+        let envelope = attack * (-(frame as f32) / decay).exp();
+        let sample = (TAU * frequency * frame as f32 / sample_rate as f32).sin() * envelope;
+        samples.extend(std::iter::repeat_n(sample, channels));
+    }
+    samples
+}
+
+/// Adds `sound`, starting at `click_start`, to the part of it that overlaps `out`.
+/// Positions are interleaved samples, `out` starts at `position_samples`.
+fn mix_click(
+    sound: &[EngineSampleFormat],
+    click_start: usize,
+    position_samples: usize,
+    out: &mut [EngineSampleFormat],
+    volume: f32,
+) {
+    let click_end = click_start + sound.len();
+    let out_end = position_samples + out.len();
+    if click_end <= position_samples || click_start >= out_end {
+        return;
+    }
+    let from = click_start.max(position_samples);
+    let to = click_end.min(out_end);
+    let out_range = &mut out[from - position_samples..to - position_samples];
+    let sound_range = &sound[from - click_start..to - click_start];
+    for (out_sample, click_sample) in out_range.iter_mut().zip(sound_range) {
+        *out_sample += click_sample * volume;
+    }
+}
+
+/// Count-in timeline, clicks every `beat_samples` before playback starts.
+#[derive(Debug, Clone, Copy)]
+pub struct CountIn {
+    pub total_samples: usize,
+    pub elapsed_samples: usize,
+    pub beat_samples: usize,
+    pub beats_per_bar: usize,
+}
+
+impl CountIn {
+    pub fn remaining_samples(&self) -> usize {
+        self.total_samples - self.elapsed_samples
+    }
+}
+
+/// Click generator following the tempo and meter maps of the snapshot.
+/// It is mixed after the master by the realtime renderer only, so it is neither metered nor bounced.
+/// Settings are atomics and the sounds an ArcSwap, so the render thread never waits on the UI.
+pub struct Metronome {
+    is_enabled: AtomicBool,
+    volume: AtomicF32,
+    count_in_bars: AtomicUsize,
+    sources: Mutex<(ClickSource, ClickSource)>,
+    sounds: ArcSwap<ClickSounds>,
+    // Count-in in progress, zero total when there is none
+    count_in_total_samples: AtomicUsize,
+    count_in_elapsed_samples: AtomicUsize,
+    count_in_beat_samples: AtomicUsize,
+    count_in_beats_per_bar: AtomicUsize,
+}
+
+impl Metronome {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: AtomicBool::new(false),
+            volume: AtomicF32::new(METRONOME_VOLUME_DEFAULT),
+            count_in_bars: AtomicUsize::new(0),
+            sources: Mutex::new((ClickSource::BuiltIn, ClickSource::BuiltIn)),
+            sounds: ArcSwap::from_pointee(ClickSounds {
+                sample_rate: 0,
+                accent: Vec::new(),
+                beat: Vec::new(),
+            }),
+            count_in_total_samples: AtomicUsize::new(0),
+            count_in_elapsed_samples: AtomicUsize::new(0),
+            count_in_beat_samples: AtomicUsize::new(0),
+            count_in_beats_per_bar: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn info(&self) -> MetronomeInfo {
+        let (accent, beat) = self.sources.lock().unwrap().clone();
+        MetronomeInfo {
+            is_enabled: self.is_enabled.load(Ordering::SeqCst),
+            volume: self.volume.load(Ordering::SeqCst),
+            count_in_bars: self.count_in_bars.load(Ordering::SeqCst),
+            accent,
+            beat,
+        }
+    }
+
+    pub fn set_enabled(&self, is_enabled: bool) {
+        self.is_enabled.store(is_enabled, Ordering::SeqCst);
+    }
+
+    /// Linear gain, 0..1.
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.clamp(0.0, 1.0), Ordering::SeqCst);
+    }
+
+    pub fn set_count_in_bars(&self, count_in_bars: usize) -> Result<()> {
+        if count_in_bars > METRONOME_COUNT_IN_BARS_MAX {
+            bail!("Count-in can be at most {METRONOME_COUNT_IN_BARS_MAX} bars");
+        }
+        self.count_in_bars.store(count_in_bars, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Uses the built-in click for `kind`, or the given audio file loaded into the asset pool.
+    pub async fn set_sound(&self, kind: ClickKind, source_path: Option<String>) -> Result<()> {
+        let source = match source_path {
+            Some(source_path) => {
+                let Some((asset_id, _, name)) = PROJECT_STATE.ensure_audio_asset(source_path).await
+                else {
+                    bail!("Couldn't load the metronome sound");
+                };
+                ClickSource::Asset { asset_id, name }
+            }
+            None => ClickSource::BuiltIn,
+        };
+        info!("Metronome {kind:?} sound: {source:?}");
+        {
+            let mut sources = self.sources.lock().unwrap();
+            match kind {
+                ClickKind::Accent => sources.0 = source,
+                ClickKind::Beat => sources.1 = source,
+            }
+        }
+        self.rebuild_sounds();
+        Ok(())
+    }
+
+    fn click_samples(source: &ClickSource, frequency: f32) -> Vec<EngineSampleFormat> {
+        if let ClickSource::Asset { asset_id, .. } = source {
+            if let Some(asset) = ASSET_POOL.audio.get_by_id(asset_id) {
+                return asset.pcm_data().samples().clone();
+            }
+        }
+        built_in_click(
+            frequency,
+            AUDIO_ENGINE.sample_rate(),
+            AUDIO_ENGINE.num_channels(),
+        )
+    }
+
+    /// Prepares the click sounds at the engine rate. Called after the engine sample rate changed
+    /// and the asset pool was resampled.
+    pub fn rebuild_sounds(&self) {
+        let (accent, beat) = self.sources.lock().unwrap().clone();
+        self.sounds.store(
+            ClickSounds {
+                sample_rate: AUDIO_ENGINE.sample_rate(),
+                accent: Self::click_samples(&accent, METRONOME_ACCENT_FREQUENCY),
+                beat: Self::click_samples(&beat, METRONOME_BEAT_FREQUENCY),
+            }
+            .into(),
+        );
+    }
+
+    /// Length in interleaved samples of the count-in before playback starting at `start_ppq`,
+    /// counted in the tempo and time signature found there. Zero when count-in is off.
+    pub fn count_in_samples(&self, start_ppq: usize) -> usize {
+        self.count_in_at(start_ppq)
+            .map_or(0, |count_in| count_in.total_samples)
+    }
+
+    fn count_in_at(&self, start_ppq: usize) -> Option<CountIn> {
+        let count_in_bars = self.count_in_bars.load(Ordering::SeqCst);
+        if count_in_bars == 0 {
+            return None;
+        }
+        let (ticks_per_beat, beats_per_bar) = PROJECT_STATE.beat_grid_at(start_ppq);
+        let beat_seconds = ticks_per_beat as f64 / PROJECT_STATE.ppq() as f64 * 60.0
+            / PROJECT_STATE.tempo_bpm_at(start_ppq) as f64;
+        let beat_frames = (beat_seconds * AUDIO_ENGINE.sample_rate() as f64).round() as usize;
+        let beat_samples = beat_frames * AUDIO_ENGINE.num_channels();
+        Some(CountIn {
+            total_samples: count_in_bars * beats_per_bar * beat_samples,
+            elapsed_samples: 0,
+            beat_samples,
+            beats_per_bar,
+        })
+    }
+
+    /// Starts a count-in before playback from `start_ppq`, if enabled.
+    pub fn arm_count_in(&self, start_ppq: usize) {
+        let Some(count_in) = self.count_in_at(start_ppq) else {
+            self.cancel_count_in();
+            return;
+        };
+        self.count_in_elapsed_samples.store(0, Ordering::SeqCst);
+        self.count_in_beat_samples
+            .store(count_in.beat_samples, Ordering::SeqCst);
+        self.count_in_beats_per_bar
+            .store(count_in.beats_per_bar, Ordering::SeqCst);
+        self.count_in_total_samples
+            .store(count_in.total_samples, Ordering::SeqCst);
+    }
+
+    pub fn cancel_count_in(&self) {
+        self.count_in_total_samples.store(0, Ordering::SeqCst);
+    }
+
+    /// The count-in still to be played, if any. Lock-free.
+    pub fn count_in(&self) -> Option<CountIn> {
+        let total_samples = self.count_in_total_samples.load(Ordering::SeqCst);
+        let elapsed_samples = self.count_in_elapsed_samples.load(Ordering::SeqCst);
+        if elapsed_samples >= total_samples {
+            return None;
+        }
+        Some(CountIn {
+            total_samples,
+            elapsed_samples,
+            beat_samples: self.count_in_beat_samples.load(Ordering::SeqCst),
+            beats_per_bar: self.count_in_beats_per_bar.load(Ordering::SeqCst),
+        })
+    }
+
+    pub fn is_counting_in(&self) -> bool {
+        self.count_in().is_some()
+    }
+
+    /// Renders the count-in clicks into `out` and moves the count-in forward by its length.
+    /// Called on the render thread.
+    pub fn render_count_in(&self, count_in: &CountIn, out: &mut [EngineSampleFormat]) {
+        let sounds = self.sounds.load();
+        if sounds.sample_rate == AUDIO_ENGINE.sample_rate() && count_in.beat_samples > 0 {
+            let volume = self.volume.load(Ordering::Relaxed);
+            let position_samples = count_in.elapsed_samples;
+            let first_beat =
+                position_samples.saturating_sub(sounds.longest()) / count_in.beat_samples;
+            let last_beat = (position_samples + out.len()).div_ceil(count_in.beat_samples);
+            for beat in first_beat..last_beat {
+                let kind = if beat % count_in.beats_per_bar == 0 {
+                    ClickKind::Accent
+                } else {
+                    ClickKind::Beat
+                };
+                let click_start = beat * count_in.beat_samples;
+                if click_start >= count_in.total_samples {
+                    break;
+                }
+                mix_click(sounds.get(kind), click_start, position_samples, out, volume);
+            }
+        }
+        self.count_in_elapsed_samples
+            .fetch_add(out.len(), Ordering::SeqCst);
+    }
+
+    /// Adds the clicks sounding in `out`, which starts at `position_samples` on the project timeline.
+    /// Stateless like the clip rendering, so loops and seeks click on the right beats.
+    pub fn render(
+        &self,
+        snapshot: &ProjectSnapshot,
+        position_samples: usize,
+        out: &mut [EngineSampleFormat],
+    ) {
+        if !self.is_enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let sounds = self.sounds.load();
+        let sample_rate = AUDIO_ENGINE.sample_rate();
+        if sounds.sample_rate != sample_rate {
+            return;
+        }
+        let channels = AUDIO_ENGINE.num_channels();
+        let volume = self.volume.load(Ordering::Relaxed);
+        let tempo_map = &snapshot.tempo_map;
+
+        // Clicks that started before this buffer may still be ringing
+        let lookback_samples = position_samples.saturating_sub(sounds.longest());
+        let start_ppq = tempo_map
+            .samples_to_ppq(lookback_samples, sample_rate, channels)
+            .saturating_sub(1);
+        let end_ppq =
+            tempo_map.samples_to_ppq(position_samples + out.len(), sample_rate, channels) + 1;
+        snapshot
+            .meter_map
+            .for_each_beat(start_ppq, end_ppq, |beat_ppq, is_downbeat| {
+                let kind = if is_downbeat {
+                    ClickKind::Accent
+                } else {
+                    ClickKind::Beat
+                };
+                let click_start = tempo_map.ppq_to_samples(beat_ppq, sample_rate, channels);
+                mix_click(sounds.get(kind), click_start, position_samples, out, volume);
+            });
+    }
+}
+
+pub static METRONOME: LazyLock<Metronome> = LazyLock::new(|| Metronome::new());
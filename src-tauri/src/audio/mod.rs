@@ -1,12 +1,27 @@
 pub mod arrangement;
 pub mod asset_pool;
+pub mod bounce;
 pub mod clip;
 pub mod decoder;
+pub mod device_watcher;
+pub mod devices;
+pub mod effects;
 pub mod engine;
+pub mod engine_stats;
+pub mod gain;
+pub mod meter_map;
+pub mod metering;
+pub mod metronome;
+pub mod null_output;
 pub mod preview_mixer;
 pub mod project_state;
+pub mod recorder;
+pub mod render_thread;
+pub mod renderer;
 pub mod resampler;
 pub mod snapshot;
+pub mod tempo_map;
 pub mod thread_pool;
 pub mod track;
 pub mod transport;
+pub mod wav_writer;
@@ -0,0 +1,236 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{error, info};
+use ringbuf::{traits::Consumer, HeapCons};
+use serde::Deserialize;
+
+use crate::{
+    audio::{
+        engine_stats::{XrunKind, ENGINE_STATS},
+        render_thread::RENDER_THREAD,
+        transport::TRANSPORT,
+        wav_writer::{WavBitDepth, WavFileWriter},
+    },
+    core::types::EngineSampleFormat,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum OutputCaptureTarget {
+    Buffer,
+    Wav {
+        file_path: String,
+        bit_depth: WavBitDepth,
+    },
+}
+
+/// Collects everything the null output "plays", either in memory or into a WAV file.
+pub struct OutputCapture {
+    samples: Vec<EngineSampleFormat>,
+    wav_writer: Option<WavFileWriter>,
+}
+
+impl OutputCapture {
+    pub fn new(
+        target: OutputCaptureTarget,
+        num_channels: usize,
+        sample_rate: usize,
+    ) -> Result<Self> {
+        let wav_writer = match target {
+            OutputCaptureTarget::Buffer => None,
+            OutputCaptureTarget::Wav {
+                file_path,
+                bit_depth,
+            } => Some(WavFileWriter::create(
+                file_path,
+                bit_depth,
+                num_channels,
+                sample_rate,
+            )?),
+        };
+        Ok(Self {
+            samples: Vec::new(),
+            wav_writer,
+        })
+    }
+
+    fn write(&mut self, samples: &[EngineSampleFormat]) {
+        match self.wav_writer.as_mut() {
+            Some(wav_writer) => {
+                if let Err(e) = wav_writer.write_samples(samples) {
+                    error!("Null output capture: {e}");
+                }
+            }
+            None => self.samples.extend_from_slice(samples),
+        }
+    }
+
+    /// Closes the capture and returns the samples collected in memory (empty for WAV captures).
+    pub fn finish(self) -> Result<Vec<EngineSampleFormat>> {
+        if let Some(wav_writer) = self.wav_writer {
+            wav_writer.finalize()?;
+        }
+        Ok(self.samples)
+    }
+}
+
+/// Stand-in for a cpal stream when there is no sound card.
+/// A timer thread drains the ring buffers at the rate a real device would.
+pub struct NullStream {
+    is_running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl NullStream {
+    pub fn start(
+        sample_rate: usize,
+        num_channels: usize,
+        buffer_size: usize,
+        mut engine_consumer: HeapCons<f32>,
+        mut preview_consumer: HeapCons<f32>,
+        mut monitor_consumer: HeapCons<f32>,
+        capture: Arc<Mutex<Option<OutputCapture>>>,
+    ) -> Self {
+        let is_running = Arc::new(AtomicBool::new(true));
+        let thread_is_running = Arc::clone(&is_running);
+        let period = Duration::from_secs_f64(buffer_size as f64 / sample_rate as f64);
+
+        let handle = thread::spawn(move || {
+            info!("Null output started: {sample_rate} Hz, {buffer_size} frames");
+            let mut output = vec![0.0f32; buffer_size * num_channels];
+            let mut preview_output = vec![0.0f32; buffer_size * num_channels];
+            let mut monitor_output = vec![0.0f32; buffer_size * num_channels];
+            let mut next_deadline = Instant::now();
+
+            while thread_is_running.load(Ordering::SeqCst) {
+                output.fill(0.0);
+                preview_output.fill(0.0);
+                monitor_output.fill(0.0);
+                let engine_len = engine_consumer.pop_slice(&mut output);
+                if engine_len < output.len() && TRANSPORT.is_streaming() {
+                    ENGINE_STATS.record_xrun(XrunKind::Underrun);
+                }
+                preview_consumer.pop_slice(&mut preview_output);
+                monitor_consumer.pop_slice(&mut monitor_output);
+                for ((sample, preview_sample), monitor_sample) in output
+                    .iter_mut()
+                    .zip(preview_output.iter())
+                    .zip(monitor_output.iter())
+                {
+                    *sample += *preview_sample + *monitor_sample;
+                }
+                RENDER_THREAD.wake();
+
+                if let Some(capture) = capture.lock().unwrap().as_mut() {
+                    capture.write(&output);
+                }
+
+                next_deadline += period;
+                let now = Instant::now();
+                if next_deadline > now {
+                    thread::sleep(next_deadline - now);
+                } else {
+                    next_deadline = now;
+                }
+            }
+            info!("Null output stopped");
+        });
+
+        Self {
+            is_running,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for NullStream {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ringbuf::{
+        traits::{Producer, Split},
+        HeapRb,
+    };
+
+    use super::*;
+    use crate::audio::{renderer::Renderer, snapshot::test_fixtures::clip_snapshot};
+
+    const SAMPLE_RATE: usize = 48000;
+    const NUM_CHANNELS: usize = 2;
+    const BUFFER_FRAMES: usize = 128;
+    const BLOCK_SAMPLES: usize = BUFFER_FRAMES * NUM_CHANNELS;
+    const NUM_BLOCKS: usize = 5;
+    const CLIP_START: usize = BLOCK_SAMPLES + 64;
+    const CLIP_LEN: usize = 600;
+    const SOURCE_OFFSET: usize = 8;
+
+    #[test]
+    fn plays_rendered_clip_unchanged() {
+        // Steps of 1/1024 are exact in f32, so any gain or offset error shows up
+        let source: Vec<f32> = (0..CLIP_LEN + SOURCE_OFFSET)
+            .map(|index| (index as f32 + 1.0) / 1024.0)
+            .collect();
+        let snapshot = clip_snapshot(&source, SAMPLE_RATE, CLIP_START, SOURCE_OFFSET);
+
+        let (mut engine_producer, engine_consumer) =
+            HeapRb::<f32>::new(BLOCK_SAMPLES * NUM_BLOCKS).split();
+        let mut renderer = Renderer::new(BLOCK_SAMPLES).with_private_buffers();
+        let mut position_samples = 0;
+        for _ in 0..NUM_BLOCKS {
+            let (block, next_position_samples) =
+                renderer.render(&snapshot, position_samples, None, None);
+            assert_eq!(engine_producer.push_slice(block), BLOCK_SAMPLES);
+            position_samples = next_position_samples;
+        }
+
+        let capture = Arc::new(Mutex::new(Some(
+            OutputCapture::new(OutputCaptureTarget::Buffer, NUM_CHANNELS, SAMPLE_RATE).unwrap(),
+        )));
+        let stream = NullStream::start(
+            SAMPLE_RATE,
+            NUM_CHANNELS,
+            BUFFER_FRAMES,
+            engine_consumer,
+            HeapRb::<f32>::new(BLOCK_SAMPLES).split().1,
+            HeapRb::<f32>::new(BLOCK_SAMPLES).split().1,
+            Arc::clone(&capture),
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let captured_len = || capture.lock().unwrap().as_ref().unwrap().samples.len();
+        while captured_len() < BLOCK_SAMPLES * NUM_BLOCKS {
+            assert!(Instant::now() < deadline, "null output stalled");
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(stream);
+        let captured = capture.lock().unwrap().take().unwrap().finish().unwrap();
+
+        let expected: Vec<f32> = (0..BLOCK_SAMPLES * NUM_BLOCKS)
+            .map(|index| match index {
+                index if (CLIP_START..CLIP_START + CLIP_LEN).contains(&index) => {
+                    source[index - CLIP_START + SOURCE_OFFSET]
+                }
+                _ => 0.0,
+            })
+            .collect();
+        assert_eq!(&captured[..expected.len()], &expected[..]);
+        // Once the ring is drained the output is silent
+        assert!(captured[expected.len()..]
+            .iter()
+            .all(|sample| *sample == 0.0));
+    }
+}
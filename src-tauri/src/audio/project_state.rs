@@ -1,23 +1,36 @@
-use anyhow::Error;
-use atomic_float::AtomicF32;
+use anyhow::{bail, Context, Error, Result};
+use arc_swap::ArcSwap;
 use indexmap::IndexMap;
 use log::info;
-use std::path::Path;
-use std::sync::atomic::AtomicU16;
-use std::sync::{atomic::Ordering, LazyLock, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU16};
+use std::sync::{atomic::Ordering, Arc, LazyLock, Mutex};
 use tauri::async_runtime;
 
 use crate::audio::asset_pool::ASSET_POOL;
 use crate::audio::clip::{Clip, ClipToInsert};
 use crate::audio::decoder::decode_audio_file;
+use crate::audio::effects::audio_effect::{EffectKind, InsertEffect};
+use crate::audio::effects::impulse_response::IMPULSE_RESPONSES;
 use crate::audio::engine::AUDIO_ENGINE;
+use crate::audio::gain::PanLaw;
+use crate::audio::meter_map::{BarBeatTick, MeterEvent, MeterMap};
 use crate::audio::preview_mixer::PREVIEW_MIXER;
+use crate::audio::recorder::RecordedTake;
+use crate::audio::snapshot::data_nodes::DataNodes;
+use crate::audio::snapshot::mix_state::MIX_STATES;
 use crate::audio::snapshot::project_snapshot::{
-    rebuild_data_nodes, rebuild_render_graph, rebuild_scheduler,
+    rebuild_data_nodes, rebuild_render_graph, rebuild_scheduler, set_project_meter_map,
+    set_project_tempo_map,
 };
+use crate::audio::snapshot::render_graph::{RenderGraph, Routing};
+use crate::audio::snapshot::solo::SoloState;
+use crate::audio::tempo_map::{TempoCurve, TempoEvent, TempoMap};
 use crate::audio::track::{
-    default_track_name, AudioTrack, BusTrack, GeneratorTrack, MasterTrack, SamplerTrack,
+    default_bus_name, default_track_name, AudioTrack, BusTrack, GeneratorTrack, MasterTrack,
+    SamplerTrack, SendAmount, SendTap, TrackSolo,
 };
+use crate::audio::transport::TRANSPORT;
 use crate::core::constants::{
     PPQ_DEFAULT, TEMPO_BPM_DEFAULT, TIME_SIGNATURE_DENOMINATOR_DEFAULT,
     TIME_SIGNATURE_NUMERATOR_DEFAULT,
@@ -27,11 +40,16 @@ use crate::core::types::Id;
 
 pub struct ProjectState {
     ppq: AtomicU16,
-    tempo_bpm: AtomicF32,
-    time_signature: (u8, u8),
+    // Swapped as a whole on edits so the render thread can read it without locking
+    tempo_map: ArcSwap<TempoMap>,
+    meter_map: ArcSwap<MeterMap>,
+    pan_law: Mutex<PanLaw>,
+    exclusive_solo: AtomicBool,
     master: Mutex<MasterTrack>,
     tracks: Mutex<IndexMap<Id, GeneratorTrack>>,
     buses: Mutex<IndexMap<Id, BusTrack>>,
+    // Where the project lives on disk, recorded takes are written below it
+    directory: Mutex<Option<PathBuf>>,
 }
 
 impl ProjectState {
@@ -43,7 +61,7 @@ impl ProjectState {
             .to_string()
     }
 
-    async fn ensure_audio_asset(&self, source_path: String) -> Option<(Id, usize, String)> {
+    pub async fn ensure_audio_asset(&self, source_path: String) -> Option<(Id, usize, String)> {
         if let Some(existing_id) = ASSET_POOL.audio.get_id_by_path(&source_path) {
             let num_samples = ASSET_POOL
                 .audio
@@ -74,37 +92,178 @@ impl ProjectState {
         }
     }
 
-    fn calc_clip_length_ppq(&self, num_samples: usize) -> usize {
+    fn calc_clip_length_ppq(&self, start_ppq: usize, num_samples: usize) -> usize {
         let engine_channels = AUDIO_ENGINE.num_channels();
         let engine_sample_rate = AUDIO_ENGINE.sample_rate();
 
         let frames = (num_samples / engine_channels) as f64;
-        let sr = engine_sample_rate as f64;
-        let bpm = self.tempo_bpm.load(Ordering::SeqCst) as f64;
-        let ppq = self.ppq.load(Ordering::SeqCst) as f64;
-        let length_ppq = ((frames * bpm * ppq) / (sr * 60.0)).round() as usize;
-        length_ppq
+        let seconds = frames / engine_sample_rate as f64;
+        self.tempo_map
+            .load()
+            .seconds_to_length_ppq(start_ppq, seconds)
+    }
+
+    /// Converts a ppq position into an interleaved sample position at the engine rate.
+    /// Lock-free, also used by the render thread.
+    pub fn ppq_to_samples(&self, position_ppq: usize) -> usize {
+        self.tempo_map.load().ppq_to_samples(
+            position_ppq,
+            AUDIO_ENGINE.sample_rate(),
+            AUDIO_ENGINE.num_channels(),
+        )
+    }
+
+    /// Converts an interleaved sample position at the engine rate into a ppq position.
+    /// Lock-free, also used by the render thread.
+    pub fn samples_to_ppq(&self, position_samples: usize) -> usize {
+        self.tempo_map.load().samples_to_ppq(
+            position_samples,
+            AUDIO_ENGINE.sample_rate(),
+            AUDIO_ENGINE.num_channels(),
+        )
+    }
+
+    /// Splits a ppq position into 1-based bars and beats plus ticks into the beat,
+    /// following the time signature changes. Lock-free.
+    pub fn ppq_to_bar_beat_tick(&self, position_ppq: usize) -> BarBeatTick {
+        self.meter_map.load().ppq_to_bar_beat_tick(position_ppq)
+    }
+
+    pub fn bar_beat_tick_to_ppq(&self, position: BarBeatTick) -> usize {
+        self.meter_map.load().bar_beat_tick_to_ppq(position)
     }
 
     pub fn ppq(&self) -> u16 {
         self.ppq.load(Ordering::SeqCst)
     }
 
-    pub fn tempo_bpm(&self) -> f32 {
-        self.tempo_bpm.load(Ordering::SeqCst)
+    pub fn tempo_bpm_at(&self, position_ppq: usize) -> f32 {
+        self.tempo_map.load().bpm_at(position_ppq)
+    }
+
+    pub fn tempo_events(&self) -> Vec<TempoEvent> {
+        self.tempo_map.load().events().to_vec()
+    }
+
+    /// Applies an edit to the tempo map. Clips keep their ppq positions, so the scheduler
+    /// is rebuilt and the playhead stays on the same beat.
+    fn update_tempo_map<R>(&self, f: impl Fn(&TempoMap) -> Result<(TempoMap, R)>) -> Result<R> {
+        let mut result = None;
+        self.tempo_map.rcu(|current| match f(current) {
+            Ok((tempo_map, value)) => {
+                result = Some(Ok(value));
+                Arc::new(tempo_map)
+            }
+            Err(e) => {
+                result = Some(Err(e));
+                Arc::clone(current)
+            }
+        });
+        let value = result.expect("rcu always runs the update at least once")?;
+        set_project_tempo_map(self.tempo_map.load_full());
+        TRANSPORT.follow_tempo_change();
+        rebuild_scheduler();
+        Ok(value)
+    }
+
+    pub fn add_tempo_event(
+        &self,
+        position_ppq: usize,
+        bpm: f32,
+        curve: TempoCurve,
+    ) -> Result<TempoEvent> {
+        info!("ProjectState: add_tempo_event at ppq {position_ppq}: {bpm} bpm {curve:?}");
+        self.update_tempo_map(|tempo_map| tempo_map.with_added_event(position_ppq, bpm, curve))
+    }
+
+    pub fn update_tempo_event(
+        &self,
+        event_id: &str,
+        position_ppq: usize,
+        bpm: f32,
+        curve: TempoCurve,
+    ) -> Result<TempoEvent> {
+        info!("ProjectState: update_tempo_event {event_id}");
+        self.update_tempo_map(|tempo_map| {
+            tempo_map.with_updated_event(event_id, position_ppq, bpm, curve)
+        })
+    }
+
+    pub fn remove_tempo_event(&self, event_id: &str) -> Result<()> {
+        info!("ProjectState: remove_tempo_event {event_id}");
+        self.update_tempo_map(|tempo_map| Ok((tempo_map.with_removed_event(event_id)?, ())))
+    }
+
+    /// Ticks per beat and beats per bar at a position.
+    pub fn beat_grid_at(&self, position_ppq: usize) -> (usize, usize) {
+        self.meter_map.load().beat_grid_at(position_ppq)
+    }
+
+    pub fn meter_events(&self) -> Vec<MeterEvent> {
+        self.meter_map.load().events().to_vec()
+    }
+
+    /// Applies an edit to the meter map. Clips are placed in ppq, so nothing needs rescheduling.
+    fn update_meter_map<R>(&self, f: impl Fn(&MeterMap) -> Result<(MeterMap, R)>) -> Result<R> {
+        let mut result = None;
+        self.meter_map.rcu(|current| match f(current) {
+            Ok((meter_map, value)) => {
+                result = Some(Ok(value));
+                Arc::new(meter_map)
+            }
+            Err(e) => {
+                result = Some(Err(e));
+                Arc::clone(current)
+            }
+        });
+        let value = result.expect("rcu always runs the update at least once")?;
+        set_project_meter_map(self.meter_map.load_full());
+        Ok(value)
+    }
+
+    pub fn add_meter_event(
+        &self,
+        bar: usize,
+        numerator: u8,
+        denominator: u8,
+    ) -> Result<MeterEvent> {
+        info!("ProjectState: add_meter_event at bar {bar}: {numerator}/{denominator}");
+        self.update_meter_map(|meter_map| meter_map.with_added_event(bar, numerator, denominator))
+    }
+
+    pub fn update_meter_event(
+        &self,
+        event_id: &str,
+        bar: usize,
+        numerator: u8,
+        denominator: u8,
+    ) -> Result<MeterEvent> {
+        info!("ProjectState: update_meter_event {event_id}");
+        self.update_meter_map(|meter_map| {
+            meter_map.with_updated_event(event_id, bar, numerator, denominator)
+        })
+    }
+
+    pub fn remove_meter_event(&self, event_id: &str) -> Result<()> {
+        info!("ProjectState: remove_meter_event {event_id}");
+        self.update_meter_map(|meter_map| Ok((meter_map.with_removed_event(event_id)?, ())))
     }
 
     pub fn new() -> Self {
         Self {
             ppq: AtomicU16::new(PPQ_DEFAULT),
-            tempo_bpm: AtomicF32::new(TEMPO_BPM_DEFAULT),
-            time_signature: (
+            tempo_map: ArcSwap::from_pointee(TempoMap::new(PPQ_DEFAULT, TEMPO_BPM_DEFAULT)),
+            meter_map: ArcSwap::from_pointee(MeterMap::new(
+                PPQ_DEFAULT,
                 TIME_SIGNATURE_NUMERATOR_DEFAULT,
                 TIME_SIGNATURE_DENOMINATOR_DEFAULT,
-            ),
+            )),
+            pan_law: Mutex::new(PanLaw::default()),
+            exclusive_solo: AtomicBool::new(false),
             master: Mutex::new(MasterTrack::new()),
             tracks: Mutex::new(IndexMap::new()),
             buses: Mutex::new(IndexMap::new()),
+            directory: Mutex::new(None),
         }
     }
 
@@ -155,7 +314,7 @@ impl ProjectState {
     pub async fn add_audio_track_with_clip(&self, clip: ClipToInsert) -> Option<AudioTrack> {
         print!("ProjectState: add_audio_track_with_clip: {:?}", clip);
         let (asset_id, num_samples, clip_name) = self.ensure_audio_asset(clip.source_path).await?;
-        let length_ppq = self.calc_clip_length_ppq(num_samples);
+        let length_ppq = self.calc_clip_length_ppq(clip.start_ppq, num_samples);
 
         let mut tracks = self.tracks.lock().unwrap();
         let mut track = AudioTrack::new(default_track_name(tracks.len() + 1));
@@ -180,7 +339,7 @@ impl ProjectState {
     pub async fn add_clip_to_audio_track(&self, clip: ClipToInsert) -> Option<Clip> {
         info!("ProjectState: add_clip_to_audio_track: {:?}", clip);
         let (asset_id, num_samples, clip_name) = self.ensure_audio_asset(clip.source_path).await?;
-        let length_ppq = self.calc_clip_length_ppq(num_samples);
+        let length_ppq = self.calc_clip_length_ppq(clip.start_ppq, num_samples);
 
         let mut tracks = self.tracks.lock().unwrap();
 
@@ -313,6 +472,671 @@ impl ProjectState {
         }
     }
 
+    /// Fader changes only touch the track's mix state, the snapshot isn't rebuilt.
+    fn with_track_mix_mut(
+        &self,
+        track_id: &str,
+        f: impl FnOnce(&mut GeneratorTrack),
+    ) -> Option<GeneratorTrack> {
+        let mut tracks = self.tracks.lock().unwrap();
+        let Some(track) = tracks.get_mut(track_id) else {
+            log_and_notify_error(format!("Track not found: {track_id}"));
+            return None;
+        };
+        f(track);
+        MIX_STATES
+            .get(track_id)
+            .set(track.volume(), track.pan(), track.muted());
+        Some(track.clone())
+    }
+
+    pub fn set_track_volume(&self, track_id: &str, volume: f32) -> Option<GeneratorTrack> {
+        self.with_track_mix_mut(track_id, |track| track.set_volume(volume.clamp(0.0, 1.0)))
+    }
+
+    pub fn set_track_pan(&self, track_id: &str, pan: f32) -> Option<GeneratorTrack> {
+        self.with_track_mix_mut(track_id, |track| track.set_pan(pan.clamp(-1.0, 1.0)))
+    }
+
+    pub fn set_track_muted(&self, track_id: &str, muted: bool) -> Option<GeneratorTrack> {
+        self.with_track_mix_mut(track_id, |track| track.set_muted(muted))
+    }
+
+    fn with_master_mix_mut(&self, f: impl FnOnce(&mut MasterTrack)) -> MasterTrack {
+        let mut master = self.master.lock().unwrap();
+        f(&mut master);
+        MIX_STATES
+            .get(DataNodes::MASTER_NODE_ID)
+            .set(master.volume, master.pan, master.muted);
+        master.clone()
+    }
+
+    pub fn set_master_volume(&self, volume: f32) -> MasterTrack {
+        self.with_master_mix_mut(|master| master.volume = volume.clamp(0.0, 1.0))
+    }
+
+    pub fn set_master_pan(&self, pan: f32) -> MasterTrack {
+        self.with_master_mix_mut(|master| master.pan = pan.clamp(-1.0, 1.0))
+    }
+
+    pub fn set_master_muted(&self, muted: bool) -> MasterTrack {
+        self.with_master_mix_mut(|master| master.muted = muted)
+    }
+
+    fn solo_states(
+        tracks: &IndexMap<Id, GeneratorTrack>,
+        buses: &IndexMap<Id, BusTrack>,
+    ) -> Vec<TrackSolo> {
+        let track_solos = tracks.iter().map(|(id, track)| TrackSolo {
+            id: id.clone(),
+            soloed: track.soloed(),
+        });
+        let bus_solos = buses.iter().map(|(id, bus)| TrackSolo {
+            id: id.clone(),
+            soloed: bus.soloed,
+        });
+        track_solos.chain(bus_solos).collect()
+    }
+
+    /// Sets the solo flag of `id` through `set_soloed`, which returns `None` when it doesn't exist.
+    /// Soloing with exclusive solo on unsolos every other track and bus, so the solo state of
+    /// all of them is returned.
+    fn with_solo_mut(
+        &self,
+        id: &str,
+        soloed: bool,
+        set_soloed: impl FnOnce(
+            &mut IndexMap<Id, GeneratorTrack>,
+            &mut IndexMap<Id, BusTrack>,
+        ) -> Option<()>,
+    ) -> Option<Vec<TrackSolo>> {
+        let mut tracks = self.tracks.lock().unwrap();
+        let mut buses = self.buses.lock().unwrap();
+        set_soloed(&mut tracks, &mut buses)?;
+        if soloed && self.exclusive_solo() {
+            tracks
+                .iter_mut()
+                .filter(|(track_id, _)| *track_id != id)
+                .for_each(|(_, track)| track.set_soloed(false));
+            buses
+                .iter_mut()
+                .filter(|(bus_id, _)| *bus_id != id)
+                .for_each(|(_, bus)| bus.soloed = false);
+        }
+        rebuild_data_nodes();
+        Some(Self::solo_states(&tracks, &buses))
+    }
+
+    pub fn set_track_soloed(&self, track_id: &str, soloed: bool) -> Option<Vec<TrackSolo>> {
+        self.with_solo_mut(track_id, soloed, |tracks, _| {
+            let Some(track) = tracks.get_mut(track_id) else {
+                log_and_notify_error(format!("Track not found: {track_id}"));
+                return None;
+            };
+            track.set_soloed(soloed);
+            Some(())
+        })
+    }
+
+    pub fn set_bus_soloed(&self, bus_id: &str, soloed: bool) -> Option<Vec<TrackSolo>> {
+        self.with_solo_mut(bus_id, soloed, |_, buses| {
+            let Some(bus) = buses.get_mut(bus_id) else {
+                log_and_notify_error(format!("Bus not found: {bus_id}"));
+                return None;
+            };
+            bus.soloed = soloed;
+            Some(())
+        })
+    }
+
+    pub fn set_bus_solo_safe(&self, bus_id: &str, solo_safe: bool) -> Option<BusTrack> {
+        let mut buses = self.buses.lock().unwrap();
+        let Some(bus) = buses.get_mut(bus_id) else {
+            log_and_notify_error(format!("Bus not found: {bus_id}"));
+            return None;
+        };
+        bus.solo_safe = solo_safe;
+        rebuild_data_nodes();
+        Some(bus.clone())
+    }
+
+    pub fn exclusive_solo(&self) -> bool {
+        self.exclusive_solo.load(Ordering::SeqCst)
+    }
+
+    pub fn set_exclusive_solo(&self, exclusive_solo: bool) {
+        self.exclusive_solo.store(exclusive_solo, Ordering::SeqCst);
+    }
+
+    pub fn directory(&self) -> Option<PathBuf> {
+        self.directory.lock().unwrap().clone()
+    }
+
+    pub fn set_directory(&self, directory: Option<PathBuf>) {
+        *self.directory.lock().unwrap() = directory;
+    }
+
+    fn collect_routing(
+        tracks: &IndexMap<Id, GeneratorTrack>,
+        buses: &IndexMap<Id, BusTrack>,
+    ) -> Routing {
+        let track_outputs = tracks
+            .iter()
+            .map(|(id, track)| (id.clone(), track.output_id().cloned()))
+            .collect();
+        let bus_outputs = buses
+            .iter()
+            .map(|(id, bus)| (id.clone(), bus.output_id.clone()))
+            .collect();
+        let sends = tracks
+            .iter()
+            .flat_map(|(id, track)| {
+                track
+                    .sends()
+                    .iter()
+                    .map(|send| (id.clone(), send.bus_id.clone(), send.tap))
+            })
+            .collect();
+        Routing {
+            track_outputs,
+            bus_outputs,
+            sends,
+        }
+    }
+
+    /// Outputs and sends of every track and every bus.
+    pub fn routing(&self) -> Routing {
+        let tracks = self.tracks.lock().unwrap();
+        let buses = self.buses.lock().unwrap();
+        Self::collect_routing(&tracks, &buses)
+    }
+
+    /// Signal flow into buses, from source to destination, sends included.
+    fn routing_edges(&self) -> Vec<(Id, Id)> {
+        let routing = self.routing();
+        routing
+            .track_outputs
+            .into_iter()
+            .chain(routing.bus_outputs)
+            .filter_map(|(id, output_id)| Some((id, output_id?)))
+            .chain(
+                routing
+                    .sends
+                    .into_iter()
+                    .map(|(id, bus_id, _)| (id, bus_id)),
+            )
+            .collect()
+    }
+
+    pub fn add_bus(&self) -> BusTrack {
+        info!("ProjectState: add_bus");
+        let mut buses = self.buses.lock().unwrap();
+        let bus = BusTrack::new(default_bus_name(buses.len() + 1));
+        buses.insert(bus.id.clone(), bus.clone());
+        rebuild_render_graph();
+        rebuild_data_nodes();
+        bus
+    }
+
+    /// Tracks and buses that were routed to the deleted bus go to the master,
+    /// sends to it are removed.
+    pub fn delete_bus(&self, bus_id: &str) {
+        info!("ProjectState: delete_bus: {bus_id}");
+        let mut tracks = self.tracks.lock().unwrap();
+        let mut buses = self.buses.lock().unwrap();
+        if buses.shift_remove(bus_id).is_none() {
+            log_and_notify_error(format!("Bus not found: {bus_id}"));
+            return;
+        }
+        for track in tracks.values_mut() {
+            if track
+                .output_id()
+                .is_some_and(|output_id| output_id == bus_id)
+            {
+                track.set_output_id(None);
+            }
+            track.sends_mut().retain(|send| send.bus_id != bus_id);
+        }
+        for bus in buses.values_mut() {
+            if bus.output_id.as_deref() == Some(bus_id) {
+                bus.output_id = None;
+            }
+        }
+        rebuild_render_graph();
+        rebuild_data_nodes();
+    }
+
+    pub fn rename_bus(&self, bus_id: &str, name: String) -> Option<BusTrack> {
+        let mut buses = self.buses.lock().unwrap();
+        let Some(bus) = buses.get_mut(bus_id) else {
+            log_and_notify_error(format!("Bus not found: {bus_id}"));
+            return None;
+        };
+        bus.name = name;
+        Some(bus.clone())
+    }
+
+    /// Checks a routing change against the current routing before applying it.
+    fn check_output(
+        tracks: &IndexMap<Id, GeneratorTrack>,
+        buses: &IndexMap<Id, BusTrack>,
+        id: &str,
+        output_id: Option<&Id>,
+    ) -> Result<()> {
+        if let Some(output_id) = output_id {
+            if !buses.contains_key(output_id) {
+                bail!("Bus not found: {output_id}");
+            }
+        }
+        let mut routing = Self::collect_routing(tracks, buses);
+        for (source_id, source_output_id) in routing
+            .track_outputs
+            .iter_mut()
+            .chain(routing.bus_outputs.iter_mut())
+        {
+            if source_id == id {
+                *source_output_id = output_id.cloned();
+            }
+        }
+        RenderGraph::from_routing(&routing)?;
+        Ok(())
+    }
+
+    /// Routes a track into a bus, or the master with `None`.
+    pub fn set_track_output(&self, track_id: &str, output_id: Option<Id>) -> Result<()> {
+        info!("ProjectState: set_track_output: {track_id} -> {output_id:?}");
+        let mut tracks = self.tracks.lock().unwrap();
+        let buses = self.buses.lock().unwrap();
+        if !tracks.contains_key(track_id) {
+            bail!("Track not found: {track_id}");
+        }
+        Self::check_output(&tracks, &buses, track_id, output_id.as_ref())?;
+        if let Some(track) = tracks.get_mut(track_id) {
+            track.set_output_id(output_id);
+        }
+        rebuild_render_graph();
+        // Solo follows routing
+        rebuild_data_nodes();
+        Ok(())
+    }
+
+    /// Routes a bus into another bus, or the master with `None`. Feedback loops are rejected.
+    pub fn set_bus_output(&self, bus_id: &str, output_id: Option<Id>) -> Result<BusTrack> {
+        info!("ProjectState: set_bus_output: {bus_id} -> {output_id:?}");
+        let tracks = self.tracks.lock().unwrap();
+        let mut buses = self.buses.lock().unwrap();
+        if !buses.contains_key(bus_id) {
+            bail!("Bus not found: {bus_id}");
+        }
+        Self::check_output(&tracks, &buses, bus_id, output_id.as_ref())?;
+        let bus = buses.get_mut(bus_id).context("Bus not found")?;
+        bus.output_id = output_id;
+        rebuild_render_graph();
+        rebuild_data_nodes();
+        Ok(bus.clone())
+    }
+
+    /// Adds a send from a track to a bus, or updates the existing one.
+    /// Only a new send or tap point changes the render graph, levels are in the data nodes.
+    pub fn set_track_send(
+        &self,
+        track_id: &str,
+        bus_id: &str,
+        amount: f32,
+        tap: SendTap,
+    ) -> Result<Vec<SendAmount>> {
+        info!("ProjectState: set_track_send: {track_id} -> {bus_id} {amount} {tap:?}");
+        let mut tracks = self.tracks.lock().unwrap();
+        let buses = self.buses.lock().unwrap();
+        if !buses.contains_key(bus_id) {
+            bail!("Bus not found: {bus_id}");
+        }
+        let track = tracks
+            .get_mut(track_id)
+            .with_context(|| format!("Track not found: {track_id}"))?;
+        let amount = amount.clamp(0.0, 1.0);
+        let sends = track.sends_mut();
+        let changes_graph = match sends.iter_mut().find(|send| send.bus_id == bus_id) {
+            Some(send) => {
+                let changes_graph = send.tap != tap;
+                send.amount = amount;
+                send.tap = tap;
+                changes_graph
+            }
+            None => {
+                sends.push(SendAmount {
+                    bus_id: bus_id.to_string(),
+                    amount,
+                    tap,
+                });
+                true
+            }
+        };
+        let sends = sends.clone();
+        if changes_graph {
+            rebuild_render_graph();
+        }
+        rebuild_data_nodes();
+        Ok(sends)
+    }
+
+    pub fn remove_track_send(&self, track_id: &str, bus_id: &str) -> Result<Vec<SendAmount>> {
+        info!("ProjectState: remove_track_send: {track_id} -> {bus_id}");
+        let mut tracks = self.tracks.lock().unwrap();
+        let track = tracks
+            .get_mut(track_id)
+            .with_context(|| format!("Track not found: {track_id}"))?;
+        let sends = track.sends_mut();
+        let len = sends.len();
+        sends.retain(|send| send.bus_id != bus_id);
+        if sends.len() == len {
+            bail!("Send not found: {track_id} -> {bus_id}");
+        }
+        let sends = sends.clone();
+        rebuild_render_graph();
+        rebuild_data_nodes();
+        Ok(sends)
+    }
+
+    /// Runs `f` on the insert chain of a track or bus, or the master's with `None`,
+    /// and rebuilds the data nodes when it succeeds.
+    fn with_inserts_mut<R>(
+        &self,
+        track_id: Option<&str>,
+        f: impl FnOnce(&mut Vec<InsertEffect>) -> Result<R>,
+    ) -> Result<R> {
+        let result = match track_id {
+            None => f(&mut self.master.lock().unwrap().inserts),
+            Some(track_id) => {
+                let mut tracks = self.tracks.lock().unwrap();
+                match tracks.get_mut(track_id) {
+                    Some(track) => f(track.inserts_mut()),
+                    None => {
+                        let mut buses = self.buses.lock().unwrap();
+                        let bus = buses
+                            .get_mut(track_id)
+                            .with_context(|| format!("Track not found: {track_id}"))?;
+                        f(&mut bus.inserts)
+                    }
+                }
+            }
+        }?;
+        rebuild_data_nodes();
+        Ok(result)
+    }
+
+    fn find_insert<'a>(
+        inserts: &'a mut [InsertEffect],
+        effect_id: &str,
+    ) -> Result<&'a mut InsertEffect> {
+        inserts
+            .iter_mut()
+            .find(|insert| insert.id == effect_id)
+            .with_context(|| format!("Effect not found: {effect_id}"))
+    }
+
+    pub fn inserts(&self, track_id: Option<&str>) -> Result<Vec<InsertEffect>> {
+        let Some(track_id) = track_id else {
+            return Ok(self.master.lock().unwrap().inserts.clone());
+        };
+        let tracks = self.tracks.lock().unwrap();
+        if let Some(track) = tracks.get(track_id) {
+            return Ok(track.inserts().to_vec());
+        }
+        let buses = self.buses.lock().unwrap();
+        let bus = buses
+            .get(track_id)
+            .with_context(|| format!("Track not found: {track_id}"))?;
+        Ok(bus.inserts.clone())
+    }
+
+    pub fn insert(&self, track_id: Option<&str>, effect_id: &str) -> Result<InsertEffect> {
+        self.inserts(track_id)?
+            .into_iter()
+            .find(|insert| insert.id == effect_id)
+            .with_context(|| format!("Effect not found: {effect_id}"))
+    }
+
+    /// Adds an effect at `index` in the chain, or at its end.
+    pub fn add_insert(
+        &self,
+        track_id: Option<&str>,
+        kind: EffectKind,
+        index: Option<usize>,
+    ) -> Result<InsertEffect> {
+        info!("ProjectState: add_insert: {track_id:?} {kind:?}");
+        self.with_inserts_mut(track_id, |inserts| {
+            let insert = InsertEffect::new(kind);
+            let index = index.unwrap_or(inserts.len()).min(inserts.len());
+            inserts.insert(index, insert.clone());
+            Ok(insert)
+        })
+    }
+
+    pub fn remove_insert(&self, track_id: Option<&str>, effect_id: &str) -> Result<()> {
+        info!("ProjectState: remove_insert: {track_id:?} {effect_id}");
+        self.with_inserts_mut(track_id, |inserts| {
+            let len = inserts.len();
+            inserts.retain(|insert| insert.id != effect_id);
+            if inserts.len() == len {
+                bail!("Effect not found: {effect_id}");
+            }
+            Ok(())
+        })
+    }
+
+    pub fn move_insert(
+        &self,
+        track_id: Option<&str>,
+        effect_id: &str,
+        index: usize,
+    ) -> Result<Vec<InsertEffect>> {
+        info!("ProjectState: move_insert: {track_id:?} {effect_id} -> {index}");
+        self.with_inserts_mut(track_id, |inserts| {
+            let from = inserts
+                .iter()
+                .position(|insert| insert.id == effect_id)
+                .with_context(|| format!("Effect not found: {effect_id}"))?;
+            let insert = inserts.remove(from);
+            inserts.insert(index.min(inserts.len()), insert);
+            Ok(inserts.clone())
+        })
+    }
+
+    /// Sets a parameter by its id, clamped to the parameter's range.
+    pub fn set_insert_parameter(
+        &self,
+        track_id: Option<&str>,
+        effect_id: &str,
+        parameter_id: &str,
+        value: f32,
+    ) -> Result<InsertEffect> {
+        self.with_inserts_mut(track_id, |inserts| {
+            let insert = Self::find_insert(inserts, effect_id)?;
+            let (index, parameter) = insert
+                .kind
+                .parameters()
+                .iter()
+                .enumerate()
+                .find(|(_, parameter)| parameter.id == parameter_id)
+                .with_context(|| format!("Unknown parameter: {parameter_id}"))?;
+            insert.parameters[index] = value.clamp(parameter.min, parameter.max);
+            Ok(insert.clone())
+        })
+    }
+
+    pub fn set_insert_bypassed(
+        &self,
+        track_id: Option<&str>,
+        effect_id: &str,
+        bypassed: bool,
+    ) -> Result<InsertEffect> {
+        self.with_inserts_mut(track_id, |inserts| {
+            let insert = Self::find_insert(inserts, effect_id)?;
+            insert.bypassed = bypassed;
+            Ok(insert.clone())
+        })
+    }
+
+    /// Sets the audio asset a convolution reverb uses, loading it first so a file that can't
+    /// be used is reported here.
+    pub fn set_insert_impulse_response(
+        &self,
+        track_id: Option<&str>,
+        effect_id: &str,
+        asset_id: Option<&str>,
+    ) -> Result<InsertEffect> {
+        info!("ProjectState: set_insert_impulse_response: {track_id:?} {effect_id} {asset_id:?}");
+        if self.insert(track_id, effect_id)?.kind != EffectKind::ConvolutionReverb {
+            bail!("Only convolution reverbs use an impulse response");
+        }
+        if let Some(asset_id) = asset_id {
+            IMPULSE_RESPONSES.get(asset_id)?;
+        }
+        self.with_inserts_mut(track_id, |inserts| {
+            let insert = Self::find_insert(inserts, effect_id)?;
+            insert.impulse_response_id = asset_id.map(str::to_string);
+            Ok(insert.clone())
+        })
+    }
+
+    pub fn solo_state(&self) -> SoloState {
+        let tracks = self.tracks.lock().unwrap();
+        let buses = self.buses.lock().unwrap();
+        let soloed = tracks
+            .values()
+            .filter(|track| track.soloed())
+            .map(|track| track.id().to_string())
+            .chain(
+                buses
+                    .values()
+                    .filter(|bus| bus.soloed)
+                    .map(|bus| bus.id.clone()),
+            )
+            .collect();
+        let solo_safe = buses
+            .values()
+            .filter(|bus| bus.solo_safe)
+            .map(|bus| bus.id.clone())
+            .collect();
+        let track_ids = tracks.keys().chain(buses.keys()).cloned().collect();
+        drop(buses);
+        drop(tracks);
+        SoloState {
+            soloed,
+            solo_safe,
+            track_ids,
+            edges: self.routing_edges(),
+        }
+    }
+
+    pub fn pan_law(&self) -> PanLaw {
+        *self.pan_law.lock().unwrap()
+    }
+
+    pub fn set_pan_law(&self, pan_law: PanLaw) {
+        info!("ProjectState: set_pan_law {pan_law:?}");
+        *self.pan_law.lock().unwrap() = pan_law;
+        rebuild_data_nodes();
+    }
+
+    fn with_audio_track_mut(
+        &self,
+        track_id: &str,
+        f: impl FnOnce(&mut AudioTrack),
+    ) -> Option<AudioTrack> {
+        let mut tracks = self.tracks.lock().unwrap();
+        let audio = match tracks.get_mut(track_id).and_then(|t| t.as_audio_mut()) {
+            Some(audio) => audio,
+            None => {
+                log_and_notify_error(format!("Audio track not found: {track_id}"));
+                return None;
+            }
+        };
+        f(audio);
+        Some(audio.clone())
+    }
+
+    pub fn set_track_record_armed(&self, track_id: &str, armed: bool) -> Option<AudioTrack> {
+        self.with_audio_track_mut(track_id, |audio| audio.record_armed = armed)
+    }
+
+    pub fn set_track_monitoring(&self, track_id: &str, monitoring: bool) -> Option<AudioTrack> {
+        self.with_audio_track_mut(track_id, |audio| audio.monitoring = monitoring)
+    }
+
+    pub fn armed_track_ids(&self) -> Vec<Id> {
+        self.with_tracks(|tracks| {
+            tracks
+                .values()
+                .filter_map(|t| t.as_audio())
+                .filter(|audio| audio.record_armed)
+                .map(|audio| audio.id.clone())
+                .collect()
+        })
+    }
+
+    /// Input is monitored while any armed track has monitoring enabled.
+    pub fn is_monitoring_input(&self) -> bool {
+        self.with_tracks(|tracks| {
+            tracks
+                .values()
+                .filter_map(|t| t.as_audio())
+                .any(|audio| audio.record_armed && audio.monitoring)
+        })
+    }
+
+    /// Adds a recorded take as a clip on each of its tracks.
+    /// The latency is skipped at the start of the file so the clip lines up with what was heard.
+    pub async fn add_recorded_take(&self, take: RecordedTake) -> Vec<Clip> {
+        info!("ProjectState: add_recorded_take: {:?}", take);
+        let Some((asset_id, num_samples, clip_name)) =
+            self.ensure_audio_asset(take.file_path).await
+        else {
+            return Vec::new();
+        };
+        let source_offset_samples =
+            (take.latency_frames * AUDIO_ENGINE.num_channels()).min(num_samples);
+        let length_ppq =
+            self.calc_clip_length_ppq(take.start_ppq, num_samples - source_offset_samples);
+
+        let mut tracks = self.tracks.lock().unwrap();
+        let mut clips = Vec::new();
+        for track_id in take.track_ids {
+            // The track may have been deleted while recording
+            let Some(audio) = tracks.get_mut(&track_id).and_then(|t| t.as_audio_mut()) else {
+                continue;
+            };
+            let new_clip = Clip::new(
+                audio.id.clone(),
+                clip_name.clone(),
+                source_offset_samples,
+                take.start_ppq,
+                length_ppq,
+                asset_id.clone(),
+            );
+            audio.clips.insert(new_clip.id.clone(), new_clip.clone());
+            clips.push(new_clip);
+        }
+        rebuild_data_nodes();
+        rebuild_scheduler();
+        clips
+    }
+
+    /// Clip source offsets are stored in engine samples, so they have to follow the engine sample rate.
+    pub fn rescale_clip_offsets(&self, from_sample_rate: usize, to_sample_rate: usize) {
+        let channels = AUDIO_ENGINE.num_channels();
+        let ratio = to_sample_rate as f64 / from_sample_rate as f64;
+        let mut tracks = self.tracks.lock().unwrap();
+        for track in tracks.values_mut() {
+            for clip in track.clips_mut().values_mut() {
+                let offset_frames = (clip.source_offset_samples / channels) as f64;
+                clip.source_offset_samples = (offset_frames * ratio).round() as usize * channels;
+            }
+        }
+        rebuild_data_nodes();
+    }
+
     pub fn with_tracks<R>(&self, f: impl FnOnce(&IndexMap<Id, GeneratorTrack>) -> R) -> R {
         let guard = self.tracks.lock().unwrap();
         f(&*guard)
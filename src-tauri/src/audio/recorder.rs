@@ -0,0 +1,428 @@
+use std::{
+    fmt::Debug,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Device, DeviceId, FromSample, Sample, SizedSample, Stream, StreamConfig,
+};
+use log::{error, info};
+use nanoid::nanoid;
+use ringbuf::{
+    traits::{Consumer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+use tauri::{Emitter, Manager};
+
+use crate::{
+    app_handle,
+    audio::{
+        engine::{device_name, open_host, AUDIO_ENGINE},
+        project_state::PROJECT_STATE,
+        wav_writer::{WavBitDepth, WavFileWriter},
+    },
+    core::{
+        constants::{
+            ENGINE_NUM_CHANNELS, NULL_HOST_ID, RECORDINGS_DIR_NAME, RECORDING_FINISHED_EVENT,
+        },
+        notify::log_and_notify_error,
+        settings::SETTINGS,
+        types::{EngineSampleFormat, Id},
+    },
+};
+
+/// How long the writer thread can fall behind before input samples are dropped.
+const RECORD_BUFFER_SECONDS: usize = 2;
+
+/// A finished take, ready to be placed on the armed tracks.
+#[derive(Debug, Clone)]
+pub struct RecordedTake {
+    pub file_path: String,
+    pub start_ppq: usize,
+    pub track_ids: Vec<Id>,
+    pub latency_frames: usize,
+}
+
+struct InputStream {
+    // Only held so the stream keeps running until it is dropped.
+    _stream: Stream,
+    buffer_size: usize,
+    record_consumer: Option<HeapCons<f32>>,
+}
+
+struct RecordingTake {
+    file_path: PathBuf,
+    start_ppq: usize,
+    track_ids: Vec<Id>,
+    latency_frames: usize,
+    writer: JoinHandle<(HeapCons<f32>, Result<()>)>,
+}
+
+/// Captures the selected input device while the transport records.
+/// The input callback only pushes into ring buffers: one drained by a writer thread
+/// into the take's WAV file, one mixed into the output for input monitoring.
+pub struct Recorder {
+    is_recording: Arc<AtomicBool>,
+    is_monitoring: Arc<AtomicBool>,
+    monitor_producer: Arc<Mutex<Option<HeapProd<f32>>>>,
+    input: Mutex<Option<InputStream>>,
+    take: Mutex<Option<RecordingTake>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            is_recording: Arc::new(AtomicBool::new(false)),
+            is_monitoring: Arc::new(AtomicBool::new(false)),
+            monitor_producer: Arc::new(Mutex::new(None)),
+            input: Mutex::new(None),
+            take: Mutex::new(None),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.is_recording.load(Ordering::SeqCst)
+    }
+
+    /// Opens the input device from the settings when a track is armed and closes it when none is.
+    /// Monitoring follows the armed tracks that have it enabled.
+    pub fn sync_with_armed_tracks(&self) -> Result<()> {
+        let has_armed_tracks = !PROJECT_STATE.armed_track_ids().is_empty();
+        self.is_monitoring
+            .store(PROJECT_STATE.is_monitoring_input(), Ordering::SeqCst);
+
+        if has_armed_tracks {
+            self.start_input()
+        } else {
+            if !self.is_recording() {
+                self.stop_input();
+            }
+            Ok(())
+        }
+    }
+
+    /// Reopens a running input stream, e.g. after the output stream or input device changed.
+    pub fn restart_input(&self) -> Result<()> {
+        self.reconnect_monitor();
+        if self.input.lock().unwrap().is_none() {
+            return Ok(());
+        }
+        if self.is_recording() {
+            bail!("Can't change the input device while recording");
+        }
+        self.stop_input();
+        self.start_input()
+    }
+
+    fn start_input(&self) -> Result<()> {
+        let mut input = self.input.lock().unwrap();
+        if input.is_some() {
+            return Ok(());
+        }
+        *input = Some(self.open_input()?);
+        Ok(())
+    }
+
+    /// The engine creates a new monitor ring every time the output stream is rebuilt, the input
+    /// keeps pushing into the old one until it picks up the new producer.
+    fn reconnect_monitor(&self) {
+        if let Some(monitor_producer) = AUDIO_ENGINE.take_monitor_producer() {
+            *self.monitor_producer.lock().unwrap() = Some(monitor_producer);
+        }
+    }
+
+    fn stop_input(&self) {
+        if self.input.lock().unwrap().take().is_some() {
+            info!("Input stream closed");
+        }
+    }
+
+    fn open_input(&self) -> Result<InputStream> {
+        let settings = SETTINGS.get().audio;
+        if settings.host_id.as_deref() == Some(NULL_HOST_ID) {
+            bail!("The null output host has no input devices");
+        }
+
+        let host = open_host(settings.host_id.as_deref())?;
+        let device = match settings.input_device_id.as_deref() {
+            Some(device_id) => host
+                .device_by_id(&DeviceId::from_str(device_id)?)
+                .with_context(|| format!("Input device not found: {device_id}"))?,
+            None => host
+                .default_input_device()
+                .context("No input device available")?,
+        };
+
+        // Takes are written and played back at the engine rate, so the input has to run at it too
+        let sample_rate = AUDIO_ENGINE.sample_rate();
+        let supported_config = device
+            .supported_input_configs()
+            .context("Couldn't query input device configs")?
+            .find_map(|range| range.try_with_sample_rate(sample_rate as u32))
+            .with_context(|| {
+                format!(
+                    "Input device {} doesn't support {sample_rate} Hz",
+                    device_name(&device)
+                )
+            })?;
+
+        let mut config: StreamConfig = supported_config.config();
+        let buffer_size = match supported_config.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } => {
+                (AUDIO_ENGINE.buffer_size() as u32).clamp(*min, *max)
+            }
+            cpal::SupportedBufferSize::Unknown => AUDIO_ENGINE.buffer_size() as u32,
+        };
+        config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
+        let buffer_frames = buffer_size as usize;
+
+        let record_rb =
+            HeapRb::<f32>::new(sample_rate * ENGINE_NUM_CHANNELS as usize * RECORD_BUFFER_SECONDS);
+        let (record_producer, record_consumer) = record_rb.split();
+        self.reconnect_monitor();
+
+        let stream = match supported_config.sample_format() {
+            cpal::SampleFormat::I8 => {
+                self.build_input_stream::<i8>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::I16 => {
+                self.build_input_stream::<i16>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::I24 | cpal::SampleFormat::I32 => {
+                self.build_input_stream::<i32>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::U8 => {
+                self.build_input_stream::<u8>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::U16 => {
+                self.build_input_stream::<u16>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::U24 | cpal::SampleFormat::U32 => {
+                self.build_input_stream::<u32>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::F32 => {
+                self.build_input_stream::<f32>(&device, &config, buffer_frames, record_producer)
+            }
+            cpal::SampleFormat::F64 => {
+                self.build_input_stream::<f64>(&device, &config, buffer_frames, record_producer)
+            }
+            sample_format => Err(anyhow!("Unsupported input sample format: {sample_format}")),
+        }?;
+
+        info!(
+            "Input device opened: {}, {} Hz, {} channels, {} frames",
+            device_name(&device),
+            config.sample_rate,
+            config.channels,
+            buffer_size
+        );
+
+        Ok(InputStream {
+            _stream: stream,
+            buffer_size: buffer_frames,
+            record_consumer: Some(record_consumer),
+        })
+    }
+
+    fn build_input_stream<SampleType>(
+        &self,
+        device: &Device,
+        config: &StreamConfig,
+        buffer_size: usize,
+        mut record_producer: HeapProd<f32>,
+    ) -> Result<Stream>
+    where
+        SampleType: Sample + SizedSample + Send + Debug + 'static,
+        EngineSampleFormat: FromSample<SampleType>,
+    {
+        let input_channels = config.channels as usize;
+        let engine_channels = ENGINE_NUM_CHANNELS as usize;
+        let is_recording = Arc::clone(&self.is_recording);
+        let is_monitoring = Arc::clone(&self.is_monitoring);
+        let monitor_producer = Arc::clone(&self.monitor_producer);
+
+        // Sized for the buffer size the stream was opened with, so the callback never allocates.
+        // Devices delivering more frames at once are converted in chunks of that size.
+        let scratch_frames = buffer_size.max(1);
+        let mut stereo_input = vec![0.0f32; scratch_frames * engine_channels];
+
+        let stream = device
+            .build_input_stream(
+                config,
+                move |input: &[SampleType], _| {
+                    for input_chunk in input.chunks(scratch_frames * input_channels) {
+                        let num_frames = input_chunk.len() / input_channels;
+                        let stereo_slice = &mut stereo_input[..num_frames * engine_channels];
+
+                        // Mono inputs are copied to both sides, extra channels are ignored
+                        for (frame, output) in input_chunk
+                            .chunks_exact(input_channels)
+                            .zip(stereo_slice.chunks_exact_mut(engine_channels))
+                        {
+                            let left = EngineSampleFormat::from_sample(frame[0]);
+                            let right = match frame.get(1) {
+                                Some(sample) => EngineSampleFormat::from_sample(*sample),
+                                None => left,
+                            };
+                            output[0] = left;
+                            output[1] = right;
+                        }
+
+                        if is_recording.load(Ordering::Relaxed) {
+                            record_producer.push_slice(stereo_slice);
+                        }
+                        if is_monitoring.load(Ordering::Relaxed) {
+                            // Only contended while a new monitor ring is being handed over
+                            if let Ok(mut monitor_producer) = monitor_producer.try_lock() {
+                                if let Some(monitor_producer) = monitor_producer.as_mut() {
+                                    monitor_producer.push_slice(stereo_slice);
+                                }
+                            }
+                        }
+                    }
+                },
+                move |err| error!("Input stream error: {}", err),
+                None,
+            )
+            .context("Failed to build input stream")?;
+
+        stream.play().context("Failed to start input stream")?;
+        Ok(stream)
+    }
+
+    fn recordings_dir() -> Result<PathBuf> {
+        if let Some(project_dir) = PROJECT_STATE.directory() {
+            return Ok(project_dir.join(RECORDINGS_DIR_NAME));
+        }
+        // Unsaved projects keep their takes in the app data directory
+        let data_dir = app_handle()
+            .path()
+            .app_data_dir()
+            .context("Couldn't resolve app data directory")?;
+        Ok(data_dir.join(RECORDINGS_DIR_NAME))
+    }
+
+    /// Starts writing the input into a new WAV file. The take is placed at `start_ppq`
+    /// on every track that is armed right now. The first `pre_roll_frames`, recorded during
+    /// a count-in, are skipped like the latency.
+    pub fn start_take(&self, start_ppq: usize, pre_roll_frames: usize) -> Result<()> {
+        if self.is_recording() {
+            bail!("Already recording");
+        }
+        let track_ids = PROJECT_STATE.armed_track_ids();
+        if track_ids.is_empty() {
+            bail!("No track is armed for recording");
+        }
+        self.start_input()?;
+
+        let recordings_dir = Self::recordings_dir()?;
+        fs::create_dir_all(&recordings_dir).context("Couldn't create recordings directory")?;
+        // Unique per take, the asset pool would hand back a cached asset for a reused path
+        let file_path = recordings_dir.join(format!("Recording {}.wav", nanoid!()));
+        let mut writer = WavFileWriter::create(
+            &file_path,
+            WavBitDepth::Float32,
+            ENGINE_NUM_CHANNELS as usize,
+            AUDIO_ENGINE.sample_rate(),
+        )?;
+
+        let mut input = self.input.lock().unwrap();
+        let input = input.as_mut().context("Input stream is not running")?;
+        let mut record_consumer = input
+            .record_consumer
+            .take()
+            .context("Input is already being recorded")?;
+        record_consumer.clear();
+
+        // Input arrives late by the input buffer, and what the performer heard was late by the output latency
+        let offset_frames = SETTINGS.get().audio.recording_offset_frames;
+        let latency_frames = (input.buffer_size + AUDIO_ENGINE.output_latency_frames()) as i64;
+        let latency_frames = (latency_frames + offset_frames).max(0) as usize + pre_roll_frames;
+
+        self.is_recording.store(true, Ordering::SeqCst);
+        let is_recording = Arc::clone(&self.is_recording);
+        let writer = thread::spawn(move || {
+            let mut buffer = vec![0.0f32; 4096];
+            loop {
+                let was_recording = is_recording.load(Ordering::SeqCst);
+                let len = record_consumer.pop_slice(&mut buffer);
+                if len > 0 {
+                    if let Err(e) = writer.write_samples(&buffer[..len]) {
+                        return (record_consumer, Err(e));
+                    }
+                } else if !was_recording {
+                    break;
+                } else {
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+            (record_consumer, writer.finalize())
+        });
+
+        info!(
+            "Recording started at ppq {start_ppq} into {}",
+            file_path.display()
+        );
+        *self.take.lock().unwrap() = Some(RecordingTake {
+            file_path,
+            start_ppq,
+            track_ids,
+            latency_frames,
+            writer,
+        });
+        Ok(())
+    }
+
+    /// Stops the running take and waits for its file to be written.
+    pub fn finish_take(&self) -> Result<Option<RecordedTake>> {
+        self.is_recording.store(false, Ordering::SeqCst);
+        let take = match self.take.lock().unwrap().take() {
+            Some(take) => take,
+            None => return Ok(None),
+        };
+
+        let (record_consumer, result) = take
+            .writer
+            .join()
+            .map_err(|_| anyhow!("Recording writer thread panicked"))?;
+        if let Some(input) = self.input.lock().unwrap().as_mut() {
+            input.record_consumer = Some(record_consumer);
+        }
+        result?;
+        info!("Recording finished: {}", take.file_path.display());
+
+        Ok(Some(RecordedTake {
+            file_path: take.file_path.to_string_lossy().into_owned(),
+            start_ppq: take.start_ppq,
+            track_ids: take.track_ids,
+            latency_frames: take.latency_frames,
+        }))
+    }
+
+    /// Finishes the running take, if any, and adds it as clips in the background.
+    /// The frontend is told about the new clips through `RECORDING_FINISHED_EVENT`.
+    pub fn stop_take(&self) {
+        match self.finish_take() {
+            Ok(Some(take)) => {
+                tauri::async_runtime::spawn(async move {
+                    let clips = PROJECT_STATE.add_recorded_take(take).await;
+                    let _ = app_handle().emit(RECORDING_FINISHED_EVENT, clips);
+                });
+            }
+            Ok(None) => {}
+            Err(e) => log_and_notify_error(format!("Error trying to save recording: {e}")),
+        }
+    }
+}
+
+pub static RECORDER: LazyLock<Recorder> = LazyLock::new(|| Recorder::new());
@@ -0,0 +1,123 @@
+use std::{
+    sync::{atomic::Ordering, Arc, LazyLock, Mutex},
+    thread::{self, JoinHandle, Thread},
+    time::Duration,
+};
+
+use arc_swap::ArcSwapOption;
+use log::info;
+
+use crate::audio::{
+    engine::AUDIO_ENGINE, renderer::Renderer, snapshot::project_snapshot::load_project_snapshot,
+    thread_pool::AUDIO_WORKER_POOL, transport::TRANSPORT,
+};
+
+/// Dedicated thread that keeps the engine ring buffer filled while the transport plays,
+/// and after it stops until the tails of reverbs and delays have rung out.
+/// It sleeps until the output callback has consumed a buffer, so it never spins and never
+/// renders more than the ring buffer holds ahead of the device.
+///
+/// Real-time guarantee: the render path reads the project only through the `PROJECT_SNAPSHOT`
+/// ArcSwap, the assets referenced by it, the tempo map ArcSwap and atomics (transport position
+/// and loop).
+/// It never locks `PROJECT_STATE`; edits there rebuild the snapshot on another thread and
+/// the new one is picked up on the next block.
+/// The engine producer is taken with `try_lock`, it is held elsewhere only while the output
+/// stream is being rebuilt. The other mutexes it takes guard render graph buffers and effect
+/// instances, which only renderers lock.
+pub struct RenderThread {
+    thread: ArcSwapOption<Thread>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl RenderThread {
+    pub fn new() -> Self {
+        Self {
+            thread: ArcSwapOption::empty(),
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Spawns the thread on first use. It lives as long as the app.
+    pub fn ensure_started(&self) {
+        let mut handle = self.handle.lock().unwrap();
+        if handle.is_some() {
+            return;
+        }
+        let new_handle = thread::Builder::new()
+            .name("render".to_string())
+            .spawn(render_loop)
+            .expect("Failed to spawn render thread");
+        self.thread
+            .store(Some(Arc::new(new_handle.thread().clone())));
+        *handle = Some(new_handle);
+    }
+
+    /// Wakes the thread up. Lock-free, called from the output callback after every buffer.
+    pub fn wake(&self) {
+        if let Some(thread) = self.thread.load().as_ref() {
+            thread.unpark();
+        }
+    }
+}
+
+fn render_loop() {
+    info!("Render thread started");
+    let mut renderer: Option<Renderer> = None;
+    let mut was_playing = false;
+    // Interleaved samples of effect tails still to render after stopping
+    let mut tail_samples_left = 0;
+    // Where playback left off, `stop` may have moved the transport back already
+    let mut last_position_samples = 0;
+
+    loop {
+        let is_playing = TRANSPORT.is_playing.load(Ordering::SeqCst);
+        if is_playing != was_playing {
+            if is_playing {
+                if tail_samples_left == 0 {
+                    AUDIO_WORKER_POOL.start();
+                }
+                tail_samples_left = 0;
+                if let Some(renderer) = renderer.as_mut() {
+                    renderer.reset_metering();
+                }
+                load_project_snapshot().get_data_nodes().reset_effects();
+            } else {
+                tail_samples_left =
+                    load_project_snapshot().max_tail_frames * AUDIO_ENGINE.num_channels();
+                if tail_samples_left == 0 {
+                    AUDIO_WORKER_POOL.stop();
+                }
+            }
+            was_playing = is_playing;
+        }
+        if !is_playing && tail_samples_left == 0 {
+            thread::park();
+            continue;
+        }
+
+        let block_size = AUDIO_ENGINE.buffer_size() * AUDIO_ENGINE.num_channels();
+        // Only reallocated when playback starts after the buffer size changed
+        let renderer = match renderer.as_mut() {
+            Some(renderer) if renderer.buffer_size() == block_size => renderer,
+            _ => renderer.insert(Renderer::new(block_size).with_metering().with_metronome()),
+        };
+        if is_playing {
+            TRANSPORT.render_pending(renderer, &mut last_position_samples);
+        } else {
+            TRANSPORT.render_tail_pending(renderer, last_position_samples, &mut tail_samples_left);
+            if tail_samples_left == 0 {
+                AUDIO_WORKER_POOL.stop();
+                continue;
+            }
+        }
+
+        // The timeout covers a stream that is being rebuilt and doesn't wake us up
+        let buffer_duration = Duration::from_secs_f64(
+            AUDIO_ENGINE.buffer_size() as f64 / AUDIO_ENGINE.sample_rate() as f64,
+        );
+        thread::park_timeout(buffer_duration);
+    }
+}
+
+pub static RENDER_THREAD: LazyLock<RenderThread> = LazyLock::new(|| RenderThread::new());
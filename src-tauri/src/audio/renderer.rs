@@ -0,0 +1,485 @@
+use std::sync::Arc;
+
+use crate::{
+    audio::{
+        effects::audio_effect::ProcessContext,
+        engine::AUDIO_ENGINE,
+        gain::{apply_gain_ramp, mix_with_gain_ramp},
+        metering::{LoudnessMeter, METERING},
+        metronome::METRONOME,
+        snapshot::{
+            project_snapshot::ProjectSnapshot,
+            render_graph::{
+                GraphNodeKind, NodeBuffer, RenderEdge, RenderGraph, RenderGraphBuffers,
+            },
+        },
+        thread_pool::WorkerPool,
+        track::SendTap,
+    },
+    core::types::{EngineSampleFormat, Id},
+};
+
+/// Part of a buffer rendered from one continuous position, split at loop jumps.
+struct Segment {
+    offset: usize,
+    len: usize,
+    position_samples: usize,
+}
+
+/// Renders the render graph of a project snapshot one buffer at a time.
+/// Shared by the realtime transport and the offline bounce so both produce identical output.
+pub struct Renderer {
+    buffer_size: usize,
+    scheduler_version: Option<Id>,
+    render_graph_version: Option<Id>,
+    // Scheduler track index of every track node, by node index
+    graph_tracks: Vec<Option<usize>>,
+    segments: Vec<Segment>,
+    // Offset in the last rendered buffer where the position jumped back to the loop start
+    loop_wrap_offset: Option<usize>,
+    // Set for renderers off the render thread, they never touch the snapshot's own buffers
+    owns_buffers: bool,
+    // Allocated by the render graph version they were made for
+    private_buffers: Option<(Id, RenderGraphBuffers)>,
+    main_buffer: Vec<EngineSampleFormat>,
+    loudness_meter: Option<LoudnessMeter>,
+    // Metronome and count-in clicks, added after the master when enabled
+    click_buffer: Option<Vec<EngineSampleFormat>>,
+}
+
+impl Renderer {
+    pub fn new(buffer_size: usize) -> Self {
+        Self {
+            buffer_size,
+            scheduler_version: None,
+            render_graph_version: None,
+            graph_tracks: Vec::new(),
+            segments: Vec::new(),
+            loop_wrap_offset: None,
+            owns_buffers: false,
+            private_buffers: None,
+            main_buffer: vec![0.0; buffer_size],
+            loudness_meter: None,
+            click_buffer: None,
+        }
+    }
+
+    /// Feeds the track and master meters with every rendered buffer. Used for realtime playback.
+    pub fn with_metering(mut self) -> Self {
+        self.loudness_meter = Some(LoudnessMeter::new());
+        self
+    }
+
+    /// Mixes the metronome and plays its count-in. Used for realtime playback.
+    pub fn with_metronome(mut self) -> Self {
+        self.click_buffer = Some(vec![0.0; self.buffer_size]);
+        self
+    }
+
+    /// Renders into buffers of its own, allocated on the calling thread whenever the render graph
+    /// changes, and leaves the snapshot's buffers to realtime playback. Used for the bounce.
+    pub fn with_private_buffers(mut self) -> Self {
+        self.owns_buffers = true;
+        self
+    }
+
+    /// Forgets the loudness history, e.g. when playback starts again.
+    pub fn reset_metering(&mut self) {
+        if let Some(loudness_meter) = self.loudness_meter.as_mut() {
+            *loudness_meter = LoudnessMeter::new();
+        }
+    }
+
+    /// Interleaved samples rendered per call.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Offset, in interleaved samples, where the last rendered buffer jumped back to the loop
+    /// start, `None` when it didn't loop.
+    pub fn loop_wrap_offset(&self) -> Option<usize> {
+        self.loop_wrap_offset
+    }
+
+    /// Track nodes find their clips by id, so the lookup only changes with a new scheduler
+    /// or render graph.
+    fn sync_graph_tracks(&mut self, snapshot: &ProjectSnapshot) {
+        if self.scheduler_version.as_ref() == Some(&snapshot.scheduler_version)
+            && self.render_graph_version.as_ref() == Some(&snapshot.render_graph_version)
+        {
+            return;
+        }
+        self.scheduler_version = Some(snapshot.scheduler_version.clone());
+        self.render_graph_version = Some(snapshot.render_graph_version.clone());
+
+        let tracks = &snapshot.get_scheduler().tracks;
+        let render_graph = &snapshot.render_graph;
+        self.graph_tracks.clear();
+        self.graph_tracks
+            .extend((0..render_graph.node_count()).map(|node_index| {
+                let node = render_graph.node(node_index);
+                if node.kind() != GraphNodeKind::Track {
+                    return None;
+                }
+                tracks
+                    .iter()
+                    .position(|track| &track.id == node.data_node_id())
+            }));
+    }
+
+    /// Renders one buffer starting at `position_samples` and returns the summed output
+    /// together with the position following it.
+    /// With a loop range, given as interleaved samples, the position jumps back to the loop start
+    /// as soon as it reaches the loop end, also in the middle of the buffer.
+    /// A pending metronome count-in is played first and the position only moves once it is over.
+    /// Graph nodes are processed on `worker_pool` when given, each one as soon as its inputs are
+    /// done, otherwise on the calling thread.
+    pub fn render(
+        &mut self,
+        snapshot: &Arc<ProjectSnapshot>,
+        position_samples: usize,
+        loop_range_samples: Option<(usize, usize)>,
+        worker_pool: Option<&WorkerPool>,
+    ) -> (&[EngineSampleFormat], usize) {
+        let next_position_samples = self.render_range(
+            snapshot,
+            position_samples,
+            loop_range_samples,
+            None,
+            worker_pool,
+        );
+        (&self.main_buffer, next_position_samples)
+    }
+
+    /// Renders one buffer like `render` without a loop, but the tracks go silent at `end_samples`
+    /// and the effects carry on with their tails. Used for the last block of a bounce, so the
+    /// tail rendered after it follows on without a gap.
+    pub fn render_until(
+        &mut self,
+        snapshot: &Arc<ProjectSnapshot>,
+        position_samples: usize,
+        end_samples: usize,
+        worker_pool: Option<&WorkerPool>,
+    ) -> &[EngineSampleFormat] {
+        self.render_range(
+            snapshot,
+            position_samples,
+            None,
+            Some(end_samples),
+            worker_pool,
+        );
+        &self.main_buffer
+    }
+
+    /// Renders into `main_buffer` and returns the position following it.
+    fn render_range(
+        &mut self,
+        snapshot: &Arc<ProjectSnapshot>,
+        position_samples: usize,
+        loop_range_samples: Option<(usize, usize)>,
+        end_samples: Option<usize>,
+        worker_pool: Option<&WorkerPool>,
+    ) -> usize {
+        self.sync_graph_tracks(snapshot);
+
+        let mut position_samples = position_samples;
+        let mut offset = 0;
+
+        if let Some(click_buffer) = self.click_buffer.as_mut() {
+            click_buffer.fill(0.0);
+            if let Some(count_in) = METRONOME.count_in() {
+                offset = count_in.remaining_samples().min(self.buffer_size);
+                METRONOME.render_count_in(&count_in, &mut click_buffer[..offset]);
+            }
+        }
+        // Tracks stay silent until the count-in is over
+        let count_in_len = offset;
+
+        self.segments.clear();
+        self.loop_wrap_offset = None;
+        while offset < self.buffer_size && end_samples.is_none_or(|end| position_samples < end) {
+            // Only a playhead that is before the loop end gets caught by the loop
+            let loop_range =
+                loop_range_samples.filter(|(start, end)| start < end && position_samples < *end);
+            let segment_len = match loop_range {
+                Some((_, loop_end)) => (loop_end - position_samples).min(self.buffer_size - offset),
+                None => self.buffer_size - offset,
+            };
+            let segment_len =
+                end_samples.map_or(segment_len, |end| segment_len.min(end - position_samples));
+            self.segments.push(Segment {
+                offset,
+                len: segment_len,
+                position_samples,
+            });
+            if let Some(click_buffer) = self.click_buffer.as_mut() {
+                METRONOME.render(
+                    snapshot,
+                    position_samples,
+                    &mut click_buffer[offset..offset + segment_len],
+                );
+            }
+
+            offset += segment_len;
+            position_samples += segment_len;
+            if let Some((loop_start, loop_end)) = loop_range {
+                if position_samples == loop_end {
+                    position_samples = loop_start;
+                    self.loop_wrap_offset = Some(offset);
+                }
+            }
+        }
+
+        let tempo_start_samples = self
+            .segments
+            .first()
+            .map_or(position_samples, |segment| segment.position_samples);
+        let tempo_bpm = tempo_bpm_at(snapshot, tempo_start_samples);
+        self.render_graph(snapshot, count_in_len, tempo_bpm, worker_pool);
+
+        if let Some(click_buffer) = self.click_buffer.as_ref() {
+            mix_into(&mut self.main_buffer, click_buffer);
+        }
+
+        position_samples
+    }
+
+    /// Renders one buffer with every track silent, so only effect tails are heard.
+    /// Used to let them ring out after the transport stopped or past the end of a bounce.
+    /// `position_samples` only picks the tempo tempo-synced effects follow.
+    pub fn render_tail(
+        &mut self,
+        snapshot: &Arc<ProjectSnapshot>,
+        position_samples: usize,
+        worker_pool: Option<&WorkerPool>,
+    ) -> &[EngineSampleFormat] {
+        self.sync_graph_tracks(snapshot);
+        self.segments.clear();
+        self.loop_wrap_offset = None;
+        let tempo_bpm = tempo_bpm_at(snapshot, position_samples);
+        self.render_graph(snapshot, self.buffer_size, tempo_bpm, worker_pool);
+        &self.main_buffer
+    }
+
+    /// Processes the render graph into `main_buffer`, track nodes rendering `segments` after
+    /// `silent_len` samples of silence and staying silent after the last one.
+    fn render_graph(
+        &mut self,
+        snapshot: &Arc<ProjectSnapshot>,
+        silent_len: usize,
+        tempo_bpm: f32,
+        worker_pool: Option<&WorkerPool>,
+    ) {
+        let render_graph = &snapshot.render_graph;
+        let claimed_buffers = if self.owns_buffers {
+            None
+        } else {
+            render_graph.try_claim_buffers(self.buffer_size)
+        };
+        if self.owns_buffers
+            && self
+                .private_buffers
+                .as_ref()
+                .is_none_or(|(version, _)| version != &snapshot.render_graph_version)
+        {
+            self.private_buffers = Some((
+                snapshot.render_graph_version.clone(),
+                render_graph.allocate_buffers(self.buffer_size),
+            ));
+        }
+        let buffers = match (claimed_buffers.as_deref(), self.private_buffers.as_ref()) {
+            (Some(buffers), _) => buffers,
+            (None, Some((_, buffers))) if self.owns_buffers => buffers,
+            // The buffers are busy or sized for another device buffer, allocating would stall
+            // the render thread so the block stays silent
+            _ => {
+                self.main_buffer.fill(0.0);
+                return;
+            }
+        };
+
+        let tracks = &snapshot.get_scheduler().tracks;
+        let data_nodes = snapshot.get_data_nodes();
+        let graph_tracks = &self.graph_tracks;
+        let segments = &self.segments;
+        let is_metering = self.loudness_meter.is_some();
+        let context = ProcessContext {
+            sample_rate: AUDIO_ENGINE.sample_rate(),
+            tempo_bpm,
+        };
+        let process_node = |_, node_index: usize| {
+            let node = render_graph.node(node_index);
+            let mut node_buffer = buffers.nodes[node_index].lock().unwrap();
+            let NodeBuffer {
+                samples,
+                pre_fader_samples,
+                previous_gains,
+            } = &mut *node_buffer;
+
+            let track = graph_tracks
+                .get(node_index)
+                .copied()
+                .flatten()
+                .and_then(|track_index| tracks.get(track_index));
+            let gains = match node.kind() {
+                GraphNodeKind::Track => {
+                    samples[..silent_len].fill(0.0);
+                    let segments_end = segments
+                        .last()
+                        .map_or(silent_len, |segment| segment.offset + segment.len);
+                    samples[segments_end..].fill(0.0);
+                    match track {
+                        Some(track) => {
+                            for segment in segments.iter() {
+                                track.render(
+                                    segment.position_samples,
+                                    &mut samples[segment.offset..segment.offset + segment.len],
+                                    snapshot.clone(),
+                                );
+                            }
+                        }
+                        None => samples.fill(0.0),
+                    }
+                    data_nodes
+                        .nodes
+                        .get(node.data_node_id())
+                        .and_then(|data_node| data_node.as_track())
+                        .map_or([1.0; 2], |track_node| track_node.gains(data_nodes.pan_law))
+                }
+                GraphNodeKind::Bus | GraphNodeKind::Master => {
+                    samples.fill(0.0);
+                    // Inputs are done before this node runs, so their locks are only
+                    // contended by other nodes reading the same input
+                    for (input_index, edge_index, edge) in render_graph.inputs(node_index) {
+                        let input_buffer = buffers.nodes[input_index].lock().unwrap();
+                        let RenderEdge::Send(tap) = edge else {
+                            mix_into(samples, &input_buffer.samples);
+                            continue;
+                        };
+                        let level = data_nodes
+                            .nodes
+                            .get(render_graph.node(input_index).data_node_id())
+                            .and_then(|data_node| data_node.as_track())
+                            .map_or(0.0, |track_node| {
+                                track_node.send_level(node.data_node_id(), tap)
+                            });
+                        let source = match tap {
+                            SendTap::PreFader => input_buffer
+                                .pre_fader_samples
+                                .as_deref()
+                                .unwrap_or(&input_buffer.samples),
+                            SendTap::PostFader => &input_buffer.samples,
+                        };
+                        let mut previous_level =
+                            buffers.previous_send_levels[edge_index].lock().unwrap();
+                        mix_with_gain_ramp(samples, source, previous_level.unwrap_or(level), level);
+                        *previous_level = Some(level);
+                    }
+                    if node.kind() == GraphNodeKind::Master {
+                        data_nodes
+                            .master()
+                            .map_or([1.0; 2], |master| master.gains(data_nodes.pan_law))
+                    } else {
+                        data_nodes
+                            .nodes
+                            .get(node.data_node_id())
+                            .and_then(|data_node| data_node.as_bus())
+                            .map_or([1.0; 2], |bus_node| bus_node.gains(data_nodes.pan_law))
+                    }
+                }
+            };
+            // Inserts run on the rendered clips or summed inputs, before sends and the fader
+            if let Some(inserts) = data_nodes
+                .nodes
+                .get(node.data_node_id())
+                .and_then(|data_node| data_node.inserts())
+            {
+                inserts.process(samples, &context);
+            }
+            if let Some(pre_fader_samples) = pre_fader_samples.as_mut() {
+                pre_fader_samples.copy_from_slice(samples);
+            }
+            apply_gain_ramp(samples, previous_gains.unwrap_or(gains), gains);
+            *previous_gains = Some(gains);
+
+            // Meters are post-fader
+            if let Some(track) = track.filter(|_| is_metering) {
+                track.meter.process(samples);
+            }
+        };
+        match worker_pool {
+            Some(worker_pool) => worker_pool.run_graph(&buffers.tasks, &process_node),
+            None => buffers.tasks.run_sequential(&process_node),
+        }
+
+        self.main_buffer.copy_from_slice(
+            &buffers.nodes[RenderGraph::MASTER_INDEX]
+                .lock()
+                .unwrap()
+                .samples,
+        );
+        drop(claimed_buffers);
+
+        if let Some(loudness_meter) = self.loudness_meter.as_mut() {
+            METERING.master.process(&self.main_buffer);
+            loudness_meter.process(&self.main_buffer, AUDIO_ENGINE.sample_rate());
+            METERING.set_master_short_term_lufs(loudness_meter.short_term_lufs());
+        }
+    }
+}
+
+fn tempo_bpm_at(snapshot: &ProjectSnapshot, position_samples: usize) -> f32 {
+    let position_ppq = snapshot.tempo_map.samples_to_ppq(
+        position_samples,
+        AUDIO_ENGINE.sample_rate(),
+        AUDIO_ENGINE.num_channels(),
+    );
+    snapshot.tempo_map.bpm_at(position_ppq)
+}
+
+fn mix_into(destination: &mut [EngineSampleFormat], source: &[EngineSampleFormat]) {
+    for (destination_sample, source_sample) in destination.iter_mut().zip(source.iter()) {
+        *destination_sample += *source_sample;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::snapshot::test_fixtures::clip_snapshot;
+
+    const BLOCK_SAMPLES: usize = 256;
+    const CLIP_START: usize = 100;
+    // Loop over the clip's start, ending in the middle of the second block
+    const LOOP_RANGE: (usize, usize) = (CLIP_START, 400);
+
+    #[test]
+    fn loop_wraps_mid_block_and_restarts_clip() {
+        let source: Vec<f32> = (0..800)
+            .map(|index| (index as f32 + 1.0) / 1024.0)
+            .collect();
+        let snapshot = clip_snapshot(&source, 48000, CLIP_START, 0);
+        let mut renderer = Renderer::new(BLOCK_SAMPLES).with_private_buffers();
+
+        let (block, position_samples) = renderer.render(&snapshot, 0, Some(LOOP_RANGE), None);
+        assert_eq!(position_samples, BLOCK_SAMPLES);
+        assert_eq!(block[CLIP_START], source[0]);
+        assert_eq!(renderer.loop_wrap_offset(), None);
+
+        let (block, position_samples) =
+            renderer.render(&snapshot, position_samples, Some(LOOP_RANGE), None);
+        let wrap_offset = LOOP_RANGE.1 - BLOCK_SAMPLES;
+        // The clip plays on up to the loop end and starts over right after it
+        assert_eq!(
+            block[wrap_offset - 1],
+            source[LOOP_RANGE.1 - CLIP_START - 1]
+        );
+        assert_eq!(block[wrap_offset], source[0]);
+        assert_eq!(
+            &block[wrap_offset..],
+            &source[..BLOCK_SAMPLES - wrap_offset]
+        );
+        assert_eq!(renderer.loop_wrap_offset(), Some(wrap_offset));
+        assert_eq!(position_samples, CLIP_START + BLOCK_SAMPLES - wrap_offset);
+    }
+}
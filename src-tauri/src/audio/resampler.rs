@@ -8,7 +8,10 @@ use crate::{audio::engine::AUDIO_ENGINE, core::constants::ENGINE_NUM_CHANNELS};
 
 // TODO in the future, extract these options from user preferences
 
-pub fn create_offline_resampler(original_sample_rate: usize) -> Result<SincFixedOut<f32>> {
+pub fn create_offline_resampler(
+    original_sample_rate: usize,
+    num_channels: usize,
+) -> Result<SincFixedOut<f32>> {
     let sinc_len = 256;
     let window = WindowFunction::BlackmanHarris2;
     let params = SincInterpolationParameters {
@@ -23,7 +26,7 @@ pub fn create_offline_resampler(original_sample_rate: usize) -> Result<SincFixed
         2.0,
         params,
         1024,
-        AUDIO_ENGINE.num_channels(),
+        num_channels,
     )
     .context("Failed to create offline resampler")?;
 
@@ -1,5 +1,24 @@
+use crate::audio::{
+    gain::{channel_gains, PanLaw},
+    snapshot::insert_chain::InsertChain,
+};
+
 pub struct BusNode {
     pub volume: f32,
     pub pan: f32,
     pub muted: bool,
+    /// Silenced because other tracks are soloed.
+    pub implied_muted: bool,
+    pub inserts: InsertChain,
+}
+
+impl BusNode {
+    pub fn gains(&self, pan_law: PanLaw) -> [f32; 2] {
+        channel_gains(
+            self.volume,
+            self.pan,
+            self.muted || self.implied_muted,
+            pan_law,
+        )
+    }
 }
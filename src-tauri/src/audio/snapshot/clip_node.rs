@@ -4,7 +4,7 @@ use log::info;
 
 use crate::{
     audio::{
-        asset_pool::ASSET_POOL,
+        asset_pool::AudioAsset,
         snapshot::{clip_event::ClipEvent, project_snapshot::ProjectSnapshot},
     },
     core::types::{EngineSampleFormat, Id},
@@ -12,10 +12,13 @@ use crate::{
 
 pub struct ClipNode {
     pub source_id: Id,
+    /// Resolved when the data nodes are built so rendering doesn't go through the pool's lock.
+    pub source: Option<Arc<AudioAsset>>,
     pub source_offset_samples: usize,
 }
 
 impl ClipNode {
+    /// Copies the part of the clip that overlaps `out` and returns the number of samples written.
     pub fn render(
         &self,
         position_samples: usize,
@@ -24,34 +27,35 @@ impl ClipNode {
         snapshot: Arc<ProjectSnapshot>,
     ) -> usize {
         // TODO get track node and do render on it
-        let pcm_data = ASSET_POOL.audio.get_pcm_by_id(&self.source_id);
-        if pcm_data.is_none() {
+        let Some(source) = self.source.as_ref() else {
             info!(
-                "ClipNode: render: pcm_data is None for source_id: {}",
+                "ClipNode: render: source is None for source_id: {}",
                 self.source_id
             );
             return 0;
+        };
+        let pcm_data = source.pcm_data();
+        let source_samples = pcm_data.samples();
+
+        let from_sample = clip_event_node.start_sample.max(position_samples);
+        let to_sample = clip_event_node.end_sample.min(position_samples + out.len());
+        if from_sample >= to_sample {
+            return 0;
         }
-        let pcm_data_unwrap = pcm_data.unwrap();
-        let source_samples = pcm_data_unwrap.as_ref().samples();
-        let needed_samples_count: usize = out.len();
-        let mut dest_start_index = 0;
-        if position_samples < clip_event_node.start_sample {
-            dest_start_index = clip_event_node.start_sample - position_samples;
-            out[0..dest_start_index].fill(0.0);
-        }
-        let source_start_index = position_samples.saturating_sub(clip_event_node.start_sample)
-            + self.source_offset_samples;
+
+        let source_start_index =
+            from_sample - clip_event_node.start_sample + self.source_offset_samples;
         let source_end_index = cmp::min(
-            source_start_index + needed_samples_count - dest_start_index,
+            source_start_index + (to_sample - from_sample),
             source_samples.len(),
         );
+        if source_start_index >= source_end_index {
+            return 0;
+        }
         let source_samples_slice = &source_samples[source_start_index..source_end_index];
-        let dest_end_index = cmp::min(
-            needed_samples_count,
-            clip_event_node.end_sample.saturating_sub(position_samples),
-        );
-        out[dest_start_index..dest_end_index].copy_from_slice(source_samples_slice);
+        let dest_start_index = from_sample - position_samples;
+        out[dest_start_index..dest_start_index + source_samples_slice.len()]
+            .copy_from_slice(source_samples_slice);
         source_samples_slice.len()
     }
 }
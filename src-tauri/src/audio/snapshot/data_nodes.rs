@@ -1,13 +1,18 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use petgraph::visit::Data;
 
 use crate::audio::clip::Clip;
 use crate::{
+    audio::asset_pool::ASSET_POOL,
+    audio::effects::audio_effect::EffectInstance,
+    audio::gain::PanLaw,
     audio::project_state::PROJECT_STATE,
     audio::snapshot::{
-        bus_node::BusNode, clip_node::ClipNode, master_node::MasterNode, track_node::TrackNode,
+        bus_node::BusNode, clip_node::ClipNode, insert_chain::InsertChain, master_node::MasterNode,
+        mix_state::MIX_STATES, project_snapshot::load_project_snapshot, track_node::TrackNode,
     },
     audio::track::GeneratorTrack,
     core::types::Id,
@@ -27,36 +32,112 @@ impl DataNode {
             _ => None,
         }
     }
+
+    pub fn as_track(&self) -> Option<&TrackNode> {
+        match self {
+            DataNode::TrackNode(track) => Some(track),
+            _ => None,
+        }
+    }
+
+    pub fn as_bus(&self) -> Option<&BusNode> {
+        match self {
+            DataNode::BusNode(bus) => Some(bus),
+            _ => None,
+        }
+    }
+
+    pub fn inserts(&self) -> Option<&InsertChain> {
+        match self {
+            DataNode::TrackNode(track) => Some(&track.inserts),
+            DataNode::BusNode(bus) => Some(&bus.inserts),
+            DataNode::MasterNode(master) => Some(&master.inserts),
+            DataNode::ClipNode(_) => None,
+        }
+    }
 }
 
 pub struct DataNodes {
     pub nodes: HashMap<Id, DataNode>,
+    pub pan_law: PanLaw,
 }
 
 impl DataNodes {
-    const MASTER_NODE_ID: &'static str = "master";
+    pub const MASTER_NODE_ID: &'static str = "master";
 
     pub fn new() -> Self {
         Self {
             nodes: HashMap::new(),
+            pan_law: PanLaw::default(),
         }
     }
 
+    /// Insert chain of a track or bus, or the master's with `None`.
+    pub fn inserts(&self, track_id: Option<&str>) -> Option<&InsertChain> {
+        self.nodes
+            .get(track_id.unwrap_or(Self::MASTER_NODE_ID))
+            .and_then(|node| node.inserts())
+    }
+
+    pub fn master(&self) -> Option<&MasterNode> {
+        match self.nodes.get(Self::MASTER_NODE_ID) {
+            Some(DataNode::MasterNode(master)) => Some(master),
+            _ => None,
+        }
+    }
+
+    /// Clears filter memory and tails of every effect, e.g. when playback starts again.
+    pub fn reset_effects(&self) {
+        for inserts in self.nodes.values().filter_map(|node| node.inserts()) {
+            inserts.reset();
+        }
+    }
+
+    /// Running effects of every insert chain, by effect id.
+    fn effect_instances(&self) -> HashMap<Id, Arc<EffectInstance>> {
+        self.nodes
+            .values()
+            .filter_map(|node| node.inserts())
+            .flat_map(|inserts| inserts.effects.iter())
+            .map(|effect| (effect.id.clone(), Arc::clone(&effect.instance)))
+            .collect()
+    }
+
+    /// Builds the data nodes from the project state, inserts keep running the effect instances
+    /// of the current snapshot.
     pub fn build(should_abort: impl Fn() -> bool) -> Option<Self> {
+        let instances = load_project_snapshot().get_data_nodes().effect_instances();
+        Self::build_with_instances(should_abort, &instances)
+    }
+
+    /// Same as `build`, but every insert gets a new effect instance. Used by the bounce, whose
+    /// effects can't share filter memory and tails with playback.
+    pub fn build_detached(should_abort: impl Fn() -> bool) -> Option<Self> {
+        Self::build_with_instances(should_abort, &HashMap::new())
+    }
+
+    fn build_with_instances(
+        should_abort: impl Fn() -> bool,
+        instances: &HashMap<Id, Arc<EffectInstance>>,
+    ) -> Option<Self> {
         if should_abort() {
             return None;
         }
 
         let mut new_data_nodes = DataNodes::new();
+        new_data_nodes.pan_law = PROJECT_STATE.pan_law();
+        let implied_mutes = PROJECT_STATE.solo_state().implied_mutes();
         let aborted = AtomicBool::new(false);
 
+        // Mix states are written under the project lock, like the mixer setters do
         PROJECT_STATE.with_master(|master| {
+            let mix = MIX_STATES.get(Self::MASTER_NODE_ID);
+            mix.set(master.volume, master.pan, master.muted);
             new_data_nodes.nodes.insert(
                 Self::MASTER_NODE_ID.to_string(),
                 DataNode::MasterNode(MasterNode {
-                    volume: master.volume,
-                    pan: master.pan,
-                    muted: master.muted,
+                    mix,
+                    inserts: InsertChain::build(&master.inserts, instances),
                 }),
             );
         });
@@ -68,14 +149,26 @@ impl DataNodes {
                     return;
                 }
 
-                let (volume, pan, muted, clips) = match track {
-                    GeneratorTrack::AudioTrack(t) => (t.volume, t.pan, t.muted, &t.clips),
-                    GeneratorTrack::SamplerTrack(t) => (t.volume, t.pan, t.muted, &t.clips),
+                let clips = match track {
+                    GeneratorTrack::AudioTrack(t) => &t.clips,
+                    GeneratorTrack::SamplerTrack(t) => &t.clips,
                 };
+                let mix = MIX_STATES.get(track_id);
+                mix.set(track.volume(), track.pan(), track.muted());
+                let sends = track
+                    .sends()
+                    .iter()
+                    .map(|send| (send.bus_id.clone(), send.amount))
+                    .collect();
 
                 new_data_nodes.nodes.insert(
                     track_id.clone(),
-                    DataNode::TrackNode(TrackNode { volume, pan, muted }),
+                    DataNode::TrackNode(TrackNode {
+                        mix,
+                        implied_muted: implied_mutes.contains(track_id),
+                        sends,
+                        inserts: InsertChain::build(track.inserts(), instances),
+                    }),
                 );
 
                 for (clip_id, clip) in clips.iter() {
@@ -88,6 +181,7 @@ impl DataNodes {
                         clip_id.clone(),
                         DataNode::ClipNode(ClipNode {
                             source_id: clip.source_id.clone(),
+                            source: ASSET_POOL.audio.get_by_id(&clip.source_id),
                             source_offset_samples: clip.source_offset_samples,
                         }),
                     );
@@ -112,6 +206,8 @@ impl DataNodes {
                         volume: bus.volume,
                         pan: bus.pan,
                         muted: bus.muted,
+                        implied_muted: implied_mutes.contains(bus_id),
+                        inserts: InsertChain::build(&bus.inserts, instances),
                     }),
                 );
 
@@ -125,6 +221,7 @@ impl DataNodes {
                         clip_id.clone(),
                         DataNode::ClipNode(ClipNode {
                             source_id: clip.source_id.clone(),
+                            source: ASSET_POOL.audio.get_by_id(&clip.source_id),
                             source_offset_samples: clip.source_offset_samples,
                         }),
                     );
@@ -136,6 +233,14 @@ impl DataNodes {
             return None;
         }
 
+        let mix_node_ids: Vec<Id> = new_data_nodes
+            .nodes
+            .iter()
+            .filter(|(_, node)| matches!(node, DataNode::TrackNode(_) | DataNode::MasterNode(_)))
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+        MIX_STATES.retain(&mix_node_ids);
+
         Some(new_data_nodes)
     }
 }
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{
+    audio::effects::audio_effect::{EffectInstance, EffectKind, InsertEffect, ProcessContext},
+    core::types::{EngineSampleFormat, Id},
+};
+
+pub struct EffectNode {
+    pub id: Id,
+    pub kind: EffectKind,
+    pub bypassed: bool,
+    pub parameters: Vec<f32>,
+    /// Kept across data node rebuilds so parameter changes don't cut tails or reset filters.
+    pub instance: Arc<EffectInstance>,
+}
+
+/// Insert effects of a track, bus or the master, in processing order.
+pub struct InsertChain {
+    pub effects: Vec<EffectNode>,
+}
+
+impl InsertChain {
+    /// Takes over the running instance of every effect that is already in `instances`, unless it
+    /// no longer matches the effect's impulse response or the engine sample rate.
+    pub fn build(inserts: &[InsertEffect], instances: &HashMap<Id, Arc<EffectInstance>>) -> Self {
+        let effects = inserts
+            .iter()
+            .map(|insert| {
+                let instance = instances
+                    .get(&insert.id)
+                    .filter(|instance| instance.is_current(insert))
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(EffectInstance::new(insert)));
+                EffectNode {
+                    id: insert.id.clone(),
+                    kind: insert.kind,
+                    bypassed: insert.bypassed,
+                    parameters: insert.parameters.clone(),
+                    instance,
+                }
+            })
+            .collect();
+        Self { effects }
+    }
+
+    /// Total delay of the effects that aren't bypassed, in frames.
+    pub fn latency_frames(&self) -> usize {
+        self.effects
+            .iter()
+            .filter(|effect| !effect.bypassed)
+            .map(|effect| effect.instance.latency_frames())
+            .sum()
+    }
+
+    /// Frames the chain keeps sounding after its input goes silent, latency included.
+    pub fn tail_frames(&self, sample_rate: usize, tempo_bpm: f32) -> usize {
+        self.effects
+            .iter()
+            .filter(|effect| !effect.bypassed)
+            .map(|effect| {
+                let tail_time = effect.kind.tail_time(&effect.parameters, tempo_bpm);
+                effect.instance.latency_frames()
+                    + effect.instance.impulse_response_frames()
+                    + (tail_time * sample_rate as f64).ceil() as usize
+            })
+            .sum()
+    }
+
+    pub fn reset(&self) {
+        for effect in self.effects.iter() {
+            effect.instance.reset();
+        }
+    }
+
+    pub fn process(&self, buffer: &mut [EngineSampleFormat], context: &ProcessContext) {
+        for effect in self.effects.iter().filter(|effect| !effect.bypassed) {
+            effect.instance.process(&effect.parameters, buffer, context);
+        }
+    }
+}
@@ -1,5 +1,17 @@
+use std::sync::Arc;
+
+use crate::audio::{
+    gain::PanLaw,
+    snapshot::{insert_chain::InsertChain, mix_state::MixState},
+};
+
 pub struct MasterNode {
-    pub volume: f32,
-    pub pan: f32,
-    pub muted: bool,
+    pub mix: Arc<MixState>,
+    pub inserts: InsertChain,
+}
+
+impl MasterNode {
+    pub fn gains(&self, pan_law: PanLaw) -> [f32; 2] {
+        self.mix.gains(false, pan_law)
+    }
 }
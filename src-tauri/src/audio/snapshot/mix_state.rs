@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
+
+use atomic_float::AtomicF32;
+
+use crate::{
+    audio::gain::{channel_gains, PanLaw},
+    core::types::Id,
+};
+
+/// Fader settings of a track or the master. Shared by every snapshot, so volume, pan and mute
+/// reach the render thread without rebuilding the data nodes.
+pub struct MixState {
+    volume: AtomicF32,
+    pan: AtomicF32,
+    muted: AtomicBool,
+}
+
+impl MixState {
+    pub fn new(volume: f32, pan: f32, muted: bool) -> Self {
+        Self {
+            volume: AtomicF32::new(volume),
+            pan: AtomicF32::new(pan),
+            muted: AtomicBool::new(muted),
+        }
+    }
+
+    pub fn set(&self, volume: f32, pan: f32, muted: bool) {
+        self.volume.store(volume, Ordering::Relaxed);
+        self.pan.store(pan, Ordering::Relaxed);
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// Per-channel gain, silent when muted here or by `implied_muted`.
+    pub fn gains(&self, implied_muted: bool, pan_law: PanLaw) -> [f32; 2] {
+        channel_gains(
+            self.volume.load(Ordering::Relaxed),
+            self.pan.load(Ordering::Relaxed),
+            self.is_muted() || implied_muted,
+            pan_law,
+        )
+    }
+}
+
+pub struct MixStates {
+    states: Mutex<HashMap<Id, Arc<MixState>>>,
+}
+
+impl MixStates {
+    pub fn new() -> Self {
+        Self {
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mix state of a track or the master, created at unity gain on first use.
+    pub fn get(&self, node_id: &str) -> Arc<MixState> {
+        let mut states = self.states.lock().unwrap();
+        Arc::clone(
+            states
+                .entry(node_id.to_string())
+                .or_insert_with(|| Arc::new(MixState::new(1.0, 0.0, false))),
+        )
+    }
+
+    /// Drops the mix states of tracks that no longer exist.
+    pub fn retain(&self, node_ids: &[Id]) {
+        self.states
+            .lock()
+            .unwrap()
+            .retain(|node_id, _| node_ids.contains(node_id));
+    }
+}
+
+pub static MIX_STATES: LazyLock<MixStates> = LazyLock::new(|| MixStates::new());
@@ -2,9 +2,14 @@ pub mod bus_node;
 pub mod clip_event;
 pub mod clip_node;
 pub mod data_nodes;
+pub mod insert_chain;
 pub mod master_node;
+pub mod mix_state;
 pub mod project_snapshot;
 pub mod render_graph;
 pub mod scheduler;
+pub mod solo;
+#[cfg(test)]
+pub mod test_fixtures;
 pub mod track_node;
 pub mod transport_runtime;
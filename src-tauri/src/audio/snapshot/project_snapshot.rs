@@ -8,10 +8,16 @@ use log::info;
 use nanoid::nanoid;
 use tauri::async_runtime;
 
+use crate::audio::engine::AUDIO_ENGINE;
+use crate::audio::meter_map::MeterMap;
 use crate::audio::snapshot::data_nodes::DataNodes;
 use crate::audio::snapshot::render_graph::RenderGraph;
 use crate::audio::snapshot::scheduler::Scheduler;
-use crate::core::constants::{PPQ_DEFAULT, TEMPO_BPM_DEFAULT};
+use crate::audio::tempo_map::TempoMap;
+use crate::core::constants::{
+    PPQ_DEFAULT, TEMPO_BPM_DEFAULT, TIME_SIGNATURE_DENOMINATOR_DEFAULT,
+    TIME_SIGNATURE_NUMERATOR_DEFAULT,
+};
 use crate::core::types::Id;
 
 #[derive(Clone)]
@@ -21,10 +27,15 @@ pub struct ProjectSnapshot {
     pub render_graph_version: Id,
     pub data_nodes_version: Id,
     pub ppq: u16,
-    pub tempo_bpm: f32,
+    pub tempo_map: Arc<TempoMap>,
+    pub meter_map: Arc<MeterMap>,
     pub scheduler: Arc<Scheduler>,
     pub render_graph: Arc<RenderGraph>,
     pub data_nodes: Arc<DataNodes>,
+    /// Frames the effect tails ring out for at the slowest tempo of the project, so it fits
+    /// wherever playback stops. Worked out when the snapshot is built, the render thread only
+    /// reads it.
+    pub max_tail_frames: usize,
 }
 
 impl ProjectSnapshot {
@@ -35,10 +46,16 @@ impl ProjectSnapshot {
             render_graph_version: nanoid!(),
             data_nodes_version: nanoid!(),
             ppq: PPQ_DEFAULT,
-            tempo_bpm: TEMPO_BPM_DEFAULT,
+            tempo_map: Arc::new(TempoMap::new(PPQ_DEFAULT, TEMPO_BPM_DEFAULT)),
+            meter_map: Arc::new(MeterMap::new(
+                PPQ_DEFAULT,
+                TIME_SIGNATURE_NUMERATOR_DEFAULT,
+                TIME_SIGNATURE_DENOMINATOR_DEFAULT,
+            )),
             scheduler: Arc::new(Scheduler::new()),
             render_graph: Arc::new(RenderGraph::new()),
             data_nodes: Arc::new(DataNodes::new()),
+            max_tail_frames: 0,
         }
     }
 
@@ -50,6 +67,57 @@ impl ProjectSnapshot {
         self.data_nodes.as_ref()
     }
 
+    /// Frames the insert effects keep sounding after the tracks go silent, along the longest
+    /// path from a track to the master.
+    pub fn tail_frames(&self, sample_rate: usize, tempo_bpm: f32) -> usize {
+        let mut node_tails = vec![None; self.render_graph.node_count()];
+        self.node_tail_frames(
+            RenderGraph::MASTER_INDEX,
+            sample_rate,
+            tempo_bpm,
+            &mut node_tails,
+        )
+    }
+
+    fn node_tail_frames(
+        &self,
+        node_index: usize,
+        sample_rate: usize,
+        tempo_bpm: f32,
+        node_tails: &mut Vec<Option<usize>>,
+    ) -> usize {
+        if let Some(tail_frames) = node_tails[node_index] {
+            return tail_frames;
+        }
+        let input_indices: Vec<usize> = self
+            .render_graph
+            .inputs(node_index)
+            .map(|(input_index, _, _)| input_index)
+            .collect();
+        let inputs_tail_frames = input_indices
+            .into_iter()
+            .map(|input_index| {
+                self.node_tail_frames(input_index, sample_rate, tempo_bpm, node_tails)
+            })
+            .max()
+            .unwrap_or(0);
+        let tail_frames = inputs_tail_frames
+            + self
+                .data_nodes
+                .nodes
+                .get(self.render_graph.node(node_index).data_node_id())
+                .and_then(|data_node| data_node.inserts())
+                .map_or(0, |inserts| inserts.tail_frames(sample_rate, tempo_bpm));
+        node_tails[node_index] = Some(tail_frames);
+        tail_frames
+    }
+
+    fn with_max_tail_frames(mut self) -> Self {
+        self.max_tail_frames =
+            self.tail_frames(AUDIO_ENGINE.sample_rate(), self.tempo_map.slowest_bpm());
+        self
+    }
+
     pub fn with_ppq(&self, ppq: u16) -> Self {
         Self {
             version: nanoid!(),
@@ -58,10 +126,19 @@ impl ProjectSnapshot {
         }
     }
 
-    pub fn with_tempo_bpm(&self, tempo_bpm: f32) -> Self {
+    pub fn with_tempo_map(&self, tempo_map: Arc<TempoMap>) -> Self {
         Self {
             version: nanoid!(),
-            tempo_bpm,
+            tempo_map,
+            ..self.clone()
+        }
+        .with_max_tail_frames()
+    }
+
+    pub fn with_meter_map(&self, meter_map: Arc<MeterMap>) -> Self {
+        Self {
+            version: nanoid!(),
+            meter_map,
             ..self.clone()
         }
     }
@@ -86,6 +163,7 @@ impl ProjectSnapshot {
             render_graph,
             ..self.clone()
         }
+        .with_max_tail_frames()
     }
 
     pub fn with_data_nodes(&self, data_nodes: Arc<DataNodes>, data_nodes_version: Id) -> Self {
@@ -95,6 +173,7 @@ impl ProjectSnapshot {
             data_nodes,
             ..self.clone()
         }
+        .with_max_tail_frames()
     }
 }
 
@@ -117,8 +196,12 @@ pub fn set_project_ppq(ppq: u16) {
     PROJECT_SNAPSHOT.rcu(|current| Arc::new(current.with_ppq(ppq)));
 }
 
-pub fn set_project_tempo_bpm(tempo_bpm: f32) {
-    PROJECT_SNAPSHOT.rcu(|current| Arc::new(current.with_tempo_bpm(tempo_bpm)));
+pub fn set_project_tempo_map(tempo_map: Arc<TempoMap>) {
+    PROJECT_SNAPSHOT.rcu(move |current| Arc::new(current.with_tempo_map(Arc::clone(&tempo_map))));
+}
+
+pub fn set_project_meter_map(meter_map: Arc<MeterMap>) {
+    PROJECT_SNAPSHOT.rcu(move |current| Arc::new(current.with_meter_map(Arc::clone(&meter_map))));
 }
 
 pub fn set_project_scheduler(scheduler: Arc<Scheduler>) {
@@ -138,73 +221,80 @@ pub fn set_project_data_nodes(data_nodes: Arc<DataNodes>) {
 }
 
 pub fn rebuild_scheduler() {
-    async_runtime::spawn_blocking(move || {
-        info!("Rebuilding scheduler...");
-        let gen = SCHEDULER_REBUILD_GEN.fetch_add(1, Ordering::SeqCst) + 1;
-        let scheduler_version = nanoid!();
+    async_runtime::spawn_blocking(rebuild_scheduler_blocking);
+}
 
-        let scheduler = Scheduler::build(|| SCHEDULER_REBUILD_GEN.load(Ordering::SeqCst) != gen);
+/// Same as `rebuild_scheduler`, but returns once the new scheduler is stored.
+pub fn rebuild_scheduler_blocking() {
+    info!("Rebuilding scheduler...");
+    let gen = SCHEDULER_REBUILD_GEN.fetch_add(1, Ordering::SeqCst) + 1;
+    let scheduler_version = nanoid!();
 
-        let Some(scheduler) = scheduler else {
-            return;
-        };
+    let scheduler = Scheduler::build(|| SCHEDULER_REBUILD_GEN.load(Ordering::SeqCst) != gen);
 
-        if SCHEDULER_REBUILD_GEN.load(Ordering::SeqCst) != gen {
-            return;
-        }
+    let Some(scheduler) = scheduler else {
+        return;
+    };
 
-        let scheduler = Arc::new(scheduler);
-        PROJECT_SNAPSHOT.rcu(move |current| {
-            Arc::new(current.with_scheduler(Arc::clone(&scheduler), scheduler_version.clone()))
-        });
+    if SCHEDULER_REBUILD_GEN.load(Ordering::SeqCst) != gen {
+        return;
+    }
+
+    let scheduler = Arc::new(scheduler);
+    PROJECT_SNAPSHOT.rcu(move |current| {
+        Arc::new(current.with_scheduler(Arc::clone(&scheduler), scheduler_version.clone()))
     });
 }
 
 pub fn rebuild_render_graph() {
-    async_runtime::spawn_blocking(move || {
-        info!("Rebuilding render graph...");
-        let gen = RENDER_GRAPH_REBUILD_GEN.fetch_add(1, Ordering::SeqCst) + 1;
-        let render_graph_version = nanoid!();
+    async_runtime::spawn_blocking(rebuild_render_graph_blocking);
+}
 
-        let render_graph =
-            RenderGraph::build(|| RENDER_GRAPH_REBUILD_GEN.load(Ordering::SeqCst) != gen);
+/// Same as `rebuild_render_graph`, but returns once the new render graph is stored.
+pub fn rebuild_render_graph_blocking() {
+    info!("Rebuilding render graph...");
+    let gen = RENDER_GRAPH_REBUILD_GEN.fetch_add(1, Ordering::SeqCst) + 1;
+    let render_graph_version = nanoid!();
 
-        let Some(render_graph) = render_graph else {
-            return;
-        };
+    let render_graph =
+        RenderGraph::build(|| RENDER_GRAPH_REBUILD_GEN.load(Ordering::SeqCst) != gen);
 
-        if RENDER_GRAPH_REBUILD_GEN.load(Ordering::SeqCst) != gen {
-            return;
-        }
+    let Some(render_graph) = render_graph else {
+        return;
+    };
 
-        let render_graph = Arc::new(render_graph);
-        PROJECT_SNAPSHOT.rcu(move |current| {
-            Arc::new(
-                current.with_render_graph(Arc::clone(&render_graph), render_graph_version.clone()),
-            )
-        });
+    if RENDER_GRAPH_REBUILD_GEN.load(Ordering::SeqCst) != gen {
+        return;
+    }
+
+    let render_graph = Arc::new(render_graph);
+    PROJECT_SNAPSHOT.rcu(move |current| {
+        Arc::new(current.with_render_graph(Arc::clone(&render_graph), render_graph_version.clone()))
     });
 }
 
 pub fn rebuild_data_nodes() {
-    async_runtime::spawn_blocking(move || {
-        info!("Rebuilding data nodes...");
-        let gen = DATA_NODES_REBUILD_GEN.fetch_add(1, Ordering::SeqCst) + 1;
-        let data_nodes_version = nanoid!();
+    async_runtime::spawn_blocking(rebuild_data_nodes_blocking);
+}
 
-        let data_nodes = DataNodes::build(|| DATA_NODES_REBUILD_GEN.load(Ordering::SeqCst) != gen);
+/// Same as `rebuild_data_nodes`, but returns once the new data nodes are stored.
+pub fn rebuild_data_nodes_blocking() {
+    info!("Rebuilding data nodes...");
+    let gen = DATA_NODES_REBUILD_GEN.fetch_add(1, Ordering::SeqCst) + 1;
+    let data_nodes_version = nanoid!();
 
-        let Some(data_nodes) = data_nodes else {
-            return;
-        };
+    let data_nodes = DataNodes::build(|| DATA_NODES_REBUILD_GEN.load(Ordering::SeqCst) != gen);
 
-        if DATA_NODES_REBUILD_GEN.load(Ordering::SeqCst) != gen {
-            return;
-        }
+    let Some(data_nodes) = data_nodes else {
+        return;
+    };
+
+    if DATA_NODES_REBUILD_GEN.load(Ordering::SeqCst) != gen {
+        return;
+    }
 
-        let data_nodes = Arc::new(data_nodes);
-        PROJECT_SNAPSHOT.rcu(move |current| {
-            Arc::new(current.with_data_nodes(Arc::clone(&data_nodes), data_nodes_version.clone()))
-        });
+    let data_nodes = Arc::new(data_nodes);
+    PROJECT_SNAPSHOT.rcu(move |current| {
+        Arc::new(current.with_data_nodes(Arc::clone(&data_nodes), data_nodes_version.clone()))
     });
 }
@@ -1,39 +1,132 @@
 use std::collections::HashMap;
+use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::Mutex;
 
+use anyhow::{anyhow, Result};
+use log::warn;
 use nanoid::nanoid;
-use petgraph::{graph::NodeIndex, Graph};
+use petgraph::{algo::toposort, graph::NodeIndex, visit::EdgeRef, Direction, Graph};
 
+use crate::audio::engine::AUDIO_ENGINE;
 use crate::audio::project_state::PROJECT_STATE;
-use crate::audio::snapshot::project_snapshot::ProjectSnapshot;
+use crate::audio::thread_pool::TaskGraph;
+use crate::audio::track::SendTap;
 use crate::core::types::{EngineSampleFormat, Id};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphNodeKind {
+    Track,
+    Bus,
+    Master,
+}
+
 pub struct GraphNode {
     data_node_id: Id,
+    kind: GraphNodeKind,
 }
 
 impl GraphNode {
-    pub fn new(data_node_id: Id) -> Self {
-        Self { data_node_id }
+    pub fn new(data_node_id: Id, kind: GraphNodeKind) -> Self {
+        Self { data_node_id, kind }
+    }
+
+    pub fn data_node_id(&self) -> &Id {
+        &self.data_node_id
+    }
+
+    pub fn kind(&self) -> GraphNodeKind {
+        self.kind
+    }
+}
+
+/// How a node feeds another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderEdge {
+    /// The node's main output, at unity gain.
+    Output,
+    /// Aux send, weighted by the send level in the source's data node.
+    Send(SendTap),
+}
+
+/// Output of a node for the current block.
+pub struct NodeBuffer {
+    pub samples: Vec<EngineSampleFormat>,
+    /// Signal before volume and pan, only kept for nodes with pre-fader sends.
+    pub pre_fader_samples: Option<Vec<EngineSampleFormat>>,
+    /// Gains applied to the previous block, ramped from to avoid clicks. `None` jumps right away.
+    pub previous_gains: Option<[f32; 2]>,
+}
+
+/// Buffers and scheduling state to render the graph, one entry per node index.
+/// Allocated with the graph off the render thread, so rendering doesn't allocate.
+/// Only one renderer can use them at a time.
+pub struct RenderGraphBuffers {
+    buffer_size: usize,
+    in_use: AtomicBool,
+    pub tasks: TaskGraph,
+    pub nodes: Vec<Mutex<NodeBuffer>>,
+    /// Send level applied to the previous block, by edge index, ramped from like node gains.
+    pub previous_send_levels: Vec<Mutex<Option<f32>>>,
+}
+
+/// Exclusive use of a graph's buffers, released on drop.
+pub struct ClaimedBuffers<'a>(&'a RenderGraphBuffers);
+
+impl Deref for ClaimedBuffers<'_> {
+    type Target = RenderGraphBuffers;
+
+    fn deref(&self) -> &RenderGraphBuffers {
+        self.0
+    }
+}
+
+impl Drop for ClaimedBuffers<'_> {
+    fn drop(&mut self) {
+        self.0.in_use.store(false, Ordering::Release);
     }
 }
 
+/// Signal flow of the project, as stored on its tracks.
+#[derive(Debug, Clone, Default)]
+pub struct Routing {
+    /// Output of every track, `None` for the master.
+    pub track_outputs: Vec<(Id, Option<Id>)>,
+    /// Output of every bus, `None` for the master.
+    pub bus_outputs: Vec<(Id, Option<Id>)>,
+    /// Aux sends from a track to a bus.
+    pub sends: Vec<(Id, Id, SendTap)>,
+}
+
+/// Signal flow from tracks through buses to the master.
+/// Edges point from a node to the node it outputs to, and the graph is always acyclic.
+/// Node indices are contiguous, the master is always at `MASTER_INDEX`.
 pub struct RenderGraph {
     version: Id,
-    graph: Graph<GraphNode, ()>,
+    graph: Graph<GraphNode, RenderEdge>,
     id_to_index_map: HashMap<Id, NodeIndex>,
+    buffers: RenderGraphBuffers,
 }
 
 impl RenderGraph {
     const MASTER_NODE_ID: &'static str = "master";
+    pub const MASTER_INDEX: usize = 0;
 
     pub fn new() -> Self {
-        Self {
+        let mut render_graph = Self {
             version: nanoid!(),
             graph: Graph::new(),
             id_to_index_map: HashMap::new(),
-        }
+            buffers: RenderGraphBuffers {
+                buffer_size: 0,
+                in_use: AtomicBool::new(false),
+                tasks: TaskGraph::new(0),
+                nodes: Vec::new(),
+                previous_send_levels: Vec::new(),
+            },
+        };
+        render_graph.add_node(&Self::MASTER_NODE_ID.to_string(), GraphNodeKind::Master);
+        render_graph
     }
 
     pub fn get_node_by_index(&self, index: NodeIndex) -> Option<&GraphNode> {
@@ -46,43 +139,231 @@ impl RenderGraph {
             .and_then(|index| self.graph.node_weight(*index))
     }
 
+    pub fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    pub fn node(&self, index: usize) -> &GraphNode {
+        &self.graph[NodeIndex::new(index)]
+    }
+
+    /// Nodes feeding `index`, as node index, edge index and edge.
+    pub fn inputs(&self, index: usize) -> impl Iterator<Item = (usize, usize, RenderEdge)> + '_ {
+        self.graph
+            .edges_directed(NodeIndex::new(index), Direction::Incoming)
+            .map(|edge| (edge.source().index(), edge.id().index(), *edge.weight()))
+    }
+
+    fn add_node(&mut self, id: &Id, kind: GraphNodeKind) -> NodeIndex {
+        let index = self.graph.add_node(GraphNode::new(id.clone(), kind));
+        self.id_to_index_map.insert(id.clone(), index);
+        index
+    }
+
+    /// Allocates buffers for `buffer_size` interleaved samples per node, with a task per node
+    /// that depends on the nodes feeding it.
+    pub fn allocate_buffers(&self, buffer_size: usize) -> RenderGraphBuffers {
+        let mut tasks = TaskGraph::new(self.node_count());
+        for edge in self.graph.edge_references() {
+            tasks.add_dependency(edge.source().index(), edge.target().index());
+        }
+        let nodes = self
+            .graph
+            .node_indices()
+            .map(|index| {
+                let has_pre_fader_send = self
+                    .graph
+                    .edges_directed(index, Direction::Outgoing)
+                    .any(|edge| *edge.weight() == RenderEdge::Send(SendTap::PreFader));
+                Mutex::new(NodeBuffer {
+                    samples: vec![0.0; buffer_size],
+                    pre_fader_samples: has_pre_fader_send.then(|| vec![0.0; buffer_size]),
+                    previous_gains: None,
+                })
+            })
+            .collect();
+        let previous_send_levels = (0..self.graph.edge_count())
+            .map(|_| Mutex::new(None))
+            .collect();
+        RenderGraphBuffers {
+            buffer_size,
+            in_use: AtomicBool::new(false),
+            tasks,
+            nodes,
+            previous_send_levels,
+        }
+    }
+
+    /// The graph's own buffers, unless they are in use or sized for another buffer size.
+    /// Lock-free, so the render thread can try it on every block.
+    pub fn try_claim_buffers(&self, buffer_size: usize) -> Option<ClaimedBuffers<'_>> {
+        if self.buffers.buffer_size != buffer_size {
+            return None;
+        }
+        self.buffers
+            .in_use
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()?;
+        Some(ClaimedBuffers(&self.buffers))
+    }
+
+    /// Builds the graph for the given routing, without buffers. Outputs to unknown buses go to
+    /// the master and sends to unknown buses are dropped.
+    /// Fails when the routing contains a feedback loop.
+    pub fn from_routing(routing: &Routing) -> Result<Self> {
+        let mut render_graph = RenderGraph::new();
+        let master_node = NodeIndex::new(Self::MASTER_INDEX);
+        let is_bus = |id: &Id| routing.bus_outputs.iter().any(|(bus_id, _)| bus_id == id);
+
+        for (id, _) in routing.track_outputs.iter() {
+            render_graph.add_node(id, GraphNodeKind::Track);
+        }
+        for (id, _) in routing.bus_outputs.iter() {
+            render_graph.add_node(id, GraphNodeKind::Bus);
+        }
+        for (id, output_id) in routing
+            .track_outputs
+            .iter()
+            .chain(routing.bus_outputs.iter())
+        {
+            let source = render_graph.id_to_index_map[id];
+            let destination = output_id
+                .as_ref()
+                .filter(|output_id| is_bus(output_id))
+                .map_or(master_node, |output_id| {
+                    render_graph.id_to_index_map[output_id]
+                });
+            render_graph
+                .graph
+                .add_edge(source, destination, RenderEdge::Output);
+        }
+        for (id, bus_id, tap) in routing.sends.iter() {
+            let (Some(source), true) = (render_graph.id_to_index_map.get(id), is_bus(bus_id))
+            else {
+                continue;
+            };
+            let destination = render_graph.id_to_index_map[bus_id];
+            render_graph
+                .graph
+                .add_edge(*source, destination, RenderEdge::Send(*tap));
+        }
+
+        toposort(&render_graph.graph, None).map_err(|cycle| {
+            let id = &render_graph.graph[cycle.node_id()].data_node_id;
+            anyhow!("Routing would create a feedback loop through {id}")
+        })?;
+
+        Ok(render_graph)
+    }
+
     pub fn build(should_abort: impl Fn() -> bool) -> Option<Self> {
         if should_abort() {
             return None;
         }
 
-        let mut new_render_graph = RenderGraph::new();
-        let aborted = AtomicBool::new(false);
+        let routing = PROJECT_STATE.routing();
 
-        let master_node = new_render_graph
-            .graph
-            .add_node(GraphNode::new(Self::MASTER_NODE_ID.to_string()));
-        new_render_graph
-            .id_to_index_map
-            .insert(Self::MASTER_NODE_ID.to_string(), master_node);
-
-        PROJECT_STATE.with_tracks(|tracks| {
-            for (track_id, _) in tracks.iter() {
-                if should_abort() {
-                    aborted.store(true, Ordering::SeqCst);
-                    return;
-                }
-
-                let track_node = new_render_graph
-                    .graph
-                    .add_node(GraphNode::new(track_id.clone()));
-                new_render_graph
-                    .id_to_index_map
-                    .insert(track_id.clone(), track_node);
+        if should_abort() {
+            return None;
+        }
 
-                new_render_graph.graph.add_edge(track_node, master_node, ());
+        let mut render_graph = match Self::from_routing(&routing) {
+            Ok(render_graph) => render_graph,
+            Err(e) => {
+                warn!("Keeping the previous render graph: {e}");
+                return None;
             }
+        };
+        render_graph.buffers =
+            render_graph.allocate_buffers(AUDIO_ENGINE.buffer_size() * AUDIO_ENGINE.num_channels());
+        Some(render_graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(id: &str) -> Id {
+        id.to_string()
+    }
+
+    /// Data node ids feeding `node_id` with their edge, sorted for comparing.
+    fn inputs_of(render_graph: &RenderGraph, node_id: &str) -> Vec<(Id, RenderEdge)> {
+        let index = render_graph.id_to_index_map[node_id].index();
+        let mut inputs: Vec<(Id, RenderEdge)> = render_graph
+            .inputs(index)
+            .map(|(input_index, _, edge)| {
+                (render_graph.node(input_index).data_node_id().clone(), edge)
+            })
+            .collect();
+        inputs.sort_by(|a, b| a.0.cmp(&b.0));
+        inputs
+    }
+
+    #[test]
+    fn routes_tracks_through_buses_to_master() {
+        let render_graph = RenderGraph::from_routing(&Routing {
+            track_outputs: vec![(id("track"), Some(id("bus")))],
+            bus_outputs: vec![(id("bus"), None)],
+            sends: vec![],
+        })
+        .unwrap();
+        assert_eq!(render_graph.node_count(), 3);
+        assert_eq!(
+            inputs_of(&render_graph, "bus"),
+            [(id("track"), RenderEdge::Output)]
+        );
+        assert_eq!(
+            inputs_of(&render_graph, RenderGraph::MASTER_NODE_ID),
+            [(id("bus"), RenderEdge::Output)]
+        );
+    }
+
+    #[test]
+    fn rejects_bus_cycles() {
+        let outputs_loop = RenderGraph::from_routing(&Routing {
+            bus_outputs: vec![(id("a"), Some(id("b"))), (id("b"), Some(id("a")))],
+            ..Default::default()
         });
+        assert!(outputs_loop.is_err());
 
-        if aborted.load(Ordering::SeqCst) {
-            return None;
-        }
+        let send_loop = RenderGraph::from_routing(&Routing {
+            bus_outputs: vec![(id("a"), Some(id("b"))), (id("b"), None)],
+            sends: vec![(id("b"), id("a"), SendTap::PostFader)],
+            ..Default::default()
+        });
+        assert!(send_loop.is_err());
+    }
+
+    #[test]
+    fn unknown_output_goes_to_master() {
+        let render_graph = RenderGraph::from_routing(&Routing {
+            track_outputs: vec![(id("track"), Some(id("missing")))],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            inputs_of(&render_graph, RenderGraph::MASTER_NODE_ID),
+            [(id("track"), RenderEdge::Output)]
+        );
+    }
 
-        Some(new_render_graph)
+    #[test]
+    fn drops_sends_to_unknown_buses() {
+        let render_graph = RenderGraph::from_routing(&Routing {
+            track_outputs: vec![(id("track"), None)],
+            bus_outputs: vec![(id("bus"), None)],
+            sends: vec![
+                (id("track"), id("missing"), SendTap::PostFader),
+                (id("track"), id("bus"), SendTap::PreFader),
+            ],
+        })
+        .unwrap();
+        assert_eq!(render_graph.graph.edge_count(), 3);
+        assert_eq!(
+            inputs_of(&render_graph, "bus"),
+            [(id("track"), RenderEdge::Send(SendTap::PreFader))]
+        );
     }
 }
@@ -1,8 +1,13 @@
 use crate::audio::snapshot::clip_event::ClipEvent;
 use crate::audio::snapshot::project_snapshot::ProjectSnapshot;
 use crate::audio::track::GeneratorTrack;
-use crate::audio::{asset_pool::ASSET_POOL, engine::AUDIO_ENGINE, project_state::PROJECT_STATE};
-use crate::core::types::Id;
+use crate::audio::{
+    asset_pool::ASSET_POOL,
+    engine::AUDIO_ENGINE,
+    metering::{LevelMeter, METERING},
+    project_state::PROJECT_STATE,
+};
+use crate::core::types::{EngineSampleFormat, Id};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
@@ -13,57 +18,36 @@ use std::sync::Arc;
 //     Automation(AutomationEvent),
 // }
 
+/// Clips of a track in samples. Volume, pan and mute live in the track's data node,
+/// so mix changes don't need a new scheduler.
 pub struct SchedulerAudioTrack {
     pub id: Id,
     pub name: String,
-    pub volume: f32,
-    pub pan: f32,
-    pub muted: bool,
     pub clips: Vec<ClipEvent>,
+    pub meter: Arc<LevelMeter>,
 }
 
 impl SchedulerAudioTrack {
+    /// Renders every clip overlapping `out`, which starts at `position_samples`.
+    /// Stateless, so jumping around (loops, seeks) always renders the right part of each clip.
     pub fn render(
         &self,
         position_samples: usize,
-        out: &mut Vec<f32>,
+        out: &mut [EngineSampleFormat],
         snapshot: Arc<ProjectSnapshot>,
     ) {
         out.fill(0.0);
-        if self.clips.is_empty() {
-            return;
-        }
-        let needed_samples_count = out.len();
-        let mut current_clip: Option<&ClipEvent> = None;
-        let mut current_clip_index = 0;
-
-        // TODO optimize this by remembering the last clip index
-        for i in 0..self.clips.len() {
-            let clip = &self.clips[i];
-            if clip.is_scheduled_at_position(position_samples, needed_samples_count) {
-                current_clip_index = i;
-                current_clip = Some(clip);
+        let end_sample = position_samples + out.len();
+
+        // Clips are sorted by start, later clips play over earlier ones
+        for clip in self.clips.iter() {
+            if clip.start_sample >= end_sample {
                 break;
             }
-        }
-
-        let mut filled_samples_count = 0;
-        while filled_samples_count < needed_samples_count
-            && current_clip.is_some()
-            && current_clip
-                .unwrap()
-                .is_scheduled_at_position(position_samples, needed_samples_count)
-        {
-            let clip = current_clip.unwrap();
-            filled_samples_count = clip.render(
-                position_samples,
-                &mut out[filled_samples_count..needed_samples_count],
-                snapshot.clone(),
-            );
-            if filled_samples_count < needed_samples_count {
-                current_clip_index += 1;
-                current_clip = self.clips.get(current_clip_index);
+            if clip.end_sample <= position_samples {
+                continue;
             }
+            clip.render(position_samples, out, snapshot.clone());
         }
     }
 }
@@ -77,6 +61,15 @@ impl Scheduler {
         Self { tracks: Vec::new() }
     }
 
+    /// Interleaved sample position where the last scheduled clip ends.
+    pub fn end_sample(&self) -> usize {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.clips.iter().map(|clip| clip.end_sample).max())
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn build(should_abort: impl Fn() -> bool) -> Option<Self> {
         if should_abort() {
             return None;
@@ -85,9 +78,6 @@ impl Scheduler {
         let mut new_scheduler = Scheduler::new();
         let aborted = AtomicBool::new(false);
 
-        let tempo_bpm = PROJECT_STATE.tempo_bpm() as f64;
-        let ppq = PROJECT_STATE.ppq() as f64;
-        let sample_rate = AUDIO_ENGINE.sample_rate() as f64;
         let channels = AUDIO_ENGINE.num_channels();
 
         PROJECT_STATE.with_tracks(|tracks| {
@@ -97,22 +87,16 @@ impl Scheduler {
                     return;
                 }
 
-                let (id, name, volume, pan, muted, clips) = match track {
-                    GeneratorTrack::AudioTrack(t) => {
-                        (&t.id, &t.name, t.volume, t.pan, t.muted, &t.clips)
-                    }
-                    GeneratorTrack::SamplerTrack(t) => {
-                        (&t.id, &t.name, t.volume, t.pan, t.muted, &t.clips)
-                    }
+                let (id, name, clips) = match track {
+                    GeneratorTrack::AudioTrack(t) => (&t.id, &t.name, &t.clips),
+                    GeneratorTrack::SamplerTrack(t) => (&t.id, &t.name, &t.clips),
                 };
 
                 let mut scheduler_track = SchedulerAudioTrack {
                     id: id.clone(),
                     name: name.clone(),
-                    volume,
-                    pan,
-                    muted,
                     clips: Vec::new(),
+                    meter: METERING.track_meter(id),
                 };
 
                 for clip in clips.values() {
@@ -141,10 +125,7 @@ impl Scheduler {
                         continue;
                     }
 
-                    let beats = clip.start_ppq as f64 / ppq;
-                    let seconds = (beats * 60.0) / tempo_bpm;
-                    let start_frames = (seconds * sample_rate).round() as usize;
-                    let start_sample = start_frames * channels;
+                    let start_sample = PROJECT_STATE.ppq_to_samples(clip.start_ppq);
                     let end_sample = start_sample.saturating_add(remaining_samples);
 
                     scheduler_track.clips.push(ClipEvent {
@@ -163,6 +144,13 @@ impl Scheduler {
             return None;
         }
 
+        let track_ids: Vec<Id> = new_scheduler
+            .tracks
+            .iter()
+            .map(|track| track.id.clone())
+            .collect();
+        METERING.retain_tracks(&track_ids);
+
         Some(new_scheduler)
     }
 }
@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::core::types::Id;
+
+/// Solo state of the project, collected when the data nodes are built.
+pub struct SoloState {
+    pub soloed: HashSet<Id>,
+    pub solo_safe: HashSet<Id>,
+    /// Every generator and bus track.
+    pub track_ids: Vec<Id>,
+    /// Signal flow between tracks and buses, from source to destination.
+    pub edges: Vec<(Id, Id)>,
+}
+
+impl SoloState {
+    /// Tracks silenced because something else is soloed.
+    /// A track stays audible when it is soloed, solo-safe, feeds a soloed track
+    /// or is fed by one, so soloing a track keeps the buses it reaches audible.
+    pub fn implied_mutes(&self) -> HashSet<Id> {
+        if self.soloed.is_empty() {
+            return HashSet::new();
+        }
+
+        let mut downstream: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut upstream: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (source, destination) in self.edges.iter() {
+            downstream.entry(source).or_default().push(destination);
+            upstream.entry(destination).or_default().push(source);
+        }
+
+        let mut audible: HashSet<&str> = self.solo_safe.iter().map(|id| id.as_str()).collect();
+        for links in [&downstream, &upstream] {
+            let mut pending: Vec<&str> = self.soloed.iter().map(|id| id.as_str()).collect();
+            let mut visited: HashSet<&str> = HashSet::new();
+            while let Some(id) = pending.pop() {
+                if !visited.insert(id) {
+                    continue;
+                }
+                audible.insert(id);
+                if let Some(next) = links.get(id) {
+                    pending.extend(next.iter().copied());
+                }
+            }
+        }
+
+        self.track_ids
+            .iter()
+            .filter(|id| !audible.contains(id.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids<T: FromIterator<Id>>(ids: &[&str]) -> T {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    /// Drums and bass feed the group bus, which feeds the master; vocals go straight to the
+    /// master with a send to the reverb bus.
+    fn solo_state(soloed: &[&str], solo_safe: &[&str]) -> SoloState {
+        SoloState {
+            soloed: ids(soloed),
+            solo_safe: ids(solo_safe),
+            track_ids: ids(&["drums", "bass", "vocals", "group", "reverb"]),
+            edges: [("drums", "group"), ("bass", "group"), ("vocals", "reverb")]
+                .iter()
+                .map(|(source, destination)| (source.to_string(), destination.to_string()))
+                .collect(),
+        }
+    }
+
+    fn muted(solo_state: &SoloState) -> Vec<String> {
+        let mut muted: Vec<String> = solo_state.implied_mutes().into_iter().collect();
+        muted.sort();
+        muted
+    }
+
+    #[test]
+    fn nothing_soloed_mutes_nothing() {
+        assert!(solo_state(&[], &["reverb"]).implied_mutes().is_empty());
+    }
+
+    #[test]
+    fn soloed_track_keeps_its_buses_audible() {
+        assert_eq!(
+            muted(&solo_state(&["drums"], &[])),
+            ["bass", "reverb", "vocals"]
+        );
+    }
+
+    #[test]
+    fn soloed_bus_keeps_its_sources_audible() {
+        assert_eq!(muted(&solo_state(&["group"], &[])), ["reverb", "vocals"]);
+    }
+
+    #[test]
+    fn solo_safe_bus_stays_audible() {
+        assert_eq!(
+            muted(&solo_state(&["drums"], &["reverb"])),
+            ["bass", "vocals"]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, sync::Arc};
+
+use nanoid::nanoid;
+
+use crate::audio::{
+    asset_pool::ASSET_POOL,
+    decoder::DecodedAudioData,
+    metering::LevelMeter,
+    snapshot::{
+        clip_event::ClipEvent,
+        clip_node::ClipNode,
+        data_nodes::{DataNode, DataNodes},
+        insert_chain::InsertChain,
+        mix_state::MixState,
+        project_snapshot::ProjectSnapshot,
+        render_graph::{RenderGraph, Routing},
+        scheduler::{Scheduler, SchedulerAudioTrack},
+        track_node::TrackNode,
+    },
+};
+
+/// One track at unity gain playing `source` from `source_offset` at `clip_start` until it runs
+/// out, straight into the master. Positions are interleaved stereo samples.
+pub fn clip_snapshot(
+    source: &[f32],
+    sample_rate: usize,
+    clip_start: usize,
+    source_offset: usize,
+) -> Arc<ProjectSnapshot> {
+    // The pool hands out the asset already loaded from a path, so every source gets its own
+    let source_id = ASSET_POOL.audio.add(DecodedAudioData {
+        data: source.to_vec(),
+        original_num_channels: 2,
+        original_sample_rate: sample_rate,
+        sample_rate,
+        file_path: format!("test-fixtures/{}.wav", nanoid!()),
+        file_name: "source.wav".to_string(),
+    });
+    let track_id = "track".to_string();
+    let clip_id = "clip".to_string();
+
+    let scheduler = Scheduler {
+        tracks: vec![Arc::new(SchedulerAudioTrack {
+            id: track_id.clone(),
+            name: "Track".to_string(),
+            clips: vec![ClipEvent {
+                start_sample: clip_start,
+                end_sample: clip_start + source.len() - source_offset,
+                node_id: clip_id.clone(),
+            }],
+            meter: Arc::new(LevelMeter::new()),
+        })],
+    };
+    let render_graph = RenderGraph::from_routing(&Routing {
+        track_outputs: vec![(track_id.clone(), None)],
+        ..Default::default()
+    })
+    .unwrap();
+    let mut data_nodes = DataNodes::new();
+    data_nodes.nodes = HashMap::from([
+        (
+            track_id,
+            DataNode::TrackNode(TrackNode {
+                mix: Arc::new(MixState::new(1.0, 0.0, false)),
+                implied_muted: false,
+                sends: HashMap::new(),
+                inserts: InsertChain { effects: vec![] },
+            }),
+        ),
+        (
+            clip_id,
+            DataNode::ClipNode(ClipNode {
+                source: ASSET_POOL.audio.get_by_id(&source_id),
+                source_id,
+                source_offset_samples: source_offset,
+            }),
+        ),
+    ]);
+
+    let snapshot = ProjectSnapshot::new()
+        .with_scheduler(Arc::new(scheduler), "scheduler".to_string())
+        .with_render_graph(Arc::new(render_graph), "render-graph".to_string())
+        .with_data_nodes(Arc::new(data_nodes), "data-nodes".to_string());
+    Arc::new(snapshot)
+}
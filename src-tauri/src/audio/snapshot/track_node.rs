@@ -1,5 +1,35 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    audio::{
+        gain::PanLaw,
+        snapshot::{insert_chain::InsertChain, mix_state::MixState},
+        track::SendTap,
+    },
+    core::types::Id,
+};
+
 pub struct TrackNode {
-    pub volume: f32,
-    pub pan: f32,
-    pub muted: bool,
+    pub mix: Arc<MixState>,
+    /// Silenced because other tracks are soloed.
+    pub implied_muted: bool,
+    /// Send level by destination bus.
+    pub sends: HashMap<Id, f32>,
+    pub inserts: InsertChain,
+}
+
+impl TrackNode {
+    pub fn gains(&self, pan_law: PanLaw) -> [f32; 2] {
+        self.mix.gains(self.implied_muted, pan_law)
+    }
+
+    /// Level of the send to `bus_id`. Post-fader sends are fed the already muted signal,
+    /// pre-fader ones are silenced here so a muted track never reaches its sends.
+    pub fn send_level(&self, bus_id: &str, tap: SendTap) -> f32 {
+        let level = self.sends.get(bus_id).copied().unwrap_or(0.0);
+        match tap {
+            SendTap::PreFader if self.mix.is_muted() || self.implied_muted => 0.0,
+            _ => level,
+        }
+    }
 }
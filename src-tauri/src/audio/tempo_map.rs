@@ -0,0 +1,316 @@
+use anyhow::{bail, Result};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    constants::{TEMPO_BPM_MAX, TEMPO_BPM_MIN},
+    types::Id,
+};
+
+/// How the tempo gets from the previous event to this one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TempoCurve {
+    /// The tempo jumps at the event.
+    Instant,
+    /// The tempo changes linearly over the beats since the previous event.
+    Linear,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TempoEvent {
+    pub id: Id,
+    pub position_ppq: usize,
+    pub bpm: f32,
+    pub curve: TempoCurve,
+}
+
+/// Span between two events, or from the last event on, where the tempo is constant or ramps linearly.
+#[derive(Debug, Clone)]
+struct TempoSegment {
+    start_ppq: f64,
+    start_seconds: f64,
+    start_bpm: f64,
+    /// Change of bpm per beat, zero for a constant tempo.
+    slope: f64,
+}
+
+impl TempoSegment {
+    fn beats_to_seconds(&self, beats: f64) -> f64 {
+        if self.slope.abs() < f64::EPSILON {
+            return beats * 60.0 / self.start_bpm;
+        }
+        // Integral of 60 / (start_bpm + slope * b) db
+        60.0 / self.slope * ((self.start_bpm + self.slope * beats) / self.start_bpm).ln()
+    }
+
+    fn seconds_to_beats(&self, seconds: f64) -> f64 {
+        if self.slope.abs() < f64::EPSILON {
+            return seconds * self.start_bpm / 60.0;
+        }
+        self.start_bpm * ((self.slope * seconds / 60.0).exp() - 1.0) / self.slope
+    }
+}
+
+/// Tempo changes of the project, sorted by position. There is always an event at ppq 0.
+/// Conversions integrate the tempo curve, so positions stay exact across ramps.
+/// Immutable once built; edits produce a new map that replaces the old one.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    ppq: u16,
+    events: Vec<TempoEvent>,
+    segments: Vec<TempoSegment>,
+}
+
+impl TempoMap {
+    pub fn new(ppq: u16, bpm: f32) -> Self {
+        Self::from_events(
+            ppq,
+            vec![TempoEvent {
+                id: nanoid!(),
+                position_ppq: 0,
+                bpm,
+                curve: TempoCurve::Instant,
+            }],
+        )
+    }
+
+    fn from_events(ppq: u16, mut events: Vec<TempoEvent>) -> Self {
+        events.sort_by_key(|event| event.position_ppq);
+        let ppq_f = ppq as f64;
+
+        let mut segments: Vec<TempoSegment> = Vec::with_capacity(events.len());
+        let mut start_seconds = 0.0;
+        for (index, event) in events.iter().enumerate() {
+            let start_ppq = event.position_ppq as f64;
+            let start_bpm = event.bpm as f64;
+            let slope = match events.get(index + 1) {
+                Some(next) if next.curve == TempoCurve::Linear => {
+                    let beats = (next.position_ppq - event.position_ppq) as f64 / ppq_f;
+                    (next.bpm as f64 - start_bpm) / beats
+                }
+                _ => 0.0,
+            };
+            if let Some(previous) = segments.last() {
+                let beats = (start_ppq - previous.start_ppq) / ppq_f;
+                start_seconds = previous.start_seconds + previous.beats_to_seconds(beats);
+            }
+            segments.push(TempoSegment {
+                start_ppq,
+                start_seconds,
+                start_bpm,
+                slope,
+            });
+        }
+
+        Self {
+            ppq,
+            events,
+            segments,
+        }
+    }
+
+    pub fn events(&self) -> &[TempoEvent] {
+        &self.events
+    }
+
+    fn segment_at_ppq(&self, position_ppq: f64) -> &TempoSegment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start_ppq <= position_ppq);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    fn segment_at_seconds(&self, seconds: f64) -> &TempoSegment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start_seconds <= seconds);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    /// Lowest tempo anywhere in the map, ramps only run between events.
+    pub fn slowest_bpm(&self) -> f32 {
+        self.events
+            .iter()
+            .map(|event| event.bpm)
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    pub fn bpm_at(&self, position_ppq: usize) -> f32 {
+        let segment = self.segment_at_ppq(position_ppq as f64);
+        let beats = (position_ppq as f64 - segment.start_ppq) / self.ppq as f64;
+        (segment.start_bpm + segment.slope * beats) as f32
+    }
+
+    pub fn ppq_to_seconds(&self, position_ppq: f64) -> f64 {
+        let segment = self.segment_at_ppq(position_ppq);
+        let beats = (position_ppq - segment.start_ppq) / self.ppq as f64;
+        segment.start_seconds + segment.beats_to_seconds(beats)
+    }
+
+    pub fn seconds_to_ppq(&self, seconds: f64) -> f64 {
+        let segment = self.segment_at_seconds(seconds);
+        let beats = segment.seconds_to_beats(seconds - segment.start_seconds);
+        segment.start_ppq + beats * self.ppq as f64
+    }
+
+    /// Converts a ppq position into an interleaved sample position.
+    pub fn ppq_to_samples(
+        &self,
+        position_ppq: usize,
+        sample_rate: usize,
+        channels: usize,
+    ) -> usize {
+        let seconds = self.ppq_to_seconds(position_ppq as f64);
+        let frames = (seconds * sample_rate as f64).round() as usize;
+        frames * channels
+    }
+
+    /// Converts an interleaved sample position into a ppq position.
+    pub fn samples_to_ppq(
+        &self,
+        position_samples: usize,
+        sample_rate: usize,
+        channels: usize,
+    ) -> usize {
+        let seconds = (position_samples / channels) as f64 / sample_rate as f64;
+        self.seconds_to_ppq(seconds).round() as usize
+    }
+
+    /// Length in ppq of `seconds` of audio starting at `start_ppq`.
+    pub fn seconds_to_length_ppq(&self, start_ppq: usize, seconds: f64) -> usize {
+        let start_seconds = self.ppq_to_seconds(start_ppq as f64);
+        let end_ppq = self.seconds_to_ppq(start_seconds + seconds);
+        (end_ppq.round() as usize).saturating_sub(start_ppq)
+    }
+
+    fn validate(position_ppq: usize, bpm: f32, other_events: &[TempoEvent]) -> Result<()> {
+        if !(TEMPO_BPM_MIN..=TEMPO_BPM_MAX).contains(&bpm) {
+            bail!("Tempo must be between {TEMPO_BPM_MIN} and {TEMPO_BPM_MAX} bpm");
+        }
+        if other_events
+            .iter()
+            .any(|event| event.position_ppq == position_ppq)
+        {
+            bail!("There is already a tempo change at this position");
+        }
+        Ok(())
+    }
+
+    pub fn with_added_event(
+        &self,
+        position_ppq: usize,
+        bpm: f32,
+        curve: TempoCurve,
+    ) -> Result<(Self, TempoEvent)> {
+        Self::validate(position_ppq, bpm, &self.events)?;
+        let event = TempoEvent {
+            id: nanoid!(),
+            position_ppq,
+            bpm,
+            curve,
+        };
+        let mut events = self.events.clone();
+        events.push(event.clone());
+        Ok((Self::from_events(self.ppq, events), event))
+    }
+
+    /// The first event stays at ppq 0 and can only change its tempo.
+    pub fn with_updated_event(
+        &self,
+        event_id: &str,
+        position_ppq: usize,
+        bpm: f32,
+        curve: TempoCurve,
+    ) -> Result<(Self, TempoEvent)> {
+        let Some(index) = self.events.iter().position(|event| event.id == event_id) else {
+            bail!("Tempo change not found: {event_id}");
+        };
+        let (position_ppq, curve) = if index == 0 {
+            (0, TempoCurve::Instant)
+        } else {
+            (position_ppq, curve)
+        };
+        let mut events = self.events.clone();
+        let event = events.remove(index);
+        Self::validate(position_ppq, bpm, &events)?;
+        let event = TempoEvent {
+            position_ppq,
+            bpm,
+            curve,
+            ..event
+        };
+        events.push(event.clone());
+        Ok((Self::from_events(self.ppq, events), event))
+    }
+
+    pub fn with_removed_event(&self, event_id: &str) -> Result<Self> {
+        let Some(index) = self.events.iter().position(|event| event.id == event_id) else {
+            bail!("Tempo change not found: {event_id}");
+        };
+        if index == 0 {
+            bail!("The tempo at the project start can't be removed");
+        }
+        let mut events = self.events.clone();
+        events.remove(index);
+        Ok(Self::from_events(self.ppq, events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PPQ: u16 = 960;
+
+    #[test]
+    fn constant_tempo_converts_exactly() {
+        let tempo_map = TempoMap::new(PPQ, 120.0);
+        assert_eq!(tempo_map.ppq_to_samples(960, 48000, 2), 48000);
+        assert_eq!(tempo_map.samples_to_ppq(48000, 48000, 2), 960);
+        assert_eq!(tempo_map.seconds_to_length_ppq(960, 1.5), 2880);
+        assert_eq!(tempo_map.bpm_at(100_000), 120.0);
+    }
+
+    #[test]
+    fn instant_change_starts_at_event() {
+        let (tempo_map, _) = TempoMap::new(PPQ, 120.0)
+            .with_added_event(1920, 60.0, TempoCurve::Instant)
+            .unwrap();
+        assert_eq!(tempo_map.bpm_at(1919), 120.0);
+        assert_eq!(tempo_map.bpm_at(1920), 60.0);
+        // Two beats at 120 bpm, then one at 60 bpm
+        assert!((tempo_map.ppq_to_seconds(2880.0) - 2.0).abs() < 1e-9);
+        assert!((tempo_map.seconds_to_ppq(2.0) - 2880.0).abs() < 1e-6);
+        assert_eq!(tempo_map.slowest_bpm(), 60.0);
+    }
+
+    #[test]
+    fn linear_ramp_integrates_tempo() {
+        let (tempo_map, _) = TempoMap::new(PPQ, 120.0)
+            .with_added_event(4 * 960, 180.0, TempoCurve::Linear)
+            .unwrap();
+        assert_eq!(tempo_map.bpm_at(2 * 960), 150.0);
+        // 60 / slope * ln(end / start) with a slope of 15 bpm per beat
+        let ramp_seconds = 4.0 * 1.5f64.ln();
+        assert!((tempo_map.ppq_to_seconds(3840.0) - ramp_seconds).abs() < 1e-9);
+        for position_ppq in [0.0, 500.0, 1920.0, 3839.0, 5000.0] {
+            let seconds = tempo_map.ppq_to_seconds(position_ppq);
+            assert!((tempo_map.seconds_to_ppq(seconds) - position_ppq).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_events() {
+        let tempo_map = TempoMap::new(PPQ, 120.0);
+        assert!(tempo_map
+            .with_added_event(0, 100.0, TempoCurve::Instant)
+            .is_err());
+        assert!(tempo_map
+            .with_added_event(960, TEMPO_BPM_MAX + 1.0, TempoCurve::Instant)
+            .is_err());
+        let first_id = tempo_map.events()[0].id.clone();
+        assert!(tempo_map.with_removed_event(&first_id).is_err());
+    }
+}
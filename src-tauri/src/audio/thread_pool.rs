@@ -120,6 +120,20 @@ impl WorkerPool {
             .store(ptr::null_mut(), Ordering::Release);
     }
 
+    /// Runs every task of `graph` once, each as soon as the tasks it depends on are finished.
+    pub fn run_graph<F>(&self, graph: &TaskGraph, task: &F)
+    where
+        F: Fn(usize, usize) + Sync,
+    {
+        if graph.is_empty() {
+            return;
+        }
+        graph.reset();
+        self.run_parallel(self.worker_count(), &|worker_id, _| {
+            graph.run_ready(worker_id, task)
+        });
+    }
+
     pub fn wait(&self) {
         let generation = self.shared.render_generation.load(Ordering::Acquire);
         self.wait_for_generation(generation);
@@ -203,6 +217,121 @@ fn run_worker_batch(worker_id: usize, generation: u64, shared: &SharedState) {
     }
 }
 
+/// Tasks and the dependencies between them, which must not form a cycle.
+/// The scheduling state is allocated up front, so running the graph doesn't allocate.
+pub struct TaskGraph {
+    dependents: Vec<Vec<usize>>,
+    input_counts: Vec<usize>,
+    pending_inputs: Vec<AtomicUsize>,
+    // Ready tasks in the order they became ready, stored as index + 1 so 0 means not yet written
+    ready: Vec<AtomicUsize>,
+    ready_head: AtomicUsize,
+    ready_tail: AtomicUsize,
+}
+
+impl TaskGraph {
+    pub fn new(task_count: usize) -> Self {
+        Self {
+            dependents: vec![Vec::new(); task_count],
+            input_counts: vec![0; task_count],
+            pending_inputs: (0..task_count).map(|_| AtomicUsize::new(0)).collect(),
+            ready: (0..task_count).map(|_| AtomicUsize::new(0)).collect(),
+            ready_head: AtomicUsize::new(0),
+            ready_tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dependents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dependents.is_empty()
+    }
+
+    /// `dependent` only runs once `task` is finished.
+    pub fn add_dependency(&mut self, task: usize, dependent: usize) {
+        self.dependents[task].push(dependent);
+        self.input_counts[dependent] += 1;
+    }
+
+    fn reset(&self) {
+        self.ready_head.store(0, Ordering::Release);
+        self.ready_tail.store(0, Ordering::Release);
+        for slot in self.ready.iter() {
+            slot.store(0, Ordering::Release);
+        }
+        for (task_index, input_count) in self.input_counts.iter().enumerate() {
+            self.pending_inputs[task_index].store(*input_count, Ordering::Release);
+            if *input_count == 0 {
+                self.push_ready(task_index);
+            }
+        }
+    }
+
+    fn push_ready(&self, task_index: usize) {
+        let slot = self.ready_tail.fetch_add(1, Ordering::AcqRel);
+        self.ready[slot].store(task_index + 1, Ordering::Release);
+    }
+
+    /// Runs ready tasks until every task has been claimed. Called by every worker at once.
+    /// A panicking task still releases its dependents, so the other workers never wait forever.
+    fn run_ready<F>(&self, worker_id: usize, task: &F)
+    where
+        F: Fn(usize, usize),
+    {
+        loop {
+            let head = self.ready_head.load(Ordering::Acquire);
+            if head >= self.len() {
+                return;
+            }
+            if head >= self.ready_tail.load(Ordering::Acquire) {
+                std::hint::spin_loop();
+                continue;
+            }
+            if self
+                .ready_head
+                .compare_exchange_weak(head, head + 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                continue;
+            }
+            // The slot is reserved before it is written
+            let task_index = loop {
+                let value = self.ready[head].load(Ordering::Acquire);
+                if value != 0 {
+                    break value - 1;
+                }
+                std::hint::spin_loop();
+            };
+
+            if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                task(worker_id, task_index)
+            })) {
+                log_worker_panic(worker_id, payload);
+            }
+
+            for dependent in self.dependents[task_index].iter() {
+                if self.pending_inputs[*dependent].fetch_sub(1, Ordering::AcqRel) == 1 {
+                    self.push_ready(*dependent);
+                }
+            }
+        }
+    }
+
+    /// Runs every task on the calling thread, in dependency order.
+    pub fn run_sequential<F>(&self, task: &F)
+    where
+        F: Fn(usize, usize),
+    {
+        if self.is_empty() {
+            return;
+        }
+        self.reset();
+        self.run_ready(0, task);
+    }
+}
+
 fn claim_next_index(shared: &SharedState, task_count: usize) -> Option<usize> {
     let task_index = shared.next_index.fetch_add(1, Ordering::Relaxed);
     (task_index < task_count).then_some(task_index)
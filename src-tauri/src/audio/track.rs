@@ -3,7 +3,7 @@ use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    audio::clip::Clip,
+    audio::{clip::Clip, effects::audio_effect::InsertEffect},
     core::{constants::MASTER_TRACK_DEFAULT_NAME, types::Id},
 };
 
@@ -11,6 +11,38 @@ pub fn default_track_name(track_number: usize) -> String {
     format!("Track #{track_number}")
 }
 
+pub fn default_bus_name(bus_number: usize) -> String {
+    format!("Bus #{bus_number}")
+}
+
+/// Where a send picks up the track's signal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SendTap {
+    /// Before volume and pan, so the send level doesn't follow the fader.
+    PreFader,
+    #[default]
+    PostFader,
+}
+
+/// Aux send from a track to a bus, e.g. a shared reverb return.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendAmount {
+    pub bus_id: Id,
+    pub amount: f32,
+    pub tap: SendTap,
+}
+
+/// Solo button state of a track or bus, returned for every one of them since exclusive solo
+/// changes the others too.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackSolo {
+    pub id: Id,
+    pub soloed: bool,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, strum_macros::Display)]
 enum TrackKind {
     Audio,
@@ -20,6 +52,8 @@ enum TrackKind {
     Master,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
 pub enum GeneratorTrack {
     AudioTrack(AudioTrack),
     SamplerTrack(SamplerTrack),
@@ -34,6 +68,104 @@ impl GeneratorTrack {
         }
     }
 
+    pub fn volume(&self) -> f32 {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.volume,
+            GeneratorTrack::SamplerTrack(t) => t.volume,
+        }
+    }
+
+    pub fn pan(&self) -> f32 {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.pan,
+            GeneratorTrack::SamplerTrack(t) => t.pan,
+        }
+    }
+
+    pub fn muted(&self) -> bool {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.muted,
+            GeneratorTrack::SamplerTrack(t) => t.muted,
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.volume = volume,
+            GeneratorTrack::SamplerTrack(t) => t.volume = volume,
+        }
+    }
+
+    pub fn set_pan(&mut self, pan: f32) {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.pan = pan,
+            GeneratorTrack::SamplerTrack(t) => t.pan = pan,
+        }
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.muted = muted,
+            GeneratorTrack::SamplerTrack(t) => t.muted = muted,
+        }
+    }
+
+    pub fn sends(&self) -> &[SendAmount] {
+        match self {
+            GeneratorTrack::AudioTrack(t) => &t.sends,
+            GeneratorTrack::SamplerTrack(t) => &t.sends,
+        }
+    }
+
+    pub fn sends_mut(&mut self) -> &mut Vec<SendAmount> {
+        match self {
+            GeneratorTrack::AudioTrack(t) => &mut t.sends,
+            GeneratorTrack::SamplerTrack(t) => &mut t.sends,
+        }
+    }
+
+    pub fn inserts(&self) -> &[InsertEffect] {
+        match self {
+            GeneratorTrack::AudioTrack(t) => &t.inserts,
+            GeneratorTrack::SamplerTrack(t) => &t.inserts,
+        }
+    }
+
+    pub fn inserts_mut(&mut self) -> &mut Vec<InsertEffect> {
+        match self {
+            GeneratorTrack::AudioTrack(t) => &mut t.inserts,
+            GeneratorTrack::SamplerTrack(t) => &mut t.inserts,
+        }
+    }
+
+    pub fn soloed(&self) -> bool {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.soloed,
+            GeneratorTrack::SamplerTrack(t) => t.soloed,
+        }
+    }
+
+    pub fn set_soloed(&mut self, soloed: bool) {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.soloed = soloed,
+            GeneratorTrack::SamplerTrack(t) => t.soloed = soloed,
+        }
+    }
+
+    pub fn output_id(&self) -> Option<&Id> {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.output_id.as_ref(),
+            GeneratorTrack::SamplerTrack(t) => t.output_id.as_ref(),
+        }
+    }
+
+    pub fn set_output_id(&mut self, output_id: Option<Id>) {
+        match self {
+            GeneratorTrack::AudioTrack(t) => t.output_id = output_id,
+            GeneratorTrack::SamplerTrack(t) => t.output_id = output_id,
+        }
+    }
+
     pub fn as_sampler_mut(&mut self) -> Option<&mut SamplerTrack> {
         match self {
             GeneratorTrack::SamplerTrack(t) => Some(t),
@@ -41,12 +173,26 @@ impl GeneratorTrack {
         }
     }
 
+    pub fn as_audio(&self) -> Option<&AudioTrack> {
+        match self {
+            GeneratorTrack::AudioTrack(t) => Some(t),
+            _ => None,
+        }
+    }
+
     pub fn as_audio_mut(&mut self) -> Option<&mut AudioTrack> {
         match self {
             GeneratorTrack::AudioTrack(t) => Some(t),
             _ => None,
         }
     }
+
+    pub fn clips_mut(&mut self) -> &mut IndexMap<Id, Clip> {
+        match self {
+            GeneratorTrack::AudioTrack(t) => &mut t.clips,
+            GeneratorTrack::SamplerTrack(t) => &mut t.clips,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,6 +203,15 @@ pub struct AudioTrack {
     pub volume: f32,
     pub pan: f32,
     pub muted: bool,
+    pub soloed: bool,
+    /// Bus the track outputs to, `None` for the master.
+    pub output_id: Option<Id>,
+    /// At most one per bus.
+    pub sends: Vec<SendAmount>,
+    pub record_armed: bool,
+    pub monitoring: bool,
+    /// Insert effects, in processing order.
+    pub inserts: Vec<InsertEffect>,
     pub clips: IndexMap<Id, Clip>,
     kind: TrackKind,
 }
@@ -69,6 +224,12 @@ impl AudioTrack {
             volume: 1.0,
             pan: 0.0,
             muted: false,
+            soloed: false,
+            output_id: None,
+            sends: Vec::new(),
+            record_armed: false,
+            monitoring: false,
+            inserts: Vec::new(),
             clips: IndexMap::new(),
             kind: TrackKind::Audio,
         }
@@ -83,7 +244,14 @@ pub struct SamplerTrack {
     pub volume: f32,
     pub pan: f32,
     pub muted: bool,
+    pub soloed: bool,
+    /// Bus the track outputs to, `None` for the master.
+    pub output_id: Option<Id>,
+    /// At most one per bus.
+    pub sends: Vec<SendAmount>,
     pub source_id: Option<Id>,
+    /// Insert effects, in processing order.
+    pub inserts: Vec<InsertEffect>,
     pub clips: IndexMap<Id, Clip>,
     kind: TrackKind,
 }
@@ -96,7 +264,11 @@ impl SamplerTrack {
             volume: 1.0,
             pan: 0.0,
             muted: false,
+            soloed: false,
+            output_id: None,
+            sends: Vec::new(),
             source_id,
+            inserts: Vec::new(),
             clips: IndexMap::new(),
             kind: TrackKind::Sampler,
         }
@@ -111,10 +283,35 @@ pub struct BusTrack {
     pub volume: f32,
     pub pan: f32,
     pub muted: bool,
+    pub soloed: bool,
+    /// Never muted by soloing other tracks, e.g. for effect returns.
+    pub solo_safe: bool,
+    /// Bus the bus outputs to, `None` for the master.
+    pub output_id: Option<Id>,
+    /// Insert effects, in processing order.
+    pub inserts: Vec<InsertEffect>,
     pub clips: IndexMap<Id, Clip>,
     kind: TrackKind,
 }
 
+impl BusTrack {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            id: nanoid!(),
+            name: name.into(),
+            volume: 1.0,
+            pan: 0.0,
+            muted: false,
+            soloed: false,
+            solo_safe: false,
+            output_id: None,
+            inserts: Vec::new(),
+            clips: IndexMap::new(),
+            kind: TrackKind::Bus,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MasterTrack {
@@ -122,6 +319,8 @@ pub struct MasterTrack {
     pub volume: f32,
     pub pan: f32,
     pub muted: bool,
+    /// Insert effects, in processing order.
+    pub inserts: Vec<InsertEffect>,
     pub clips: IndexMap<Id, Clip>,
     kind: TrackKind,
 }
@@ -133,6 +332,7 @@ impl MasterTrack {
             volume: 1.0,
             pan: 0.0,
             muted: false,
+            inserts: Vec::new(),
             clips: IndexMap::new(),
             kind: TrackKind::Master,
         }
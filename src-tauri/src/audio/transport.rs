@@ -5,24 +5,63 @@ use std::sync::{
     },
     LazyLock, Mutex,
 };
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{bail, Result};
 use log::info;
 use ringbuf::traits::{Observer, Producer};
+use serde::Serialize;
+use tauri::Emitter;
 
 use crate::{
+    app_handle,
     audio::{
-        engine::AUDIO_ENGINE, preview_mixer::PREVIEW_MIXER,
+        engine::AUDIO_ENGINE, engine_stats::ENGINE_STATS, metronome::METRONOME,
+        preview_mixer::PREVIEW_MIXER, project_state::PROJECT_STATE, recorder::RECORDER,
+        render_thread::RENDER_THREAD, renderer::Renderer,
         snapshot::project_snapshot::load_project_snapshot, thread_pool::AUDIO_WORKER_POOL,
     },
-    core::constants::BUFFER_SIZE_DEFAULT,
+    core::{
+        constants::PLAYHEAD_EVENT,
+        settings::{TransportSettings, SETTINGS},
+    },
 };
 
+/// About 30 updates per second.
+const PLAYHEAD_REPORT_INTERVAL: Duration = Duration::from_millis(33);
+/// `loop_wrap_rendered_samples` while the current play hasn't looped.
+const NO_LOOP_WRAP: usize = usize::MAX;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayheadPosition {
+    pub ppq: usize,
+    pub seconds: f64,
+    pub bars: usize,
+    pub beats: usize,
+    pub ticks: usize,
+    pub bpm: f32,
+}
+
 pub struct Transport {
     pub is_playing: AtomicBool,
     pub position_ppq: AtomicUsize,
     pub loop_range_ppq: (AtomicUsize, AtomicUsize),
+    pub is_looping: AtomicBool,
     pub position_samples: AtomicUsize,
+    // Where the last `play` started, the stop position when returning to start
+    play_start_ppq: AtomicUsize,
+    // Set once the current play pushed its first block, the engine ring starts out empty
+    has_rendered_block: AtomicBool,
+    // Where the current play started or was last moved to, nothing before it is heard
+    play_from_samples: AtomicUsize,
+    // Interleaved samples pushed since `play_from_samples`
+    rendered_samples: AtomicUsize,
+    // Value of `rendered_samples` where the render position last jumped to the loop start
+    loop_wrap_rendered_samples: AtomicUsize,
+    return_to_start_on_stop: AtomicBool,
+    playhead_reporter: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Transport {
@@ -31,7 +70,15 @@ impl Transport {
             is_playing: AtomicBool::new(false),
             position_ppq: AtomicUsize::new(0),
             loop_range_ppq: (AtomicUsize::new(0), AtomicUsize::new(0)),
+            is_looping: AtomicBool::new(false),
             position_samples: AtomicUsize::new(0),
+            play_start_ppq: AtomicUsize::new(0),
+            has_rendered_block: AtomicBool::new(false),
+            play_from_samples: AtomicUsize::new(0),
+            rendered_samples: AtomicUsize::new(0),
+            loop_wrap_rendered_samples: AtomicUsize::new(NO_LOOP_WRAP),
+            return_to_start_on_stop: AtomicBool::new(false),
+            playhead_reporter: Mutex::new(None),
         }
     }
 
@@ -39,75 +86,380 @@ impl Transport {
         self.position_ppq.load(Ordering::SeqCst)
     }
 
+    /// Whether the device should be getting rendered audio: playing, and past the first block,
+    /// so running out of samples counts as an underrun.
+    pub fn is_streaming(&self) -> bool {
+        self.is_playing.load(Ordering::Relaxed) && self.has_rendered_block.load(Ordering::Relaxed)
+    }
+
     pub fn stop(&self) {
         PREVIEW_MIXER.is_canceled.store(true, Ordering::SeqCst);
         self.is_playing
             .store(false, std::sync::atomic::Ordering::SeqCst);
-        self.position_ppq.store(0, Ordering::SeqCst);
-        self.position_samples.store(0, Ordering::SeqCst);
-        AUDIO_WORKER_POOL.stop();
+        METRONOME.cancel_count_in();
+        RECORDER.stop_take();
+        if self.return_to_start_on_stop.load(Ordering::SeqCst) {
+            let play_start_ppq = self.play_start_ppq.load(Ordering::SeqCst);
+            self.position_samples.store(
+                PROJECT_STATE.ppq_to_samples(play_start_ppq),
+                Ordering::SeqCst,
+            );
+            self.position_ppq.store(play_start_ppq, Ordering::SeqCst);
+        } else {
+            self.position_ppq.store(0, Ordering::SeqCst);
+            self.position_samples.store(0, Ordering::SeqCst);
+        }
+        RENDER_THREAD.wake();
+        self.emit_playhead();
+    }
+
+    /// Stops playback and keeps the playhead where it is heard, behind the render position by
+    /// what is still queued for the device. A running take is finished.
+    pub fn pause(&self) {
+        let heard_position_samples = self.heard_position_samples();
+        self.is_playing.store(false, Ordering::SeqCst);
+        let heard_position_ppq = PROJECT_STATE.samples_to_ppq(heard_position_samples);
+        self.position_samples
+            .store(heard_position_samples, Ordering::SeqCst);
+        self.position_ppq
+            .store(heard_position_ppq, Ordering::SeqCst);
+        info!("Transport pause, ppq {heard_position_ppq}");
+        METRONOME.cancel_count_in();
+        RECORDER.stop_take();
+        RENDER_THREAD.wake();
+        self.emit_playhead();
+    }
+
+    pub fn return_to_start_on_stop(&self) -> bool {
+        self.return_to_start_on_stop.load(Ordering::SeqCst)
+    }
+
+    /// Applies the persisted transport preferences, called once at startup.
+    pub fn apply_settings(&self, settings: &TransportSettings) {
+        self.return_to_start_on_stop
+            .store(settings.return_to_start_on_stop, Ordering::SeqCst);
+    }
+
+    pub fn set_return_to_start_on_stop(&self, return_to_start_on_stop: bool) -> Result<()> {
+        self.return_to_start_on_stop
+            .store(return_to_start_on_stop, Ordering::SeqCst);
+        SETTINGS
+            .update(|current| current.transport.return_to_start_on_stop = return_to_start_on_stop)
+    }
+
+    /// Moves the playhead, also while playing. Audio already queued for the device still plays out.
+    pub fn seek(&self, position_ppq: usize) {
+        info!("Transport seek to ppq {position_ppq}");
+        let position_samples = PROJECT_STATE.ppq_to_samples(position_ppq);
+        self.position_samples
+            .store(position_samples, Ordering::SeqCst);
+        self.position_ppq.store(position_ppq, Ordering::SeqCst);
+        self.reset_heard_position(position_samples);
+        self.emit_playhead();
+    }
+
+    /// Starts tracking the heard position over from `position_samples`, nothing is queued yet.
+    fn reset_heard_position(&self, position_samples: usize) {
+        self.play_from_samples
+            .store(position_samples, Ordering::SeqCst);
+        self.rendered_samples.store(0, Ordering::SeqCst);
+        self.loop_wrap_rendered_samples
+            .store(NO_LOOP_WRAP, Ordering::SeqCst);
+    }
+
+    /// Position the listener hears right now, see `heard_position`.
+    fn heard_position_samples(&self) -> usize {
+        let position_samples = self.position_samples.load(Ordering::SeqCst);
+        if !self.is_playing.load(Ordering::SeqCst) || METRONOME.is_counting_in() {
+            return position_samples;
+        }
+        let loop_wrap_rendered_samples = self.loop_wrap_rendered_samples.load(Ordering::SeqCst);
+        heard_position(
+            position_samples,
+            AUDIO_ENGINE.output_latency_frames() * AUDIO_ENGINE.num_channels(),
+            self.play_from_samples.load(Ordering::SeqCst),
+            self.rendered_samples.load(Ordering::SeqCst),
+            (loop_wrap_rendered_samples != NO_LOOP_WRAP).then_some(loop_wrap_rendered_samples),
+            self.loop_range_samples(),
+        )
+    }
+
+    pub fn playhead_position(&self) -> PlayheadPosition {
+        let position_samples = self.heard_position_samples();
+        let ppq = PROJECT_STATE.samples_to_ppq(position_samples);
+        let position = PROJECT_STATE.ppq_to_bar_beat_tick(ppq);
+        PlayheadPosition {
+            ppq,
+            seconds: (position_samples / AUDIO_ENGINE.num_channels()) as f64
+                / AUDIO_ENGINE.sample_rate() as f64,
+            bars: position.bar,
+            beats: position.beat,
+            ticks: position.tick,
+            bpm: PROJECT_STATE.tempo_bpm_at(ppq),
+        }
+    }
+
+    fn emit_playhead(&self) {
+        let _ = app_handle().emit(PLAYHEAD_EVENT, self.playhead_position());
+    }
+
+    /// Spawns the thread that sends the playhead to the frontend while playing.
+    /// It lives as long as the app.
+    pub fn start_playhead_reporter(&self) {
+        let mut playhead_reporter = self.playhead_reporter.lock().unwrap();
+        if playhead_reporter.is_some() {
+            return;
+        }
+        *playhead_reporter = Some(thread::spawn(|| loop {
+            thread::sleep(PLAYHEAD_REPORT_INTERVAL);
+            if TRANSPORT.is_playing.load(Ordering::SeqCst) {
+                TRANSPORT.emit_playhead();
+            }
+        }));
+    }
+
+    pub fn set_loop_range(&self, start_ppq: usize, end_ppq: usize) -> Result<()> {
+        if end_ppq <= start_ppq {
+            bail!("Loop end must be after loop start");
+        }
+        self.loop_range_ppq.0.store(start_ppq, Ordering::SeqCst);
+        self.loop_range_ppq.1.store(end_ppq, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn set_looping(&self, is_looping: bool) {
+        self.is_looping.store(is_looping, Ordering::SeqCst);
+    }
+
+    /// Loop range as interleaved sample positions, `None` when looping is off.
+    /// Converted on every block so tempo changes apply right away; lock-free.
+    fn loop_range_samples(&self) -> Option<(usize, usize)> {
+        if !self.is_looping.load(Ordering::SeqCst) {
+            return None;
+        }
+        let start_ppq = self.loop_range_ppq.0.load(Ordering::SeqCst);
+        let end_ppq = self.loop_range_ppq.1.load(Ordering::SeqCst);
+        Some((
+            PROJECT_STATE.ppq_to_samples(start_ppq),
+            PROJECT_STATE.ppq_to_samples(end_ppq),
+        ))
+    }
+
+    /// Keeps the playhead on the same beat after the tempo map changed.
+    pub fn follow_tempo_change(&self) {
+        self.position_samples.store(
+            PROJECT_STATE.ppq_to_samples(self.position_ppq()),
+            Ordering::SeqCst,
+        );
+    }
+
+    /// Keeps the playhead at the same time when the engine sample rate changes.
+    pub fn rescale_position(&self, from_sample_rate: usize, to_sample_rate: usize) {
+        let channels = AUDIO_ENGINE.num_channels();
+        let frames = (self.position_samples.load(Ordering::SeqCst) / channels) as f64;
+        let ratio = to_sample_rate as f64 / from_sample_rate as f64;
+        self.position_samples.store(
+            (frames * ratio).round() as usize * channels,
+            Ordering::SeqCst,
+        );
+    }
+
+    /// Starts a take at the current position. Playback has to be started by the caller.
+    /// Input during the count-in `play` adds is recorded but left out of the clip.
+    pub fn record(&self) -> Result<()> {
+        if self.is_playing.load(Ordering::SeqCst) {
+            bail!("Stop playback before recording");
+        }
+        let position_ppq = self.position_ppq();
+        let pre_roll_frames =
+            METRONOME.count_in_samples(position_ppq) / AUDIO_ENGINE.num_channels();
+        RECORDER.start_take(position_ppq, pre_roll_frames)
     }
 
-    pub fn play(&self) -> Result<()> {
+    /// Starts playback from the current position, after the metronome count-in if there is one,
+    /// and remembers the position as the play start.
+    pub fn play(&self) {
+        let position_ppq = self.position_ppq();
+        self.play_start_ppq.store(position_ppq, Ordering::SeqCst);
+        METRONOME.arm_count_in(position_ppq);
+        self.resume();
+    }
+
+    /// Continues playback from the current position, e.g. after `pause`, keeping the play start.
+    /// Rendering happens on the render thread.
+    pub fn resume(&self) {
         info!(
             "Transport play, ppq {}, samples {}",
             self.position_ppq(),
             self.position_samples.load(SeqCst)
         );
-        TRANSPORT
-            .is_playing
-            .store(true, std::sync::atomic::Ordering::SeqCst);
-        let mut engine_producer_guard = AUDIO_ENGINE
-            .engine_producer
-            .lock()
-            .map_err(|_| anyhow!("Could not lock preview producer"))?;
-        let engine_producer = engine_producer_guard
-            .as_mut()
-            .context("Preview producer missing or stream already started")?;
-        let buffer_size = BUFFER_SIZE_DEFAULT as usize * AUDIO_ENGINE.num_channels();
-        let snapshot = load_project_snapshot();
-        let mut current_snapshot_version = snapshot.version.clone();
-        let mut tracks = &snapshot.get_scheduler().tracks;
-        // TODO find a way to not put these buffers inside a mutex
-        let mut track_buffers = tracks
-            .iter()
-            .map(|_| Mutex::new(vec![0.0f32; buffer_size]))
-            .collect::<Vec<_>>();
-        let mut main_buffer = vec![0.0f32; buffer_size];
-        AUDIO_WORKER_POOL.start();
-        while TRANSPORT.is_playing.load(Ordering::SeqCst) {
-            main_buffer.fill(0.0);
+        self.has_rendered_block.store(false, Ordering::SeqCst);
+        self.reset_heard_position(self.position_samples.load(Ordering::SeqCst));
+        self.is_playing.store(true, Ordering::SeqCst);
+        RENDER_THREAD.ensure_started();
+        RENDER_THREAD.wake();
+    }
+
+    /// Renders blocks until the engine ring buffer is full, keeping the position following the
+    /// last pushed block in `last_position_samples`. Called on the render thread only.
+    pub fn render_pending(&self, renderer: &mut Renderer, last_position_samples: &mut usize) {
+        let Ok(mut engine_producer) = AUDIO_ENGINE.engine_producer.try_lock() else {
+            return;
+        };
+        let Some(engine_producer) = engine_producer.as_mut() else {
+            return;
+        };
+
+        let block_size = renderer.buffer_size();
+        let block_duration = Duration::from_secs_f64(
+            AUDIO_ENGINE.buffer_size() as f64 / AUDIO_ENGINE.sample_rate() as f64,
+        );
+        while self.is_playing.load(Ordering::SeqCst) && engine_producer.vacant_len() >= block_size {
+            let render_started = Instant::now();
             let snapshot = load_project_snapshot();
-            tracks = &snapshot.get_scheduler().tracks;
-            if snapshot.version != current_snapshot_version {
-                current_snapshot_version = snapshot.version.clone();
-                track_buffers.truncate(tracks.len());
-                while track_buffers.len() < tracks.len() {
-                    track_buffers.push(Mutex::new(vec![0.0f32; buffer_size]));
-                }
-            }
-            if engine_producer.vacant_len() < buffer_size {
+            let position_samples = self.position_samples.load(Ordering::SeqCst);
+            let position_ppq = self.position_ppq();
+            let (main_buffer, next_position_samples) = renderer.render(
+                &snapshot,
+                position_samples,
+                self.loop_range_samples(),
+                Some(&AUDIO_WORKER_POOL),
+            );
+            // A seek, stop or tempo change moved the playhead while rendering, the block is
+            // dropped and the next one starts from there
+            if self
+                .position_samples
+                .compare_exchange(
+                    position_samples,
+                    next_position_samples,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_err()
+            {
                 continue;
             }
-            let position_samples = self.position_samples.load(Ordering::SeqCst);
-            AUDIO_WORKER_POOL.run_parallel(tracks.len(), &|_, track_index| {
-                let mut track_buffer = track_buffers[track_index].lock().unwrap();
-                tracks[track_index].render(position_samples, &mut track_buffer, snapshot.clone());
-            });
-
-            for track_buffer in track_buffers.iter() {
-                let track_buffer = track_buffer.lock().unwrap();
-                for (main_sample, track_sample) in main_buffer.iter_mut().zip(track_buffer.iter()) {
-                    *main_sample += *track_sample;
-                }
+            engine_producer.push_slice(main_buffer);
+            *last_position_samples = next_position_samples;
+            let rendered_samples = self
+                .rendered_samples
+                .fetch_add(block_size, Ordering::SeqCst);
+            if let Some(loop_wrap_offset) = renderer.loop_wrap_offset() {
+                self.loop_wrap_rendered_samples
+                    .store(rendered_samples + loop_wrap_offset, Ordering::SeqCst);
             }
-            engine_producer.push_slice(&main_buffer);
-            self.position_samples
-                .fetch_add(buffer_size, Ordering::SeqCst);
+            self.has_rendered_block.store(true, Ordering::SeqCst);
+            ENGINE_STATS.record_render_time(render_started.elapsed(), block_duration);
+            let _ = self.position_ppq.compare_exchange(
+                position_ppq,
+                PROJECT_STATE.samples_to_ppq(next_position_samples),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+        }
+    }
+
+    /// Renders blocks of effect tails after stopping until `tail_samples_left` runs out or the
+    /// engine ring buffer is full. Tempo-synced effects follow the tempo at `position_samples`,
+    /// where playback left off. Called on the render thread only.
+    pub fn render_tail_pending(
+        &self,
+        renderer: &mut Renderer,
+        position_samples: usize,
+        tail_samples_left: &mut usize,
+    ) {
+        let Ok(mut engine_producer) = AUDIO_ENGINE.engine_producer.try_lock() else {
+            return;
+        };
+        let Some(engine_producer) = engine_producer.as_mut() else {
+            *tail_samples_left = 0;
+            return;
+        };
+
+        let block_size = renderer.buffer_size();
+        while !self.is_playing.load(Ordering::SeqCst)
+            && *tail_samples_left > 0
+            && engine_producer.vacant_len() >= block_size
+        {
+            let snapshot = load_project_snapshot();
+            let block = renderer.render_tail(&snapshot, position_samples, Some(&AUDIO_WORKER_POOL));
+            engine_producer.push_slice(block);
+            *tail_samples_left = tail_samples_left.saturating_sub(block_size);
         }
-        AUDIO_WORKER_POOL.stop();
-        Ok(())
+    }
+}
+
+/// Position the listener hears, `latency_samples` behind the render position.
+/// Nothing rendered has been heard yet as long as the render position is less than the latency
+/// past `play_from_samples`, so the playhead stays there. `loop_wrap_rendered_samples` counts the
+/// samples pushed before the last jump to the loop start; while that jump is still queued the
+/// listener hears the part before the loop end.
+fn heard_position(
+    position_samples: usize,
+    latency_samples: usize,
+    play_from_samples: usize,
+    rendered_samples: usize,
+    loop_wrap_rendered_samples: Option<usize>,
+    loop_range_samples: Option<(usize, usize)>,
+) -> usize {
+    let heard_position_samples = position_samples.saturating_sub(latency_samples);
+    let Some(loop_wrap_rendered_samples) = loop_wrap_rendered_samples else {
+        return heard_position_samples.max(play_from_samples);
+    };
+    match (
+        rendered_samples.checked_sub(loop_wrap_rendered_samples),
+        loop_range_samples,
+    ) {
+        (Some(queued_after_wrap), Some((loop_start, loop_end)))
+            if queued_after_wrap < latency_samples =>
+        {
+            loop_end
+                .saturating_sub(latency_samples - queued_after_wrap)
+                .max(loop_start)
+        }
+        _ => heard_position_samples,
     }
 }
 
 pub static TRANSPORT: LazyLock<Transport> = LazyLock::new(|| Transport::new());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LATENCY: usize = 4000;
+    const LOOP_RANGE: (usize, usize) = (10_000, 20_000);
+
+    #[test]
+    fn starting_at_loop_start_stays_there() {
+        let position = heard_position(11_000, LATENCY, 10_000, 1000, None, Some(LOOP_RANGE));
+        assert_eq!(position, 10_000);
+        let position = heard_position(15_000, LATENCY, 10_000, 5000, None, Some(LOOP_RANGE));
+        assert_eq!(position, 11_000);
+    }
+
+    #[test]
+    fn entering_loop_from_before_is_not_wrapped() {
+        let position = heard_position(12_000, LATENCY, 9000, 3000, None, Some(LOOP_RANGE));
+        assert_eq!(position, 9000);
+        let position = heard_position(11_000, LATENCY, 5000, 6000, None, Some(LOOP_RANGE));
+        assert_eq!(position, 7000);
+    }
+
+    #[test]
+    fn queued_loop_wrap_is_heard_before_loop_end() {
+        // Wrapped 1000 samples ago, the last 3000 samples before the loop end are still queued
+        let position = heard_position(11_000, LATENCY, 12_000, 9000, Some(8000), Some(LOOP_RANGE));
+        assert_eq!(position, 17_000);
+        // Once the wrap was heard the position follows the render position again
+        let position = heard_position(
+            15_000,
+            LATENCY,
+            12_000,
+            13_000,
+            Some(8000),
+            Some(LOOP_RANGE),
+        );
+        assert_eq!(position, 11_000);
+    }
+}
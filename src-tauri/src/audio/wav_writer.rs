@@ -0,0 +1,81 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::{Deserialize, Serialize};
+
+use crate::core::types::EngineSampleFormat;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WavBitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavBitDepth {
+    fn spec(&self, num_channels: usize, sample_rate: usize) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            WavBitDepth::Int16 => (16, SampleFormat::Int),
+            WavBitDepth::Int24 => (24, SampleFormat::Int),
+            WavBitDepth::Float32 => (32, SampleFormat::Float),
+        };
+        WavSpec {
+            channels: num_channels as u16,
+            sample_rate: sample_rate as u32,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+/// Writes interleaved engine samples into a WAV file, converting to the requested bit depth.
+pub struct WavFileWriter {
+    writer: WavWriter<BufWriter<File>>,
+    bit_depth: WavBitDepth,
+}
+
+impl WavFileWriter {
+    pub fn create(
+        file_path: impl AsRef<Path>,
+        bit_depth: WavBitDepth,
+        num_channels: usize,
+        sample_rate: usize,
+    ) -> Result<Self> {
+        let writer = WavWriter::create(file_path, bit_depth.spec(num_channels, sample_rate))
+            .context("Couldn't create WAV file")?;
+        Ok(Self { writer, bit_depth })
+    }
+
+    pub fn write_samples(&mut self, samples: &[EngineSampleFormat]) -> Result<()> {
+        match self.bit_depth {
+            WavBitDepth::Int16 => {
+                for sample in samples {
+                    let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+                    self.writer.write_sample(value)?;
+                }
+            }
+            WavBitDepth::Int24 => {
+                const INT24_MAX: f32 = 8_388_607.0;
+                for sample in samples {
+                    let value = (sample.clamp(-1.0, 1.0) * INT24_MAX).round() as i32;
+                    self.writer.write_sample(value)?;
+                }
+            }
+            WavBitDepth::Float32 => {
+                for sample in samples {
+                    self.writer.write_sample(*sample)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finalize(self) -> Result<()> {
+        self.writer
+            .finalize()
+            .context("Couldn't finalize WAV file")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    audio::{
+        effects::audio_effect::{EffectInfo, EffectKind, FrequencyResponse, InsertEffect},
+        engine::AUDIO_ENGINE,
+        project_state::PROJECT_STATE,
+        snapshot::project_snapshot::load_project_snapshot,
+    },
+    core::types::Id,
+};
+
+// `track_id` is a track or bus id, or null for the master
+
+#[tauri::command]
+pub fn effects_get_kinds() -> Vec<EffectInfo> {
+    EffectKind::ALL
+        .iter()
+        .copied()
+        .map(EffectInfo::from)
+        .collect()
+}
+
+#[tauri::command]
+pub fn effects_get_inserts(track_id: Option<Id>) -> Result<Vec<InsertEffect>, String> {
+    PROJECT_STATE
+        .inserts(track_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn effects_add_insert(
+    track_id: Option<Id>,
+    kind: EffectKind,
+    index: Option<usize>,
+) -> Result<InsertEffect, String> {
+    PROJECT_STATE
+        .add_insert(track_id.as_deref(), kind, index)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn effects_remove_insert(track_id: Option<Id>, effect_id: Id) -> Result<(), String> {
+    PROJECT_STATE
+        .remove_insert(track_id.as_deref(), &effect_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn effects_move_insert(
+    track_id: Option<Id>,
+    effect_id: Id,
+    index: usize,
+) -> Result<Vec<InsertEffect>, String> {
+    PROJECT_STATE
+        .move_insert(track_id.as_deref(), &effect_id, index)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn effects_set_parameter(
+    track_id: Option<Id>,
+    effect_id: Id,
+    parameter_id: String,
+    value: f32,
+) -> Result<InsertEffect, String> {
+    PROJECT_STATE
+        .set_insert_parameter(track_id.as_deref(), &effect_id, &parameter_id, value)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn effects_set_bypassed(
+    track_id: Option<Id>,
+    effect_id: Id,
+    bypassed: bool,
+) -> Result<InsertEffect, String> {
+    PROJECT_STATE
+        .set_insert_bypassed(track_id.as_deref(), &effect_id, bypassed)
+        .map_err(|e| e.to_string())
+}
+
+/// Sets the audio asset a convolution reverb convolves with, null to remove it.
+#[tauri::command]
+pub fn effects_set_impulse_response(
+    track_id: Option<Id>,
+    effect_id: Id,
+    asset_id: Option<Id>,
+) -> Result<InsertEffect, String> {
+    PROJECT_STATE
+        .set_insert_impulse_response(track_id.as_deref(), &effect_id, asset_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Curve of an effect for its current parameters, null for effects that don't filter.
+#[tauri::command]
+pub fn effects_get_frequency_response(
+    track_id: Option<Id>,
+    effect_id: Id,
+    num_points: usize,
+) -> Result<Option<FrequencyResponse>, String> {
+    let insert = PROJECT_STATE
+        .insert(track_id.as_deref(), &effect_id)
+        .map_err(|e| e.to_string())?;
+    Ok(FrequencyResponse::of(
+        &insert,
+        AUDIO_ENGINE.sample_rate(),
+        num_points,
+    ))
+}
+
+/// Delay added by the insert chain, in frames.
+#[tauri::command]
+pub fn effects_get_latency(track_id: Option<Id>) -> usize {
+    load_project_snapshot()
+        .get_data_nodes()
+        .inserts(track_id.as_deref())
+        .map_or(0, |inserts| inserts.latency_frames())
+}
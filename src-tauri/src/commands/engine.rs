@@ -0,0 +1,100 @@
+use crate::{
+    audio::{
+        devices::{self, AudioDeviceInfo, AudioHostInfo},
+        engine::{OutputDeviceInfo, AUDIO_ENGINE},
+        engine_stats::{EngineStatsInfo, ENGINE_STATS},
+        null_output::OutputCaptureTarget,
+    },
+    core::{
+        settings::{AudioSettings, SETTINGS},
+        types::EngineSampleFormat,
+    },
+};
+
+#[tauri::command]
+pub fn engine_list_hosts() -> Vec<AudioHostInfo> {
+    devices::list_hosts()
+}
+
+#[tauri::command]
+pub fn engine_list_output_devices(host_id: Option<String>) -> Result<Vec<AudioDeviceInfo>, String> {
+    devices::list_output_devices(host_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn engine_list_input_devices(host_id: Option<String>) -> Result<Vec<AudioDeviceInfo>, String> {
+    devices::list_input_devices(host_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn engine_set_input_device(device_id: Option<String>) -> Result<(), String> {
+    devices::set_input_device(device_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn engine_get_output_device() -> OutputDeviceInfo {
+    AUDIO_ENGINE.output_device_info()
+}
+
+async fn apply_audio_settings(
+    update: impl FnOnce(&mut AudioSettings) + Send + 'static,
+) -> Result<OutputDeviceInfo, String> {
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let mut settings = SETTINGS.get().audio;
+        update(&mut settings);
+        devices::apply_audio_settings(settings)
+    })
+    .await;
+
+    result
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn engine_set_output_device(
+    host_id: Option<String>,
+    device_id: Option<String>,
+) -> Result<OutputDeviceInfo, String> {
+    apply_audio_settings(move |settings| {
+        settings.host_id = host_id;
+        settings.output_device_id = device_id;
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn engine_set_sample_rate(
+    sample_rate: Option<usize>,
+) -> Result<OutputDeviceInfo, String> {
+    apply_audio_settings(move |settings| settings.sample_rate = sample_rate).await
+}
+
+#[tauri::command]
+pub async fn engine_set_buffer_size(
+    buffer_size: Option<usize>,
+) -> Result<OutputDeviceInfo, String> {
+    apply_audio_settings(move |settings| settings.buffer_size = buffer_size).await
+}
+
+#[tauri::command]
+pub fn engine_start_output_capture(target: OutputCaptureTarget) -> Result<(), String> {
+    AUDIO_ENGINE
+        .start_capture(target)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn engine_stop_output_capture() -> Result<Vec<EngineSampleFormat>, String> {
+    AUDIO_ENGINE.stop_capture().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn engine_get_stats() -> EngineStatsInfo {
+    ENGINE_STATS.info()
+}
+
+#[tauri::command]
+pub fn engine_reset_stats() {
+    ENGINE_STATS.reset();
+}
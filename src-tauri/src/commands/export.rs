@@ -0,0 +1,26 @@
+use tauri::Emitter;
+
+use crate::{
+    app_handle,
+    audio::bounce::{BounceOptions, BOUNCER},
+    core::constants::BOUNCE_PROGRESS_EVENT,
+};
+
+#[tauri::command]
+pub async fn export_bounce_to_wav(options: BounceOptions) -> Result<(), String> {
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        BOUNCER.bounce(options, |progress| {
+            let _ = app_handle().emit(BOUNCE_PROGRESS_EVENT, progress);
+        })
+    })
+    .await;
+
+    result
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn export_cancel_bounce() {
+    BOUNCER.cancel();
+}
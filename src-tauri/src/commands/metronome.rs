@@ -0,0 +1,36 @@
+use crate::audio::metronome::{ClickKind, MetronomeInfo, METRONOME};
+
+#[tauri::command]
+pub fn metronome_get_info() -> MetronomeInfo {
+    METRONOME.info()
+}
+
+#[tauri::command]
+pub fn metronome_set_enabled(is_enabled: bool) {
+    METRONOME.set_enabled(is_enabled);
+}
+
+#[tauri::command]
+pub fn metronome_set_volume(volume: f32) {
+    METRONOME.set_volume(volume);
+}
+
+#[tauri::command]
+pub fn metronome_set_count_in_bars(count_in_bars: usize) -> Result<(), String> {
+    METRONOME
+        .set_count_in_bars(count_in_bars)
+        .map_err(|e| e.to_string())
+}
+
+/// `source_path` of `None` goes back to the built-in click.
+#[tauri::command]
+pub async fn metronome_set_sound(
+    kind: ClickKind,
+    source_path: Option<String>,
+) -> Result<MetronomeInfo, String> {
+    METRONOME
+        .set_sound(kind, source_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(METRONOME.info())
+}
@@ -1,10 +1,15 @@
 use crate::{
     audio::{
         clip::{Clip, ClipToInsert},
+        gain::PanLaw,
         project_state::PROJECT_STATE,
-        track::{AudioTrack, SamplerTrack},
+        recorder::RECORDER,
+        track::{
+            AudioTrack, BusTrack, GeneratorTrack, MasterTrack, SamplerTrack, SendAmount, SendTap,
+            TrackSolo,
+        },
     },
-    core::types::Id,
+    core::{notify::log_and_notify_error, types::Id},
 };
 
 #[tauri::command]
@@ -51,3 +56,139 @@ pub fn mixer_move_clip_in_audio_track(track_id: Id, clip_id: Id, start_ppq: usiz
 pub fn mixer_delete_clip_from_audio_track(track_id: Id, clip_id: Id) {
     PROJECT_STATE.delete_clip_from_audio_track(&track_id, &clip_id)
 }
+
+fn sync_recorder_with_armed_tracks() {
+    if let Err(e) = RECORDER.sync_with_armed_tracks() {
+        log_and_notify_error(format!("Error trying to open input device: {e}"));
+    }
+}
+
+#[tauri::command]
+pub fn mixer_set_track_record_armed(track_id: Id, armed: bool) -> Option<AudioTrack> {
+    let track = PROJECT_STATE.set_track_record_armed(&track_id, armed);
+    sync_recorder_with_armed_tracks();
+    track
+}
+
+#[tauri::command]
+pub fn mixer_set_track_monitoring(track_id: Id, monitoring: bool) -> Option<AudioTrack> {
+    let track = PROJECT_STATE.set_track_monitoring(&track_id, monitoring);
+    sync_recorder_with_armed_tracks();
+    track
+}
+
+#[tauri::command]
+pub fn mixer_set_track_volume(track_id: Id, volume: f32) -> Option<GeneratorTrack> {
+    PROJECT_STATE.set_track_volume(&track_id, volume)
+}
+
+#[tauri::command]
+pub fn mixer_set_track_pan(track_id: Id, pan: f32) -> Option<GeneratorTrack> {
+    PROJECT_STATE.set_track_pan(&track_id, pan)
+}
+
+#[tauri::command]
+pub fn mixer_set_track_muted(track_id: Id, muted: bool) -> Option<GeneratorTrack> {
+    PROJECT_STATE.set_track_muted(&track_id, muted)
+}
+
+#[tauri::command]
+pub fn mixer_set_track_soloed(track_id: Id, soloed: bool) -> Option<Vec<TrackSolo>> {
+    PROJECT_STATE.set_track_soloed(&track_id, soloed)
+}
+
+#[tauri::command]
+pub fn mixer_set_bus_soloed(bus_id: Id, soloed: bool) -> Option<Vec<TrackSolo>> {
+    PROJECT_STATE.set_bus_soloed(&bus_id, soloed)
+}
+
+#[tauri::command]
+pub fn mixer_set_bus_solo_safe(bus_id: Id, solo_safe: bool) -> Option<BusTrack> {
+    PROJECT_STATE.set_bus_solo_safe(&bus_id, solo_safe)
+}
+
+#[tauri::command]
+pub fn mixer_add_bus() -> BusTrack {
+    PROJECT_STATE.add_bus()
+}
+
+#[tauri::command]
+pub fn mixer_delete_bus(bus_id: Id) {
+    PROJECT_STATE.delete_bus(&bus_id)
+}
+
+#[tauri::command]
+pub fn mixer_rename_bus(bus_id: Id, name: String) -> Option<BusTrack> {
+    PROJECT_STATE.rename_bus(&bus_id, name)
+}
+
+/// Routes a track into a bus, or the master when `output_id` is null.
+#[tauri::command]
+pub fn mixer_set_track_output(track_id: Id, output_id: Option<Id>) -> Result<(), String> {
+    PROJECT_STATE
+        .set_track_output(&track_id, output_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Routes a bus into another bus, or the master when `output_id` is null.
+#[tauri::command]
+pub fn mixer_set_bus_output(bus_id: Id, output_id: Option<Id>) -> Result<BusTrack, String> {
+    PROJECT_STATE
+        .set_bus_output(&bus_id, output_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Adds or updates the send from a track to a bus, returns the track's sends.
+#[tauri::command]
+pub fn mixer_set_track_send(
+    track_id: Id,
+    bus_id: Id,
+    amount: f32,
+    tap: SendTap,
+) -> Result<Vec<SendAmount>, String> {
+    PROJECT_STATE
+        .set_track_send(&track_id, &bus_id, amount, tap)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn mixer_remove_track_send(track_id: Id, bus_id: Id) -> Result<Vec<SendAmount>, String> {
+    PROJECT_STATE
+        .remove_track_send(&track_id, &bus_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn mixer_get_exclusive_solo() -> bool {
+    PROJECT_STATE.exclusive_solo()
+}
+
+#[tauri::command]
+pub fn mixer_set_exclusive_solo(exclusive_solo: bool) {
+    PROJECT_STATE.set_exclusive_solo(exclusive_solo)
+}
+
+#[tauri::command]
+pub fn mixer_set_master_volume(volume: f32) -> MasterTrack {
+    PROJECT_STATE.set_master_volume(volume)
+}
+
+#[tauri::command]
+pub fn mixer_set_master_pan(pan: f32) -> MasterTrack {
+    PROJECT_STATE.set_master_pan(pan)
+}
+
+#[tauri::command]
+pub fn mixer_set_master_muted(muted: bool) -> MasterTrack {
+    PROJECT_STATE.set_master_muted(muted)
+}
+
+#[tauri::command]
+pub fn mixer_get_pan_law() -> PanLaw {
+    PROJECT_STATE.pan_law()
+}
+
+#[tauri::command]
+pub fn mixer_set_pan_law(pan_law: PanLaw) {
+    PROJECT_STATE.set_pan_law(pan_law)
+}
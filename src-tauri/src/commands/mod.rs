@@ -1,4 +1,11 @@
+pub mod effects;
+pub mod engine;
+pub mod export;
 pub mod fs;
 pub mod preview;
+pub mod metronome;
 pub mod mixer;
+pub mod project;
+pub mod tempo;
+pub mod time_signature;
 pub mod transport;
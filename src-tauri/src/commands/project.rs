@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+
+use crate::audio::project_state::PROJECT_STATE;
+
+#[tauri::command]
+pub fn project_get_directory() -> Option<PathBuf> {
+    PROJECT_STATE.directory()
+}
+
+#[tauri::command]
+pub fn project_set_directory(directory: Option<PathBuf>) {
+    PROJECT_STATE.set_directory(directory);
+}
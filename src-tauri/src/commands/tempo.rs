@@ -0,0 +1,42 @@
+use crate::{
+    audio::{
+        project_state::PROJECT_STATE,
+        tempo_map::{TempoCurve, TempoEvent},
+    },
+    core::types::Id,
+};
+
+#[tauri::command]
+pub fn tempo_get_events() -> Vec<TempoEvent> {
+    PROJECT_STATE.tempo_events()
+}
+
+#[tauri::command]
+pub fn tempo_add_event(
+    position_ppq: usize,
+    bpm: f32,
+    curve: TempoCurve,
+) -> Result<TempoEvent, String> {
+    PROJECT_STATE
+        .add_tempo_event(position_ppq, bpm, curve)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn tempo_update_event(
+    event_id: Id,
+    position_ppq: usize,
+    bpm: f32,
+    curve: TempoCurve,
+) -> Result<TempoEvent, String> {
+    PROJECT_STATE
+        .update_tempo_event(&event_id, position_ppq, bpm, curve)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn tempo_remove_event(event_id: Id) -> Result<(), String> {
+    PROJECT_STATE
+        .remove_tempo_event(&event_id)
+        .map_err(|e| e.to_string())
+}
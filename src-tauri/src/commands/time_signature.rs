@@ -0,0 +1,52 @@
+use crate::{
+    audio::{
+        meter_map::{BarBeatTick, MeterEvent},
+        project_state::PROJECT_STATE,
+    },
+    core::types::Id,
+};
+
+#[tauri::command]
+pub fn time_signature_get_events() -> Vec<MeterEvent> {
+    PROJECT_STATE.meter_events()
+}
+
+#[tauri::command]
+pub fn time_signature_add_event(
+    bar: usize,
+    numerator: u8,
+    denominator: u8,
+) -> Result<MeterEvent, String> {
+    PROJECT_STATE
+        .add_meter_event(bar, numerator, denominator)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn time_signature_update_event(
+    event_id: Id,
+    bar: usize,
+    numerator: u8,
+    denominator: u8,
+) -> Result<MeterEvent, String> {
+    PROJECT_STATE
+        .update_meter_event(&event_id, bar, numerator, denominator)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn time_signature_remove_event(event_id: Id) -> Result<(), String> {
+    PROJECT_STATE
+        .remove_meter_event(&event_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn time_signature_ppq_to_bar_beat_tick(ppq: usize) -> BarBeatTick {
+    PROJECT_STATE.ppq_to_bar_beat_tick(ppq)
+}
+
+#[tauri::command]
+pub fn time_signature_bar_beat_tick_to_ppq(position: BarBeatTick) -> usize {
+    PROJECT_STATE.bar_beat_tick_to_ppq(position)
+}
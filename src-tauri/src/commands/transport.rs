@@ -1,4 +1,4 @@
-use crate::audio::transport::TRANSPORT;
+use crate::audio::transport::{PlayheadPosition, TRANSPORT};
 
 #[tauri::command]
 pub fn transport_stop() {
@@ -7,6 +7,56 @@ pub fn transport_stop() {
 
 #[tauri::command]
 pub fn transport_play() {
-    // TODO eventually create a dedicated thread for transport
-    tauri::async_runtime::spawn_blocking(|| TRANSPORT.play());
+    TRANSPORT.play();
+}
+
+#[tauri::command]
+pub fn transport_pause() {
+    TRANSPORT.pause();
+}
+
+#[tauri::command]
+pub fn transport_resume() {
+    TRANSPORT.resume();
+}
+
+#[tauri::command]
+pub fn transport_get_return_to_start_on_stop() -> bool {
+    TRANSPORT.return_to_start_on_stop()
+}
+
+#[tauri::command]
+pub fn transport_set_return_to_start_on_stop(return_to_start_on_stop: bool) -> Result<(), String> {
+    TRANSPORT
+        .set_return_to_start_on_stop(return_to_start_on_stop)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn transport_record() -> Result<(), String> {
+    TRANSPORT.record().map_err(|e| e.to_string())?;
+    TRANSPORT.play();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn transport_set_loop_range(start_ppq: usize, end_ppq: usize) -> Result<(), String> {
+    TRANSPORT
+        .set_loop_range(start_ppq, end_ppq)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn transport_set_looping(is_looping: bool) {
+    TRANSPORT.set_looping(is_looping);
+}
+
+#[tauri::command]
+pub fn transport_seek(ppq: usize) {
+    TRANSPORT.seek(ppq);
+}
+
+#[tauri::command]
+pub fn transport_get_playhead() -> PlayheadPosition {
+    TRANSPORT.playhead_position()
 }
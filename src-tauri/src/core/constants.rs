@@ -2,8 +2,35 @@ pub const ENGINE_NUM_CHANNELS: u8 = 2;
 pub const BUFFER_SIZE_DEFAULT: u16 = 512;
 pub const PPQ_DEFAULT: u16 = 960;
 pub const TEMPO_BPM_DEFAULT: f32 = 128.0;
+pub const TEMPO_BPM_MIN: f32 = 20.0;
+pub const TEMPO_BPM_MAX: f32 = 999.0;
 pub const TIME_SIGNATURE_NUMERATOR_DEFAULT: u8 = 4;
 pub const TIME_SIGNATURE_DENOMINATOR_DEFAULT: u8 = 4;
+pub const TIME_SIGNATURE_NUMERATOR_MAX: u8 = 32;
+pub const METRONOME_VOLUME_DEFAULT: f32 = 0.7;
+pub const METRONOME_ACCENT_FREQUENCY: f32 = 1500.0;
+pub const METRONOME_BEAT_FREQUENCY: f32 = 1000.0;
+/// Length of the built-in click in seconds.
+pub const METRONOME_CLICK_DURATION: f32 = 0.05;
+pub const METRONOME_COUNT_IN_BARS_MAX: usize = 4;
+/// Time in seconds effect parameters take to glide to a new value.
+pub const EFFECT_PARAMETER_SMOOTHING_TIME: f64 = 0.02;
+/// Highest sample rate effects size their delay lines for, so a rate change never allocates.
+pub const EFFECT_SAMPLE_RATE_MAX: usize = 192000;
 pub const MASTER_TRACK_DEFAULT_NAME: &str = "Master";
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+pub const NULL_HOST_ID: &str = "null";
+pub const NULL_OUTPUT_SAMPLE_RATE_DEFAULT: usize = 48000;
+/// Buffer sizes in frames the null output advertises and accepts.
+pub const NULL_OUTPUT_BUFFER_SIZE_MIN: u32 = 16;
+pub const NULL_OUTPUT_BUFFER_SIZE_MAX: u32 = 8192;
+pub const RECORDINGS_DIR_NAME: &str = "Recordings";
 
 pub const NOTIFICATION_ERROR_EVENT: &str = "notification-error";
+pub const XRUN_EVENT: &str = "xrun";
+pub const METERS_EVENT: &str = "meters";
+pub const PLAYHEAD_EVENT: &str = "playhead";
+pub const AUDIO_DEVICE_LOST_EVENT: &str = "audio-device-lost";
+pub const AUDIO_DEVICE_RESTORED_EVENT: &str = "audio-device-restored";
+pub const BOUNCE_PROGRESS_EVENT: &str = "bounce-progress";
+pub const RECORDING_FINISHED_EVENT: &str = "recording-finished";
@@ -1,7 +1,25 @@
-use crate::audio::engine::AUDIO_ENGINE;
+use crate::{
+    audio::{
+        device_watcher::DEVICE_WATCHER, engine::AUDIO_ENGINE, engine_stats::ENGINE_STATS,
+        metering::METERING, metronome::METRONOME, transport::TRANSPORT,
+    },
+    core::{notify::log_and_notify_error, settings::SETTINGS},
+};
 
 pub fn initialize_project(loaded_file_path: Option<&str>) {
-    // load settings
+    if let Err(e) = SETTINGS.load() {
+        log_and_notify_error(format!("Error trying to load settings: {e}"));
+    }
+    TRANSPORT.apply_settings(&SETTINGS.get().transport);
     // load project
-    AUDIO_ENGINE.start();
+    if let Err(e) = AUDIO_ENGINE.start_with_settings(&SETTINGS.get().audio) {
+        log_and_notify_error(format!("Error trying to open saved output device: {e}"));
+        // Lets the watcher pick a fallback and switch over once the device is plugged in
+        DEVICE_WATCHER.report_device_lost();
+    }
+    METRONOME.rebuild_sounds();
+    ENGINE_STATS.start_reporter();
+    METERING.start_reporter();
+    DEVICE_WATCHER.start();
+    TRANSPORT.start_playhead_reporter();
 }
@@ -4,4 +4,5 @@ pub mod initializator;
 pub mod logger;
 pub mod notify;
 pub mod project;
+pub mod settings;
 pub mod types;
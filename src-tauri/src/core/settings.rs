@@ -0,0 +1,96 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::{app_handle, core::constants::SETTINGS_FILE_NAME};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AudioSettings {
+    pub host_id: Option<String>,
+    pub output_device_id: Option<String>,
+    pub sample_rate: Option<usize>,
+    pub buffer_size: Option<usize>,
+    pub input_device_id: Option<String>,
+    /// Added to the measured latency when placing recorded takes, can be negative.
+    pub recording_offset_frames: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TransportSettings {
+    /// Stop moves the playhead back to where playback started instead of the project start.
+    pub return_to_start_on_stop: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub transport: TransportSettings,
+}
+
+/// User preferences persisted as JSON in the app config directory.
+pub struct SettingsStore {
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    pub fn new() -> Self {
+        Self {
+            settings: Mutex::new(Settings::default()),
+        }
+    }
+
+    fn file_path() -> Result<PathBuf> {
+        let config_dir = app_handle()
+            .path()
+            .app_config_dir()
+            .context("Couldn't resolve app config directory")?;
+        Ok(config_dir.join(SETTINGS_FILE_NAME))
+    }
+
+    pub fn load(&self) -> Result<()> {
+        let file_path = Self::file_path()?;
+        if !file_path.exists() {
+            info!("No settings file found, using defaults");
+            return Ok(());
+        }
+
+        let contents = fs::read_to_string(&file_path).context("Couldn't read settings file")?;
+        let settings: Settings =
+            serde_json::from_str(&contents).context("Couldn't parse settings file")?;
+        *self.settings.lock().unwrap() = settings;
+        info!("Settings loaded from {}", file_path.display());
+        Ok(())
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut Settings)) -> Result<()> {
+        let mut settings = self.settings.lock().unwrap();
+        f(&mut settings);
+        Self::save(&settings)
+    }
+
+    fn save(settings: &Settings) -> Result<()> {
+        let file_path = Self::file_path()?;
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).context("Couldn't create app config directory")?;
+        }
+        let contents = serde_json::to_string_pretty(settings)?;
+        fs::write(&file_path, contents).context("Couldn't write settings file")?;
+        Ok(())
+    }
+}
+
+pub static SETTINGS: LazyLock<SettingsStore> = LazyLock::new(|| SettingsStore::new());
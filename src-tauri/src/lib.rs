@@ -29,13 +29,85 @@ pub fn run() {
             commands::preview::preview_play,
             commands::transport::transport_stop,
             commands::transport::transport_play,
+            commands::transport::transport_pause,
+            commands::transport::transport_resume,
+            commands::transport::transport_get_return_to_start_on_stop,
+            commands::transport::transport_set_return_to_start_on_stop,
+            commands::transport::transport_record,
+            commands::transport::transport_set_loop_range,
+            commands::transport::transport_set_looping,
+            commands::transport::transport_seek,
+            commands::transport::transport_get_playhead,
+            commands::metronome::metronome_get_info,
+            commands::metronome::metronome_set_enabled,
+            commands::metronome::metronome_set_volume,
+            commands::metronome::metronome_set_count_in_bars,
+            commands::metronome::metronome_set_sound,
+            commands::tempo::tempo_get_events,
+            commands::tempo::tempo_add_event,
+            commands::tempo::tempo_update_event,
+            commands::tempo::tempo_remove_event,
+            commands::time_signature::time_signature_get_events,
+            commands::time_signature::time_signature_add_event,
+            commands::time_signature::time_signature_update_event,
+            commands::time_signature::time_signature_remove_event,
+            commands::time_signature::time_signature_ppq_to_bar_beat_tick,
+            commands::time_signature::time_signature_bar_beat_tick_to_ppq,
+            commands::effects::effects_get_kinds,
+            commands::effects::effects_get_inserts,
+            commands::effects::effects_add_insert,
+            commands::effects::effects_remove_insert,
+            commands::effects::effects_move_insert,
+            commands::effects::effects_set_parameter,
+            commands::effects::effects_set_bypassed,
+            commands::effects::effects_set_impulse_response,
+            commands::effects::effects_get_frequency_response,
+            commands::effects::effects_get_latency,
             commands::mixer::mixer_add_audio_track,
             commands::mixer::mixer_add_clip_to_audio_track,
             commands::mixer::mixer_add_audio_track_with_clip,
             commands::mixer::mixer_add_sampler_track,
             commands::mixer::mixer_assign_source_to_sampler_track,
             commands::mixer::mixer_move_clip_in_audio_track,
-            commands::mixer::mixer_delete_clip_from_audio_track
+            commands::mixer::mixer_delete_clip_from_audio_track,
+            commands::mixer::mixer_set_track_record_armed,
+            commands::mixer::mixer_set_track_monitoring,
+            commands::mixer::mixer_set_track_volume,
+            commands::mixer::mixer_set_track_pan,
+            commands::mixer::mixer_set_track_muted,
+            commands::mixer::mixer_set_track_soloed,
+            commands::mixer::mixer_set_bus_soloed,
+            commands::mixer::mixer_set_bus_solo_safe,
+            commands::mixer::mixer_add_bus,
+            commands::mixer::mixer_delete_bus,
+            commands::mixer::mixer_rename_bus,
+            commands::mixer::mixer_set_track_output,
+            commands::mixer::mixer_set_bus_output,
+            commands::mixer::mixer_set_track_send,
+            commands::mixer::mixer_remove_track_send,
+            commands::mixer::mixer_get_exclusive_solo,
+            commands::mixer::mixer_set_exclusive_solo,
+            commands::mixer::mixer_set_master_volume,
+            commands::mixer::mixer_set_master_pan,
+            commands::mixer::mixer_set_master_muted,
+            commands::mixer::mixer_get_pan_law,
+            commands::mixer::mixer_set_pan_law,
+            commands::project::project_get_directory,
+            commands::project::project_set_directory,
+            commands::export::export_bounce_to_wav,
+            commands::export::export_cancel_bounce,
+            commands::engine::engine_list_hosts,
+            commands::engine::engine_list_output_devices,
+            commands::engine::engine_get_output_device,
+            commands::engine::engine_list_input_devices,
+            commands::engine::engine_set_input_device,
+            commands::engine::engine_set_output_device,
+            commands::engine::engine_set_sample_rate,
+            commands::engine::engine_set_buffer_size,
+            commands::engine::engine_start_output_capture,
+            commands::engine::engine_stop_output_capture,
+            commands::engine::engine_get_stats,
+            commands::engine::engine_reset_stats
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
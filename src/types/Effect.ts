@@ -0,0 +1,46 @@
+import Id from './Id'
+
+export enum EffectKind {
+  Gain = 'gain',
+  ParametricEq = 'parametricEq',
+  Compressor = 'compressor',
+  Limiter = 'limiter',
+  Gate = 'gate',
+  Reverb = 'reverb',
+  Delay = 'delay',
+  ConvolutionReverb = 'convolutionReverb',
+}
+
+export interface EffectParameter {
+  id: string
+  name: string
+  min: number
+  max: number
+  default: number
+  unit: string
+  options: string[] // labels when the value is an option index, empty otherwise
+}
+
+export interface EffectInfo {
+  kind: EffectKind
+  name: string
+  parameters: EffectParameter[]
+}
+
+export interface InsertEffect {
+  id: Id
+  kind: EffectKind
+  bypassed: boolean
+  parameters: number[] // in the order of EffectInfo.parameters
+  impulseResponseId: Id | null // audio asset of a convolution reverb
+}
+
+export interface FrequencyResponse {
+  frequencies: number[] // Hz, log-spaced from 20 Hz to 20 kHz
+  gainsDb: number[]
+}
+
+export interface EffectGainReduction {
+  effectId: Id
+  gainReductionDb: number // highest since the previous meters frame, positive
+}
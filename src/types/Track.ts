@@ -1,4 +1,5 @@
 import { Clip } from './Clip'
+import { InsertEffect } from './Effect'
 import Id from './Id'
 
 export enum TrackKind {
@@ -15,20 +16,32 @@ export interface BaseTrack {
   pan: number // -1..1
   muted: boolean
   kind: TrackKind
+  inserts: InsertEffect[]
   clips: Record<Id, Clip>
 }
 
+export type SendTap = 'preFader' | 'postFader'
+
 export interface SendAmount {
   busId: string
   amount: number // 0..1
+  tap: SendTap
 }
 
 export interface AudioTrack extends BaseTrack {
   id: string
+  soloed: boolean
+  outputId: string | null // null for the master
+  sends: SendAmount[]
+  recordArmed: boolean
+  monitoring: boolean
 }
 
 export interface SamplerTrack extends BaseTrack {
   id: string
+  soloed: boolean
+  outputId: string | null // null for the master
+  sends: SendAmount[]
   sourceId: string
 }
 
@@ -36,8 +49,16 @@ export type GeneratorTrack = AudioTrack | SamplerTrack
 
 export interface BusTrack extends BaseTrack {
   id: string
+  soloed: boolean
+  soloSafe: boolean
+  outputId: string | null // null for the master
 }
 
 export interface MasterTrack extends BaseTrack {}
 
+export interface TrackSolo {
+  id: string
+  soloed: boolean
+}
+
 export type GeneratorOrBusTrack = GeneratorTrack | BusTrack